@@ -3,8 +3,10 @@ use std::fmt::Display;
 
 use clap::{Parser, Subcommand};
 
+use coffee_lib::build::BuildLimits;
 use coffee_lib::error;
 use coffee_lib::errors::CoffeeError;
+use coffee_lib::types::request::HistoryFilter;
 
 /// Coffee main command line definition for the command line tools.
 #[derive(Debug, Parser)]
@@ -21,6 +23,29 @@ pub struct CoffeeArgs {
     pub data_dir: Option<String>,
     #[clap(short, long, action = clap::ArgAction::SetTrue)]
     pub skip_verify: bool,
+    /// Kill the plugin build after the given amount of seconds
+    #[clap(long, value_parser, name = "build-timeout")]
+    pub build_timeout: Option<u64>,
+    /// Maximum memory (in MB) that a plugin build can use
+    #[clap(long, value_parser, name = "build-memory-limit")]
+    pub build_memory_limit: Option<u64>,
+    /// Maximum CPU time (in seconds) that a plugin build can use
+    #[clap(long, value_parser, name = "build-cpu-limit")]
+    pub build_cpu_limit: Option<u64>,
+    /// Number of plugins that can be built concurrently
+    #[clap(short, long, value_parser)]
+    pub jobs: Option<usize>,
+    /// Send the command to the running coffee httpd or plugin,
+    /// when they are using the data directory
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub forward: bool,
+    /// PEM file with the client certificate used to forward the
+    /// commands to a httpd that requires the client certificates
+    #[clap(long, value_parser, requires = "tls_client_key")]
+    pub tls_client_cert: Option<String>,
+    /// PEM file with the private key of the client certificate
+    #[clap(long, value_parser, requires = "tls_client_cert")]
+    pub tls_client_key: Option<String>,
 }
 
 /// Coffee subcommand of the command line daemon.
@@ -33,10 +58,11 @@ pub enum CoffeeCommand {
     /// Unlink coffee from the core lightning configuration
     #[clap(arg_required_else_help = true)]
     Unlink { cln_conf: String },
-    /// Install a single by name.
+    /// Install one or more plugins by name.
     #[clap(arg_required_else_help = true)]
     Install {
-        plugin: String,
+        #[arg(required = true)]
+        plugins: Vec<String>,
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         verbose: bool,
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
@@ -52,6 +78,18 @@ pub enum CoffeeCommand {
     /// Print the list of plugins installed in cln.
     #[clap(arg_required_else_help = false)]
     List {},
+    /// Print the state of the plugins inside cln.
+    #[clap(arg_required_else_help = false)]
+    Status {},
+    /// Start a plugin installed in cln.
+    #[clap(arg_required_else_help = true)]
+    Start { plugin: String },
+    /// Stop a plugin running in cln.
+    #[clap(arg_required_else_help = true)]
+    Stop { plugin: String },
+    /// Restart a plugin, e.g. to run the new build after an upgrade.
+    #[clap(arg_required_else_help = true)]
+    Restart { plugin: String },
     /// Remove a plugin installed in cln.
     #[clap(arg_required_else_help = true)]
     Remove { plugin: String },
@@ -75,6 +113,15 @@ pub enum CoffeeCommand {
         /// verify that coffee configuration is sane (without taking any action)
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         verify: bool,
+        /// show the repairs with their risk level (without taking any action)
+        #[arg(short, long, action = clap::ArgAction::SetTrue, conflicts_with = "verify")]
+        plan: bool,
+        /// print the plan as JSON
+        #[arg(long, action = clap::ArgAction::SetTrue, requires = "plan")]
+        json: bool,
+        /// apply only the repair with the given id (can be repeated)
+        #[arg(short, long, conflicts_with_all = ["verify", "plan"])]
+        apply: Vec<String>,
     },
     /// tipping a plugins developer.
     #[clap(arg_required_else_help = false)]
@@ -85,6 +132,48 @@ pub enum CoffeeCommand {
     /// Enable a plugin
     #[clap(arg_required_else_help = true)]
     Enable { plugin: String },
+    /// Manage the build cache
+    #[clap(arg_required_else_help = true)]
+    Cache {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+    /// Manage the options of a plugin
+    #[clap(arg_required_else_help = true)]
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+    /// Store the coffee state inside a backup archive
+    #[clap(arg_required_else_help = true)]
+    Backup {
+        file: String,
+        /// include the built plugins inside the archive
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        with_plugins: bool,
+    },
+    /// Restore the coffee state from a backup archive
+    #[clap(arg_required_else_help = true)]
+    Restore { file: String },
+    /// Show the operations that changed the coffee state
+    #[clap(arg_required_else_help = false)]
+    History {
+        /// show only the given operation (e.g. install)
+        #[arg(short, long)]
+        operation: Option<String>,
+        /// show only the operations on the given plugin or repository
+        #[arg(short, long)]
+        target: Option<String>,
+        /// show only the operations asked by the given caller
+        #[arg(short, long, value_parser = ["cli", "httpd", "plugin"])]
+        caller: Option<String>,
+        /// show only the failed operations
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        failed: bool,
+        /// maximum number of operations to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -99,18 +188,51 @@ pub enum RemoteAction {
     List {},
 }
 
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    /// Show the plugins builds inside the cache.
+    List {},
+    /// Remove the builds that are not used by any installed plugin.
+    Prune {
+        /// remove also the builds used by the installed plugins
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Show the options of a plugin with the current values.
+    Get {
+        plugin: String,
+        option: Option<String>,
+    },
+    /// Set the value of a plugin option.
+    Set {
+        plugin: String,
+        option: String,
+        value: String,
+    },
+    /// Remove the value of a plugin option.
+    Unset { plugin: String, option: String },
+}
+
 impl From<&CoffeeCommand> for coffee_core::CoffeeOperation {
     fn from(value: &CoffeeCommand) -> Self {
         match value {
             CoffeeCommand::Link { cln_conf } => Self::Link(cln_conf.to_owned()),
             CoffeeCommand::Unlink { cln_conf } => Self::Unlink(cln_conf.to_owned()),
             CoffeeCommand::Install {
-                plugin,
+                plugins,
                 verbose,
                 dynamic,
-            } => Self::Install(plugin.to_owned(), *verbose, *dynamic),
+            } => Self::Install(plugins.to_owned(), *verbose, *dynamic),
             CoffeeCommand::Upgrade { repo, verbose } => Self::Upgrade(repo.to_owned(), *verbose),
             CoffeeCommand::List {} => Self::List,
+            CoffeeCommand::Status {} => Self::Status,
+            CoffeeCommand::Start { plugin } => Self::Start(plugin.to_owned()),
+            CoffeeCommand::Stop { plugin } => Self::Stop(plugin.to_owned()),
+            CoffeeCommand::Restart { plugin } => Self::Restart(plugin.to_owned()),
             CoffeeCommand::Remote { action, name } => {
                 if let Some(action) = action {
                     return Self::Remote(Some(action.into()), name.clone());
@@ -120,17 +242,64 @@ impl From<&CoffeeCommand> for coffee_core::CoffeeOperation {
             CoffeeCommand::Remove { plugin } => Self::Remove(plugin.to_owned()),
             CoffeeCommand::Show { plugin } => Self::Show(plugin.to_owned()),
             CoffeeCommand::Search { plugin } => Self::Search(plugin.to_owned()),
-            CoffeeCommand::Nurse { verify } => Self::Nurse(*verify),
+            CoffeeCommand::Nurse {
+                verify,
+                plan,
+                apply,
+                ..
+            } => {
+                let action = if *verify {
+                    coffee_core::NurseAction::Verify
+                } else if *plan {
+                    coffee_core::NurseAction::Plan
+                } else {
+                    coffee_core::NurseAction::Apply(apply.to_owned())
+                };
+                Self::Nurse(action)
+            }
             CoffeeCommand::Tip {
                 plugin,
                 amount_msat,
             } => Self::Tip(plugin.to_owned(), amount_msat.clone()),
             CoffeeCommand::Disable { plugin } => Self::Disable(plugin.to_owned()),
             CoffeeCommand::Enable { plugin } => Self::Enable(plugin.to_owned()),
+            CoffeeCommand::Cache { action } => Self::Cache(action.into()),
+            CoffeeCommand::Config { action } => Self::Config(action.into()),
+            CoffeeCommand::Backup { file, with_plugins } => {
+                Self::Backup(file.to_owned(), *with_plugins)
+            }
+            CoffeeCommand::Restore { file } => Self::Restore(file.to_owned()),
+            CoffeeCommand::History { .. } => {
+                Self::History(value.history_filter().unwrap_or_default())
+            }
         }
     }
 }
 
+impl CoffeeCommand {
+    /// Return the filter of the `history` command.
+    pub fn history_filter(&self) -> Option<HistoryFilter> {
+        let CoffeeCommand::History {
+            operation,
+            target,
+            caller,
+            failed,
+            limit,
+        } = self
+        else {
+            return None;
+        };
+        Some(HistoryFilter {
+            operation: operation.clone(),
+            target: target.clone(),
+            caller: caller.clone(),
+            failed: *failed,
+            offset: 0,
+            limit: Some(*limit),
+        })
+    }
+}
+
 impl From<&RemoteAction> for coffee_core::RemoteAction {
     fn from(value: &RemoteAction) -> Self {
         match value {
@@ -142,6 +311,31 @@ impl From<&RemoteAction> for coffee_core::RemoteAction {
     }
 }
 
+impl From<&CacheAction> for coffee_core::CacheAction {
+    fn from(value: &CacheAction) -> Self {
+        match value {
+            CacheAction::List {} => Self::List,
+            CacheAction::Prune { all } => Self::Prune(*all),
+        }
+    }
+}
+
+impl From<&ConfigAction> for coffee_core::ConfigAction {
+    fn from(value: &ConfigAction) -> Self {
+        match value {
+            ConfigAction::Get { plugin, option } => Self::Get(plugin.to_owned(), option.clone()),
+            ConfigAction::Set {
+                plugin,
+                option,
+                value,
+            } => Self::Set(plugin.to_owned(), option.to_owned(), value.to_owned()),
+            ConfigAction::Unset { plugin, option } => {
+                Self::Unset(plugin.to_owned(), option.to_owned())
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ClnNetwork {
     Mainnet,
@@ -209,4 +403,16 @@ impl coffee_core::CoffeeArgs for CoffeeArgs {
     fn skip_verify(&self) -> bool {
         self.skip_verify
     }
+
+    fn build_limits(&self) -> BuildLimits {
+        BuildLimits {
+            timeout: self.build_timeout,
+            memory_limit: self.build_memory_limit,
+            cpu_limit: self.build_cpu_limit,
+        }
+    }
+
+    fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
 }
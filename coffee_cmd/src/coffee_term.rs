@@ -1,3 +1,5 @@
 mod command_show;
+mod progress;
 
 pub use command_show::*;
+pub use progress::*;
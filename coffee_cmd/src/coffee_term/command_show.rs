@@ -7,7 +7,11 @@ use term::Element;
 
 use coffee_lib::error;
 use coffee_lib::errors::CoffeeError;
-use coffee_lib::types::response::{CoffeeList, CoffeeNurse, CoffeeRemote, CoffeeTip, NurseStatus};
+use coffee_lib::types::response::{
+    CoffeeCache, CoffeeConfig, CoffeeHistory, CoffeeList, CoffeeNurse, CoffeeNursePlan,
+    CoffeeRemote, CoffeeStatus, CoffeeTip, HistoryResult, NurseStatus, PluginOptionValue,
+    PluginState, RiskLevel,
+};
 
 pub fn show_list(coffee_list: Result<CoffeeList, CoffeeError>) -> Result<(), CoffeeError> {
     let remotes = coffee_list?;
@@ -46,6 +50,54 @@ pub fn show_list(coffee_list: Result<CoffeeList, CoffeeError>) -> Result<(), Cof
     Ok(())
 }
 
+pub fn show_status(coffee_status: Result<CoffeeStatus, CoffeeError>) -> Result<(), CoffeeError> {
+    let status = coffee_status?;
+
+    term::println(
+        term::format::bold("●"),
+        term::format::tertiary("Plugins status"),
+    );
+    let mut table = radicle_term::Table::new(TableOptions::bordered());
+    table.push([
+        term::format::dim(String::from("●")),
+        term::format::bold(String::from("Name")),
+        term::format::bold(String::from("State")),
+        term::format::bold(String::from("Dynamic")),
+        term::format::bold(String::from("PID")),
+        term::format::bold(String::from("Exec path")),
+    ]);
+    table.divider();
+
+    for plugin in &status.plugins {
+        let state = match plugin.state {
+            PluginState::Running => term::format::positive(plugin.state.to_string()),
+            PluginState::Crashed => term::format::negative(plugin.state.to_string()),
+            PluginState::Stopped | PluginState::Unmanaged => {
+                term::format::dim(plugin.state.to_string())
+            }
+        };
+        let dynamic = match plugin.dynamic {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        let pid = plugin
+            .pid
+            .map(|pid| pid.to_string())
+            .unwrap_or(String::from("-"));
+        table.push([
+            term::format::positive("●").into(),
+            term::format::bold(plugin.name.to_owned()),
+            state,
+            term::format::highlight(dynamic.to_owned()),
+            term::format::highlight(pid),
+            term::format::highlight(plugin.exec_path.to_owned()),
+        ])
+    }
+    table.print();
+    Ok(())
+}
+
 pub fn show_remote_list(remote_list: Result<CoffeeRemote, CoffeeError>) -> Result<(), CoffeeError> {
     let repositories = remote_list?.remotes;
 
@@ -101,7 +153,7 @@ pub fn show_nurse_result(
             table.push([
                 term::format::dim(String::from("●")),
                 term::format::bold(String::from("Actions Taken")),
-                term::format::bold(String::from("Affected repositories")),
+                term::format::bold(String::from("Affected")),
             ]);
             table.divider();
 
@@ -111,11 +163,30 @@ pub fn show_nurse_result(
                     NurseStatus::RepositoryLocallyRemoved(_) => {
                         "Removed from local storage".to_string()
                     }
+                    NurseStatus::PluginConfEntryRemoved(_) => {
+                        "Removed from the configuration".to_string()
+                    }
+                    NurseStatus::PluginConfEntryRestored(_) => {
+                        "Restored in the configuration".to_string()
+                    }
+                    NurseStatus::DisablePluginEntryFixed(_) => {
+                        "Disabled state fixed in the configuration".to_string()
+                    }
+                    NurseStatus::DuplicatedConfEntriesRemoved(_) => {
+                        "Duplicated entries removed".to_string()
+                    }
+                    NurseStatus::PluginRebuilt(_) => "Rebuilt from the repository".to_string(),
+                    NurseStatus::PluginRemoved(_) => "Removed, source not available".to_string(),
+                    NurseStatus::PluginRebuildFailed(_) => {
+                        "Rebuild failed, the plugin is kept".to_string()
+                    }
+                    NurseStatus::ClnConfigRelinked(_) => "Linked again".to_string(),
+                    NurseStatus::ClnLinkNotRepaired(_) => {
+                        "Not found, run `coffee link` to link again".to_string()
+                    }
+                    NurseStatus::External(action, _) => action.to_owned(),
                 };
-                let repos_str = match status {
-                    NurseStatus::RepositoryLocallyRestored(repos)
-                    | NurseStatus::RepositoryLocallyRemoved(repos) => repos.join(", "),
-                };
+                let repos_str = status.affected().join(", ");
 
                 table.push([
                     term::format::positive("●").into(),
@@ -131,6 +202,46 @@ pub fn show_nurse_result(
     Ok(())
 }
 
+pub fn show_nurse_plan(plan: &CoffeeNursePlan) -> Result<(), CoffeeError> {
+    if plan.is_sane() {
+        term::success!("Coffee configuration is not corrupt! No need to run coffee nurse");
+        return Ok(());
+    }
+    term::println(
+        term::format::bold("●"),
+        term::format::tertiary("Nurse repair plan"),
+    );
+    let mut table = radicle_term::Table::new(TableOptions::bordered());
+    table.push([
+        term::format::dim(String::from("●")),
+        term::format::bold(String::from("Id")),
+        term::format::bold(String::from("Defect")),
+        term::format::bold(String::from("Repair")),
+        term::format::bold(String::from("Risk")),
+        term::format::bold(String::from("Affected")),
+    ]);
+    table.divider();
+
+    for repair in &plan.repairs {
+        let risk = match repair.risk {
+            RiskLevel::Low => term::format::positive(repair.risk.to_string()),
+            RiskLevel::Medium => term::format::tertiary(repair.risk.to_string()),
+            RiskLevel::High => term::format::negative(repair.risk.to_string()),
+        };
+        table.push([
+            term::format::positive("●").into(),
+            term::format::primary(repair.id.to_owned()),
+            term::format::bold(repair.defect.to_owned()),
+            term::format::highlight(repair.repair.to_owned()),
+            risk,
+            term::format::highlight(repair.affected.join(", ")),
+        ])
+    }
+    table.print();
+    term::info!("Run `coffee nurse --apply <id>` to apply only some repairs");
+    Ok(())
+}
+
 pub fn show_tips(coffee_tip: &CoffeeTip) -> Result<(), CoffeeError> {
     term::println(term::format::bold("●"), term::format::tertiary("Plugin"));
     let mut table = radicle_term::Table::new(TableOptions::bordered());
@@ -155,3 +266,146 @@ pub fn show_tips(coffee_tip: &CoffeeTip) -> Result<(), CoffeeError> {
     table.print();
     Ok(())
 }
+
+pub fn show_cache(coffee_cache: Result<CoffeeCache, CoffeeError>) -> Result<(), CoffeeError> {
+    let cache = coffee_cache?;
+
+    term::println(
+        term::format::bold("●"),
+        term::format::tertiary("Build cache"),
+    );
+    let mut table = radicle_term::Table::new(TableOptions::bordered());
+    table.push([
+        term::format::dim(String::from("●")),
+        term::format::bold(String::from("Key")),
+        term::format::bold(String::from("Plugin")),
+        term::format::bold(String::from("Language")),
+        term::format::bold(String::from("Size (MB)")),
+        term::format::bold(String::from("Date")),
+    ]);
+    table.divider();
+
+    for entry in &cache.entries {
+        let key = entry.key.chars().take(7).collect::<String>();
+        table.push([
+            if entry.in_use {
+                term::format::positive("●").into()
+            } else {
+                term::format::dim("●").into()
+            },
+            term::format::primary(key),
+            term::format::bold(entry.plugin.to_owned()),
+            term::format::highlight(entry.lang.to_owned()),
+            term::format::highlight((entry.size / (1024 * 1024)).to_string()),
+            term::format::bold(entry.date.to_owned()),
+        ])
+    }
+    table.print();
+    Ok(())
+}
+
+pub fn show_history(history: Result<CoffeeHistory, CoffeeError>) -> Result<(), CoffeeError> {
+    let history = history?;
+
+    term::println(
+        term::format::bold("●"),
+        term::format::tertiary(format!(
+            "History ({} of {} operations)",
+            history.entries.len(),
+            history.total
+        )),
+    );
+    let mut table = radicle_term::Table::new(TableOptions::bordered());
+    table.push([
+        term::format::dim(String::from("●")),
+        term::format::bold(String::from("Date")),
+        term::format::bold(String::from("Operation")),
+        term::format::bold(String::from("Target")),
+        term::format::bold(String::from("Commit")),
+        term::format::bold(String::from("Caller")),
+        term::format::bold(String::from("Result")),
+    ]);
+    table.divider();
+
+    let short = |commit: &Option<String>| {
+        commit
+            .as_ref()
+            .map(|commit| commit.chars().take(7).collect::<String>())
+            .unwrap_or(String::from("-"))
+    };
+    for entry in &history.entries {
+        let commit = if entry.before == entry.after {
+            short(&entry.after)
+        } else {
+            format!("{} → {}", short(&entry.before), short(&entry.after))
+        };
+        let (dot, result) = match &entry.result {
+            HistoryResult::Success => (
+                term::format::positive("●"),
+                term::format::positive(String::from("ok")),
+            ),
+            HistoryResult::Failure(err) => (
+                term::format::negative("●"),
+                term::format::negative(err.to_owned()),
+            ),
+        };
+        table.push([
+            dot.into(),
+            term::format::bold(entry.date.to_owned()),
+            term::format::highlight(entry.operation.to_owned()),
+            term::format::bold(entry.target.clone().unwrap_or(String::from("-"))),
+            term::format::primary(commit),
+            term::format::dim(entry.caller.to_string()),
+            result,
+        ])
+    }
+    table.print();
+    Ok(())
+}
+
+pub fn show_options(plugin: &str, options: &[PluginOptionValue]) -> Result<(), CoffeeError> {
+    term::println(
+        term::format::bold("●"),
+        term::format::tertiary(format!("Options of plugin {plugin}")),
+    );
+    let mut table = radicle_term::Table::new(TableOptions::bordered());
+    table.push([
+        term::format::dim(String::from("●")),
+        term::format::bold(String::from("Name")),
+        term::format::bold(String::from("Type")),
+        term::format::bold(String::from("Value")),
+        term::format::bold(String::from("Default")),
+        term::format::bold(String::from("Description")),
+    ]);
+    table.divider();
+
+    for option in options {
+        let default = option
+            .default
+            .as_ref()
+            .map(|default| match default {
+                serde_json::Value::String(default) => default.to_owned(),
+                default => default.to_string(),
+            })
+            .unwrap_or(String::from("-"));
+        table.push([
+            if option.value.is_some() {
+                term::format::positive("●").into()
+            } else {
+                term::format::dim("●").into()
+            },
+            term::format::bold(option.name.to_owned()),
+            term::format::highlight(option.kind.to_owned()),
+            term::format::primary(option.value.clone().unwrap_or(String::from("-"))),
+            term::format::highlight(default),
+            term::format::dim(option.description.clone().unwrap_or_default()),
+        ])
+    }
+    table.print();
+    Ok(())
+}
+
+pub fn show_config(coffee_config: Result<CoffeeConfig, CoffeeError>) -> Result<(), CoffeeError> {
+    let config = coffee_config?;
+    show_options(&config.plugin, &config.options)
+}
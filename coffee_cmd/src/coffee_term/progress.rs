@@ -0,0 +1,89 @@
+//! Spinner that follows the progress events of coffee.
+use std::sync::{Arc, Mutex, PoisonError};
+
+use radicle_term as term;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+use coffee_core::progress::ProgressEvent;
+
+pub struct ProgressSpinner {
+    message: String,
+    spinner: Arc<Mutex<Option<term::Spinner>>>,
+    task: JoinHandle<()>,
+}
+
+/// Show a spinner with the `message`, followed by the last event received.
+pub fn progress_spinner(
+    message: &str,
+    mut events: broadcast::Receiver<ProgressEvent>,
+) -> ProgressSpinner {
+    let spinner = Arc::new(Mutex::new(Some(term::spinner(message))));
+    let task = {
+        let spinner = spinner.clone();
+        let message = message.to_owned();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let mut spinner = spinner.lock().unwrap_or_else(PoisonError::into_inner);
+                        if let Some(spinner) = spinner.as_mut() {
+                            spinner.message(format!("{message}: {event}"));
+                        }
+                    }
+                    // the spinner shows only the last event, so
+                    // the lost ones do not matter.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    };
+    ProgressSpinner {
+        message: message.to_owned(),
+        spinner,
+        task,
+    }
+}
+
+impl ProgressSpinner {
+    /// Stop following the events, and return the spinner
+    /// with the final `message`.
+    fn stop(self, message: &str) -> Option<term::Spinner> {
+        self.task.abort();
+        let mut spinner = self
+            .spinner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()?;
+        spinner.message(message);
+        Some(spinner)
+    }
+
+    pub fn finish(self) {
+        let message = self.message.clone();
+        if let Some(spinner) = self.stop(&message) {
+            spinner.finish();
+        }
+    }
+
+    pub fn finish_with(self, message: &str) {
+        if let Some(spinner) = self.stop(message) {
+            spinner.finish();
+        }
+    }
+
+    pub fn failed(self) {
+        let message = self.message.clone();
+        if let Some(spinner) = self.stop(&message) {
+            spinner.failed();
+        }
+    }
+
+    pub fn error(self, err: String) {
+        let message = self.message.clone();
+        if let Some(spinner) = self.stop(&message) {
+            spinner.error(err);
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! Forward the command to the coffee process that holds
+//! the lock of the data directory (the httpd daemon or
+//! the core lightning plugin), instead of opening the
+//! storage.
+use std::time::Duration;
+
+use clightningrpc_common::client::Client;
+use reqwest::{Certificate, Identity};
+use serde_json::{json, Value};
+
+use coffee_core::lock::{LockAccess, LockEndpoint};
+use coffee_core::token::TokenStore;
+use coffee_lib::error;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::types::response::{CoffeeJob, ErrorResponse, JobState};
+
+use crate::cmd::{CacheAction, CoffeeArgs, CoffeeCommand, ConfigAction, RemoteAction};
+
+/// Time to wait between two requests of the state of a job.
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Request to the httpd daemon.
+enum HttpdRequest<'a> {
+    Get(&'a str, Option<Value>),
+    /// Get request with the fields of the object as query parameters.
+    Query(&'a str, Value),
+    Post(&'a str, Value),
+}
+
+fn httpd_requests(
+    command: &CoffeeCommand,
+) -> Result<Option<Vec<HttpdRequest<'static>>>, CoffeeError> {
+    let requests = match command {
+        CoffeeCommand::Install {
+            plugins, dynamic, ..
+        } => vec![HttpdRequest::Post(
+            "/install/many",
+            json!({ "plugins": plugins, "try_dynamic": dynamic }),
+        )],
+        CoffeeCommand::Remove { plugin } => {
+            vec![HttpdRequest::Post("/remove", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::List {} => vec![HttpdRequest::Get("/list", None)],
+        CoffeeCommand::Remote {
+            action: Some(action),
+            ..
+        } => match action {
+            RemoteAction::Add { name, url } => vec![HttpdRequest::Post(
+                "/remote/add",
+                json!({ "repository_name": name, "repository_url": url }),
+            )],
+            RemoteAction::Rm { name } => vec![HttpdRequest::Post(
+                "/remote/rm",
+                json!({ "repository_name": name }),
+            )],
+            RemoteAction::Inspect { name } => vec![HttpdRequest::Get(
+                "/remote/list_plugins",
+                Some(json!({ "repository_name": name })),
+            )],
+            RemoteAction::List {} => vec![HttpdRequest::Get("/remote/list", None)],
+        },
+        CoffeeCommand::Show { plugin } => {
+            vec![HttpdRequest::Get(
+                "/show",
+                Some(json!({ "plugin": plugin })),
+            )]
+        }
+        CoffeeCommand::Search { plugin } => {
+            vec![HttpdRequest::Get(
+                "/search",
+                Some(json!({ "plugin": plugin })),
+            )]
+        }
+        CoffeeCommand::Disable { plugin } => {
+            vec![HttpdRequest::Post("/disable", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::Enable { plugin } => {
+            vec![HttpdRequest::Post("/enable", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::Start { plugin } => {
+            vec![HttpdRequest::Post("/start", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::Stop { plugin } => {
+            vec![HttpdRequest::Post("/stop", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::Restart { plugin } => {
+            vec![HttpdRequest::Post("/restart", json!({ "plugin": plugin }))]
+        }
+        CoffeeCommand::Status {} => vec![HttpdRequest::Get("/status", None)],
+        CoffeeCommand::Upgrade { repo, .. } => vec![HttpdRequest::Post(
+            "/upgrade",
+            json!({ "repository_name": repo }),
+        )],
+        CoffeeCommand::Link { cln_conf } => {
+            vec![HttpdRequest::Post("/link", json!({ "cln_path": cln_conf }))]
+        }
+        CoffeeCommand::Unlink { cln_conf } => {
+            vec![HttpdRequest::Post(
+                "/unlink",
+                json!({ "cln_path": cln_conf }),
+            )]
+        }
+        CoffeeCommand::Nurse {
+            verify,
+            plan,
+            apply,
+            ..
+        } => {
+            if *verify {
+                vec![HttpdRequest::Get("/nurse/verify", None)]
+            } else if *plan {
+                vec![HttpdRequest::Get("/nurse/plan", None)]
+            } else {
+                vec![HttpdRequest::Post("/nurse", json!({ "ids": apply }))]
+            }
+        }
+        CoffeeCommand::Tip {
+            plugin,
+            amount_msat,
+        } => vec![HttpdRequest::Post(
+            "/tip",
+            json!({ "plugin": plugin, "amount_msat": amount_msat }),
+        )],
+        CoffeeCommand::Config { action } => match action {
+            ConfigAction::Get { plugin, option } => vec![HttpdRequest::Query(
+                "/config",
+                json!({ "plugin": plugin, "option": option }),
+            )],
+            ConfigAction::Set {
+                plugin,
+                option,
+                value,
+            } => vec![HttpdRequest::Post(
+                "/config/set",
+                json!({ "plugin": plugin, "option": option, "value": value }),
+            )],
+            ConfigAction::Unset { plugin, option } => vec![HttpdRequest::Post(
+                "/config/unset",
+                json!({ "plugin": plugin, "option": option }),
+            )],
+        },
+        CoffeeCommand::Cache { action } => match action {
+            CacheAction::List {} => vec![HttpdRequest::Get("/cache/list", None)],
+            CacheAction::Prune { all } => {
+                vec![HttpdRequest::Post("/cache/prune", json!({ "all": all }))]
+            }
+        },
+        CoffeeCommand::Backup { file, with_plugins } => vec![HttpdRequest::Post(
+            "/backup",
+            json!({ "name": archive_name(file)?, "with_plugins": with_plugins }),
+        )],
+        CoffeeCommand::Restore { file } => vec![HttpdRequest::Post(
+            "/restore",
+            json!({ "name": archive_name(file)? }),
+        )],
+        _ => return Ok(None),
+    };
+    Ok(Some(requests))
+}
+
+/// The daemon keeps the archives inside its own coffee home,
+/// so only the name of the archive can be forwarded.
+fn archive_name(file: &str) -> Result<&str, CoffeeError> {
+    match std::path::Path::new(file).file_name() {
+        Some(name) if name == file => Ok(file),
+        _ => Err(error!(
+            "coffee httpd stores the backups inside its `backups` directory, use only the file name"
+        )),
+    }
+}
+
+fn plugin_requests(command: &CoffeeCommand) -> Option<Vec<(&'static str, Value)>> {
+    let requests = match command {
+        CoffeeCommand::Install { plugins, .. } => plugins
+            .iter()
+            .map(|plugin| ("coffee_install", json!({ "name": plugin })))
+            .collect(),
+        CoffeeCommand::List {} => vec![("coffee_list", json!({}))],
+        CoffeeCommand::Remote {
+            action: Some(RemoteAction::Add { name, url }),
+            ..
+        } => vec![(
+            "coffee_remote",
+            json!({ "cmd": "add", "name": name, "url": url }),
+        )],
+        CoffeeCommand::Remote {
+            action: Some(RemoteAction::Rm { name }),
+            ..
+        } => vec![("coffee_remote", json!({ "cmd": "rm", "name": name }))],
+        CoffeeCommand::Start { plugin } => vec![("coffee_start", json!({ "name": plugin }))],
+        CoffeeCommand::Stop { plugin } => vec![("coffee_stop", json!({ "name": plugin }))],
+        CoffeeCommand::Restart { plugin } => {
+            vec![("coffee_restart", json!({ "name": plugin }))]
+        }
+        CoffeeCommand::Remote {
+            action: Some(RemoteAction::List {}),
+            ..
+        } => vec![("coffee_remote", json!({ "cmd": "list" }))],
+        CoffeeCommand::Remote {
+            action: Some(RemoteAction::Inspect { name }),
+            ..
+        } => vec![("coffee_remote", json!({ "cmd": "inspect", "name": name }))],
+        CoffeeCommand::Remove { plugin } => vec![("coffee_remove", json!({ "name": plugin }))],
+        CoffeeCommand::Upgrade { repo, .. } => {
+            vec![("coffee_upgrade", json!({ "repository": repo }))]
+        }
+        CoffeeCommand::Show { plugin } => vec![("coffee_show", json!({ "name": plugin }))],
+        CoffeeCommand::Search { plugin } => vec![("coffee_search", json!({ "name": plugin }))],
+        CoffeeCommand::Enable { plugin } => vec![("coffee_enable", json!({ "name": plugin }))],
+        CoffeeCommand::Disable { plugin } => {
+            vec![("coffee_disable", json!({ "name": plugin }))]
+        }
+        CoffeeCommand::Nurse {
+            verify,
+            plan,
+            apply,
+            ..
+        } => {
+            let action = if *verify {
+                "verify"
+            } else if *plan {
+                "plan"
+            } else {
+                "apply"
+            };
+            vec![("coffee_nurse", json!({ "action": action, "ids": apply }))]
+        }
+        CoffeeCommand::Tip {
+            plugin,
+            amount_msat,
+        } => vec![(
+            "coffee_tip",
+            json!({ "name": plugin, "amount_msat": amount_msat }),
+        )],
+        CoffeeCommand::Status {} => vec![("coffee_status", json!({}))],
+        _ => return None,
+    };
+    Some(requests)
+}
+
+/// Client of the httpd, that trusts the certificate published
+/// inside the lock and presents the client certificate if any.
+fn httpd_client(
+    access: &LockAccess,
+    identity: Option<(&str, &str)>,
+) -> Result<reqwest::Client, CoffeeError> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(path) = &access.tls_cert {
+        let pem = std::fs::read(path)?;
+        let cert = Certificate::from_pem(&pem)
+            .map_err(|err| error!("invalid TLS certificate `{path}`: {err}"))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    if let Some((cert, key)) = identity {
+        let identity = Identity::from_pkcs8_pem(&std::fs::read(cert)?, &std::fs::read(key)?)
+            .map_err(|err| error!("invalid TLS client certificate `{cert}`: {err}"))?;
+        builder = builder.identity(identity);
+    }
+    builder.build().map_err(|err| error!("{err}"))
+}
+
+async fn send_httpd(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    request: HttpdRequest<'_>,
+) -> Result<Value, CoffeeError> {
+    let request = match request {
+        HttpdRequest::Get(path, body) => {
+            let request = client.get(format!("{url}{path}"));
+            match body {
+                Some(body) => request.json(&body),
+                None => request,
+            }
+        }
+        HttpdRequest::Query(path, query) => client.get(format!("{url}{path}")).query(&query),
+        HttpdRequest::Post(path, body) => client.post(format!("{url}{path}")).json(&body),
+    };
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|err| error!("unable to reach coffee httpd at {url}: {err}"))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|err| error!("{err}"))?;
+    if !status.is_success() {
+        return Err(match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(err) => error!("{}", err.message).with_kind(error_kind(&err.code)),
+            Err(_) => error!("{body}"),
+        });
+    }
+    let answer = serde_json::from_str(&body).unwrap_or(Value::String(body));
+    // the operations without a response answer with a message.
+    match answer.get("message").and_then(Value::as_str) {
+        Some(message) if answer.as_object().is_some_and(|answer| answer.len() == 1) => {
+            Ok(Value::String(message.to_owned()))
+        }
+        _ => Ok(answer),
+    }
+}
+
+/// Category of the error from its httpd code.
+fn error_kind(code: &str) -> ErrorKind {
+    serde_json::from_value(Value::String(code.to_owned())).unwrap_or_default()
+}
+
+/// The long running operations are jobs inside the httpd, so we
+/// wait until the job is finished and return its response.
+async fn wait_job(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    job: CoffeeJob,
+) -> Result<Value, CoffeeError> {
+    let mut job = job;
+    while !job.state.is_finished() {
+        tokio::time::sleep(JOB_POLL_INTERVAL).await;
+        let path = format!("/jobs/{}", job.id);
+        let answer = send_httpd(client, url, token, HttpdRequest::Get(&path, None)).await?;
+        job = serde_json::from_value(answer).map_err(|err| error!("{err}"))?;
+    }
+    let kind = job
+        .error_code
+        .as_deref()
+        .map(error_kind)
+        .unwrap_or_default();
+    match (job.state, job.error) {
+        (JobState::Succeeded, _) => Ok(job.result.unwrap_or(Value::Null)),
+        (state, Some(err)) => Err(error!("job {} {state}: {err}", job.id).with_kind(kind)),
+        (state, None) => Err(error!("job {} {state}", job.id)),
+    }
+}
+
+fn send_plugin(socket: &str, method: &str, payload: Value) -> Result<Value, CoffeeError> {
+    let client = Client::new(socket.to_owned());
+    let response = client
+        .send_request(method, payload)
+        .map_err(|err| error!("{err}"))?;
+    if let Some(err) = response.error {
+        // the plugin reports the category of the error inside the data.
+        let kind = err
+            .data
+            .and_then(|data| serde_json::from_value::<ErrorResponse>(data).ok())
+            .map(|data| error_kind(&data.code))
+            .unwrap_or_default();
+        return Err(error!("{}", err.message).with_kind(kind));
+    }
+    Ok(response.result.unwrap_or(Value::Null))
+}
+
+/// Forward the command to the endpoint, and return the answers.
+///
+/// The httpd requests are authenticated with the admin token of
+/// the token file published inside the lock (by default the one
+/// stored inside the coffee home).
+pub async fn forward(
+    endpoint: &LockEndpoint,
+    access: &LockAccess,
+    args: &CoffeeArgs,
+    root_path: &str,
+) -> Result<Vec<Value>, CoffeeError> {
+    let command = &args.command;
+    let mut answers = vec![];
+    match endpoint {
+        LockEndpoint::Httpd(url) => {
+            let Some(requests) = httpd_requests(command)? else {
+                return Err(error!("the command can not be forwarded to coffee httpd"));
+            };
+            let token_file = access
+                .token_file
+                .clone()
+                .unwrap_or(TokenStore::path(root_path));
+            let tokens = TokenStore::load(&token_file).ok();
+            let token = tokens
+                .as_ref()
+                .and_then(|tokens| tokens.admin())
+                .map(|token| token.token.as_str());
+            let identity = args
+                .tls_client_cert
+                .as_deref()
+                .zip(args.tls_client_key.as_deref());
+            let client = httpd_client(access, identity)?;
+            for request in requests {
+                let answer = send_httpd(&client, url, token, request).await?;
+                let answer = match serde_json::from_value::<CoffeeJob>(answer.clone()) {
+                    Ok(job) => wait_job(&client, url, token, job).await?,
+                    Err(_) => answer,
+                };
+                answers.push(answer);
+            }
+        }
+        LockEndpoint::Plugin(socket) => {
+            let Some(requests) = plugin_requests(command) else {
+                return Err(error!(
+                    "the command can not be forwarded to the coffee plugin"
+                ));
+            };
+            for (method, payload) in requests {
+                answers.push(send_plugin(socket, method, payload)?);
+            }
+        }
+    }
+    Ok(answers)
+}
@@ -1,19 +1,34 @@
 mod cmd;
 mod coffee_term;
+mod forward;
 
 use clap::Parser;
 use radicle_term as term;
 
 use coffee_core::coffee::CoffeeManager;
+use coffee_core::config::CoffeeConf;
+use coffee_core::lock::{DataDirLock, LockOwner};
 use coffee_lib::error;
 use coffee_lib::errors::CoffeeError;
 use coffee_lib::plugin_manager::PluginManager;
-use coffee_lib::types::response::{CoffeeRemote, UpgradeStatus};
+use coffee_lib::types::response::{CoffeeRemote, ConfigApplied, UpgradeStatus};
 
+use crate::cmd::CacheAction;
 use crate::cmd::CoffeeArgs;
 use crate::cmd::CoffeeCommand;
+use crate::cmd::ConfigAction;
 use crate::cmd::RemoteAction;
 
+fn show_config_applied(applied: Option<ConfigApplied>) {
+    match applied {
+        Some(applied @ ConfigApplied::NextStart(_)) => {
+            term::warning(format!("The change {applied}"))
+        }
+        Some(applied) => term::info!("The change was {applied}"),
+        None => {}
+    }
+}
+
 async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeError> {
     match args.command {
         CoffeeCommand::Link { cln_conf } => {
@@ -25,19 +40,32 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
             coffee.unlink(&cln_conf).await?;
         }
         CoffeeCommand::Install {
-            plugin,
+            plugins,
             verbose,
             dynamic,
         } => {
             let spinner = if !verbose {
-                Some(term::spinner("Compiling and installing"))
+                Some(coffee_term::progress_spinner(
+                    "Compiling and installing",
+                    coffee.progress.subscribe(),
+                ))
             } else {
                 None
             };
-            match coffee.install(&plugin, verbose, dynamic).await {
-                Ok(_) => {
-                    spinner.and_then(|spinner| Some(spinner.finish()));
-                    term::success!("Plugin {plugin} Compiled and Installed")
+            match coffee.install_many(&plugins, verbose, dynamic).await {
+                Ok(result) => {
+                    if result.plugins.iter().all(|status| status.is_installed()) {
+                        spinner.and_then(|spinner| Some(spinner.finish()));
+                    } else {
+                        spinner.and_then(|spinner| Some(spinner.failed()));
+                    }
+                    for status in result.plugins {
+                        let plugin = status.plugin;
+                        match status.error {
+                            None => term::success!("Plugin {plugin} Compiled and Installed"),
+                            Some(err) => term::error(format!("Plugin {plugin}: {err}")),
+                        }
+                    }
                 }
                 Err(err) => {
                     spinner.and_then(|spinner| Some(spinner.failed()));
@@ -59,9 +87,46 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
             let remotes = coffee.list().await;
             coffee_term::show_list(remotes)?;
         }
+        CoffeeCommand::Status {} => {
+            let status = coffee.status().await;
+            coffee_term::show_status(status)?;
+        }
+        CoffeeCommand::Start { plugin } => {
+            let mut spinner = term::spinner(format!("Starting plugin {plugin}"));
+            match coffee.start(&plugin).await {
+                Ok(status) => {
+                    spinner.message(format!("Plugin {plugin} {}", status.state));
+                    spinner.finish();
+                }
+                Err(err) => spinner.error(format!("Error while starting the plugin: {err}")),
+            }
+        }
+        CoffeeCommand::Stop { plugin } => {
+            let mut spinner = term::spinner(format!("Stopping plugin {plugin}"));
+            match coffee.stop(&plugin).await {
+                Ok(status) => {
+                    spinner.message(format!("Plugin {plugin} {}", status.state));
+                    spinner.finish();
+                }
+                Err(err) => spinner.error(format!("Error while stopping the plugin: {err}")),
+            }
+        }
+        CoffeeCommand::Restart { plugin } => {
+            let mut spinner = term::spinner(format!("Restarting plugin {plugin}"));
+            match coffee.restart(&plugin).await {
+                Ok(status) => {
+                    spinner.message(format!("Plugin {plugin} {}", status.state));
+                    spinner.finish();
+                }
+                Err(err) => spinner.error(format!("Error while restarting the plugin: {err}")),
+            }
+        }
         CoffeeCommand::Upgrade { repo, verbose } => {
             let spinner = if !verbose {
-                Some(term::spinner("Upgrading"))
+                Some(coffee_term::progress_spinner(
+                    "Upgrading",
+                    coffee.progress.subscribe(),
+                ))
             } else {
                 None
             };
@@ -79,6 +144,13 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
                             )
                         }
                     }
+                    for status in res.plugins_status {
+                        let plugin = status.plugin;
+                        match status.error {
+                            None => term::success!("Plugin {plugin} upgraded"),
+                            Some(err) => term::error(format!("Plugin {plugin}: {err}")),
+                        }
+                    }
                 }
                 Err(err) => {
                     spinner.and_then(|spinner| Some(spinner.failed()));
@@ -89,14 +161,16 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
         CoffeeCommand::Remote { action, name } => {
             match action {
                 Some(RemoteAction::Add { name, url }) => {
-                    let mut spinner = term::spinner(format!("Fetch remote from {url}"));
+                    let spinner = coffee_term::progress_spinner(
+                        &format!("Fetch remote from {url}"),
+                        coffee.progress.subscribe(),
+                    );
                     let result = coffee.add_remote(&name, &url).await;
                     if let Err(err) = &result {
                         spinner.error(format!("Error while add remote: {err}"));
                         return result;
                     }
-                    spinner.message("Remote added!");
-                    spinner.finish();
+                    spinner.finish_with("Remote added!");
                 }
                 Some(RemoteAction::Rm { name }) => {
                     let mut spinner = term::spinner(format!("Removing remote {name}"));
@@ -146,23 +220,40 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
             let val = coffee.show(&plugin).await?;
 
             // FIXME: modify the radicle_term markdown
-            let val = val.readme.as_str();
-            term::markdown(val);
+            let readme = val.readme.as_str();
+            term::markdown(readme);
+            if !val.options.is_empty() {
+                coffee_term::show_options(&plugin, &val.options)?;
+            }
         }
         CoffeeCommand::Search { plugin } => {
             let val = coffee.search(&plugin).await?;
             let repository_url = val.repository_url.as_str();
             term::success!("found plugin {plugin} in remote repository {repository_url}");
         }
-        CoffeeCommand::Nurse { verify } => {
+        CoffeeCommand::Nurse {
+            verify,
+            plan,
+            json,
+            apply,
+        } => {
             if verify {
                 let result = coffee.nurse_verify().await?;
                 term::info!("{}", result);
                 if !result.is_sane() {
                     term::info!("Coffee local directory is damaged, please run `coffee nurse` to try to fix it");
                 }
+            } else if plan {
+                let plan = coffee.nurse_plan().await?;
+                if json {
+                    let plan =
+                        serde_json::to_string_pretty(&plan).map_err(|err| error!("{err}"))?;
+                    println!("{plan}");
+                } else {
+                    coffee_term::show_nurse_plan(&plan)?;
+                }
             } else {
-                let nurse_result = coffee.nurse().await;
+                let nurse_result = coffee.nurse_apply(&apply).await;
                 coffee_term::show_nurse_result(nurse_result)?;
             }
         }
@@ -181,6 +272,75 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
             coffee.enable(&plugin).await?;
             term::success!("Plugin {plugin} enabled");
         }
+        CoffeeCommand::Cache { action } => match action {
+            CacheAction::List {} => {
+                let cache = coffee.cache_list().await;
+                coffee_term::show_cache(cache)?;
+            }
+            CacheAction::Prune { all } => {
+                let pruned = coffee.cache_prune(all).await?;
+                let size: u64 = pruned.entries.iter().map(|entry| entry.size).sum();
+                term::success!(
+                    "Removed {} build(s) from the cache, {} MB freed",
+                    pruned.entries.len(),
+                    size / (1024 * 1024)
+                );
+            }
+        },
+        CoffeeCommand::Config { action } => match action {
+            ConfigAction::Get { plugin, option } => {
+                let config = coffee.config_get(&plugin, option.as_deref()).await;
+                coffee_term::show_config(config)?;
+            }
+            ConfigAction::Set {
+                plugin,
+                option,
+                value,
+            } => {
+                let config = coffee.config_set(&plugin, &option, &value).await?;
+                term::success!("Option {option} of plugin {plugin} set to {value}");
+                show_config_applied(config.applied);
+            }
+            ConfigAction::Unset { plugin, option } => {
+                let config = coffee.config_unset(&plugin, &option).await?;
+                term::success!("Option {option} of plugin {plugin} unset");
+                show_config_applied(config.applied);
+            }
+        },
+        CoffeeCommand::Backup { file, with_plugins } => {
+            let mut spinner = term::spinner("Creating the backup");
+            match coffee.backup(&file, with_plugins).await {
+                Ok(backup) => {
+                    spinner.message(format!(
+                        "Backup of {} plugins stored in {}",
+                        backup.plugins.len(),
+                        backup.path
+                    ));
+                    spinner.finish();
+                }
+                Err(err) => spinner.error(format!("Error while creating the backup: {err}")),
+            }
+        }
+        CoffeeCommand::History { .. } => {
+            let filter = args.command.history_filter().unwrap_or_default();
+            let history = coffee.history(&filter).await;
+            coffee_term::show_history(history)?;
+        }
+        CoffeeCommand::Restore { file } => {
+            let mut spinner = term::spinner("Restoring the backup");
+            match coffee.restore(&file).await {
+                Ok(restore) => {
+                    spinner.message(format!(
+                        "Restored {} plugins for the network {}",
+                        restore.plugins.len(),
+                        restore.network
+                    ));
+                    spinner.finish();
+                    coffee_term::show_nurse_result(Ok(restore.nurse))?;
+                }
+                Err(err) => spinner.error(format!("Error while restoring the backup: {err}")),
+            }
+        }
     };
     Ok(())
 }
@@ -189,7 +349,51 @@ async fn run(args: CoffeeArgs, mut coffee: CoffeeManager) -> Result<(), CoffeeEr
 async fn main() -> Result<(), CoffeeError> {
     env_logger::init();
     let args = CoffeeArgs::parse();
+    if args.forward {
+        let root_path = CoffeeConf::root_path(args.data_dir.clone());
+        match DataDirLock::owner(&root_path) {
+            Some(LockOwner {
+                endpoint: Some(endpoint),
+                access,
+                ..
+            }) => {
+                match forward::forward(&endpoint, &access, &args, &root_path).await {
+                    Ok(answers) => {
+                        for answer in answers {
+                            match answer {
+                                serde_json::Value::String(answer) => term::success!("{answer}"),
+                                answer => println!(
+                                    "{}",
+                                    serde_json::to_string_pretty(&answer)
+                                        .map_err(|err| error!("{err}"))?
+                                ),
+                            }
+                        }
+                    }
+                    Err(err) => term::error(format!("{err}")),
+                }
+                return Ok(());
+            }
+            // nobody is using the data directory, so we can
+            // run the command locally.
+            _ => log::info!("no running coffee to forward the command, running it locally"),
+        }
+    }
     let coffee = CoffeeManager::new(&args).await?;
+
+    // On Ctrl-C we cancel the running build, so coffee is able
+    // to clean up the partial install before exiting.
+    let canceller = coffee.canceller.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if !canceller.is_running() {
+                std::process::exit(130);
+            }
+            term::info!("Cancelling the running build ...");
+            canceller.cancel();
+        }
+    });
+
     if let Err(err) = run(args, coffee).await {
         term::error(format!("{err}"))
     }
@@ -0,0 +1,119 @@
+//! Backup archive of the coffee state.
+//!
+//! The archive is a tar.gz file that contains the content of
+//! the storage (as JSON), the coffee configuration of the network
+//! and optionally the built plugins. The repositories are not
+//! included, because they can be cloned again at the commit
+//! stored inside the storage.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use coffee_lib::build::run_command;
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+
+/// File inside the archive that contains the backup information.
+pub const BACKUP_MANIFEST: &str = "coffee-backup.json";
+
+/// Information stored inside the backup archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Schema version of the storage content.
+    pub schema_version: u32,
+    pub network: String,
+    /// Coffee home where the backup was made, used to
+    /// relocate the paths on restore.
+    pub root_path: String,
+    /// Unix timestamp of the backup.
+    pub created_at: u64,
+    pub with_plugins: bool,
+    /// Content of the storage, by key.
+    pub storage: BTreeMap<String, Value>,
+}
+
+/// Current unix timestamp, in seconds.
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Return a new directory used to build or extract an archive.
+pub async fn staging_dir(root_path: &str, kind: &str) -> Result<String, CoffeeError> {
+    let path = format!("{root_path}/tmp/{kind}-{}", timestamp());
+    fs::create_dir_all(&path).await?;
+    Ok(path)
+}
+
+/// Resolve the path given by the user, relative to the
+/// current directory.
+pub fn absolute_path(path: &str) -> Result<String, CoffeeError> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return Ok(path.to_string_lossy().to_string());
+    }
+    let path = std::env::current_dir()?.join(path);
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Directory of the archives made through the server.
+pub fn archive_dir(root_path: &str) -> String {
+    format!("{root_path}/backups")
+}
+
+/// Path of an archive made through the server.
+///
+/// The server accepts only a file name, and the archive is
+/// stored inside the coffee home, so a client can not read or
+/// write files outside of it.
+pub async fn archive_path(root_path: &str, name: &str) -> Result<String, CoffeeError> {
+    let is_file_name = Path::new(name)
+        .file_name()
+        .is_some_and(|file_name| file_name == name);
+    if !is_file_name || name.starts_with('.') {
+        return Err(error!(
+            kind = InvalidRequest,
+            "`{name}` is not a valid backup name, use a file name like `coffee.tar.gz`"
+        ));
+    }
+    let dir = archive_dir(root_path);
+    fs::create_dir_all(&dir).await?;
+    Ok(format!("{dir}/{name}"))
+}
+
+/// Compress the content of the `dir` inside the `archive`.
+pub async fn pack(dir: &str, archive: &str) -> Result<(), CoffeeError> {
+    run_command("tar", &["-czf", archive, "-C", dir, "."]).await
+}
+
+/// Extract the `archive` inside the `dir`.
+pub async fn unpack(archive: &str, dir: &str) -> Result<(), CoffeeError> {
+    if !Path::new(archive).exists() {
+        return Err(error!(kind = NotFound, "backup `{archive}` not found"));
+    }
+    run_command("tar", &["-xzf", archive, "-C", dir]).await
+}
+
+/// Copy a file or a directory, keeping the permissions.
+pub async fn copy(from: &str, to: &str) -> Result<(), CoffeeError> {
+    run_command("cp", &["-a", from, to]).await
+}
+
+/// Replace the coffee home of the backup with the current one.
+pub fn relocate(content: &str, from: &str, to: &str) -> String {
+    content
+        .replace(&format!("\"{from}\""), &format!("\"{to}\""))
+        .replace(&format!("{from}/"), &format!("{to}/"))
+}
+
+/// Replace the coffee home inside a stored value.
+pub fn relocate_value(value: &Value, from: &str, to: &str) -> Result<Value, CoffeeError> {
+    let content = serde_json::to_string(value).map_err(|err| error!("{err}"))?;
+    serde_json::from_str(&relocate(&content, from, to)).map_err(|err| error!("{err}"))
+}
@@ -0,0 +1,435 @@
+//! Build cache implementation.
+//!
+//! The cache is content addressed, the key of an entry is
+//! the hash of the plugin source tree, the install script and
+//! the version of the toolchain used to build the plugin.
+//!
+//! In this way the same plugin installed in different networks,
+//! or reinstalled without changes (e.g: during an upgrade),
+//! is built only once.
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::TimeZone;
+use tokio::fs;
+use tokio::process::Command;
+
+use coffee_lib::build::{run_command, BuildOptions};
+use coffee_lib::error;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::plugin::Plugin;
+use coffee_lib::types::response::CacheEntry;
+use coffee_lib::utils::check_dir_or_make_if_missing;
+
+use crate::progress::{Phase, Progress};
+
+/// Directory inside a cache entry that contains the built plugin.
+const ENTRY_PLUGIN_DIR: &str = "plugin";
+/// File inside a cache entry that contains the entry information.
+const ENTRY_META_FILE: &str = "entry.json";
+/// Directory inside the cache with the entries that are being written.
+const ENTRY_TMP_DIR: &str = ".tmp";
+
+/// Id of the next directory inside `ENTRY_TMP_DIR`.
+static NEXT_TMP_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug)]
+pub struct BuildCache {
+    /// root directory of the build cache
+    pub path: String,
+}
+
+impl BuildCache {
+    pub fn new(root_path: &str) -> Self {
+        BuildCache {
+            path: format!("{root_path}/cache"),
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> String {
+        format!("{}/{key}", self.path)
+    }
+
+    /// Return a new directory where the entry is written before
+    /// moving it in place, it is never listed as an entry.
+    fn tmp_path(&self, key: &str) -> String {
+        let id = NEXT_TMP_ID.fetch_add(1, Ordering::SeqCst);
+        format!(
+            "{}/{ENTRY_TMP_DIR}/{key}.{}.{id}",
+            self.path,
+            std::process::id()
+        )
+    }
+
+    /// Compute the content address of a plugin from its
+    /// source directory and the toolchain used to build it.
+    pub fn key(
+        &self,
+        plugin: &Plugin,
+        source: &str,
+        toolchain: &str,
+    ) -> Result<String, CoffeeError> {
+        let mut manifest = format!("name {}\nlang {}\n", plugin.name(), plugin.lang);
+        manifest += &format!("toolchain {toolchain}\n");
+        manifest += &format!("install {}\n", plugin.install_script().unwrap_or_default());
+        let source = Path::new(source);
+        hash_tree(source, source, &mut manifest)?;
+        let key = git2::Oid::hash_object(git2::ObjectType::Blob, manifest.as_bytes())
+            .map_err(|err| error!("{}", err.message()))?;
+        Ok(key.to_string())
+    }
+
+    /// Return the cache entry with the given key, if present.
+    pub async fn lookup(&self, key: &str) -> Option<CacheEntry> {
+        let entry_path = self.entry_path(key);
+        if !Path::new(&format!("{entry_path}/{ENTRY_PLUGIN_DIR}")).exists() {
+            return None;
+        }
+        let meta = fs::read_to_string(format!("{entry_path}/{ENTRY_META_FILE}"))
+            .await
+            .ok()?;
+        serde_json::from_str(&meta).ok()
+    }
+
+    /// Install the plugin from the source directory inside the
+    /// plugin root path, reusing the cached build when the sources,
+    /// the install script and the toolchain are the same.
+    ///
+    /// In case of success return the path of the executable.
+    pub async fn install(
+        &self,
+        plugin: &mut Plugin,
+        source: &str,
+        verbose: bool,
+        options: &BuildOptions,
+        progress: &Progress,
+    ) -> Result<String, CoffeeError> {
+        check_dir_or_make_if_missing(self.path.clone()).await?;
+        let root_path = plugin.root_path.clone();
+        let toolchain = toolchain_version(plugin).await;
+        // the source tree is read with the blocking api.
+        let key = {
+            let (cache, plugin, source, toolchain) = (
+                self.clone(),
+                plugin.clone(),
+                source.to_owned(),
+                toolchain.clone(),
+            );
+            tokio::task::spawn_blocking(move || cache.key(&plugin, &source, &toolchain))
+                .await
+                .map_err(|err| error!("{err}"))??
+        };
+        log::debug!("build cache key for plugin `{}`: {key}", plugin.name());
+        plugin.cache_key = Some(key.clone());
+
+        let result = if let Some(entry) = self.lookup(&key).await {
+            log::info!("reusing the cached build of plugin `{}`", plugin.name());
+            self.reuse(plugin, &entry, verbose, options, progress)
+                .await
+                .map(|_| format!("{root_path}/{}", entry.exec_path))
+        } else {
+            self.build(plugin, source, &toolchain, verbose, options, progress)
+                .await
+        };
+
+        match result {
+            Ok(path) => Ok(path),
+            Err(err) => {
+                // roll back the partial install, so the
+                // plugin can be installed again.
+                log::debug!("install failed, removing {root_path}");
+                let _ = fs::remove_dir_all(&root_path).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Install the plugin from the cache entry.
+    async fn reuse(
+        &self,
+        plugin: &mut Plugin,
+        entry: &CacheEntry,
+        verbose: bool,
+        options: &BuildOptions,
+        progress: &Progress,
+    ) -> Result<(), CoffeeError> {
+        let name = plugin.name();
+        progress
+            .phase(
+                &name,
+                Phase::Copying,
+                self.restore(entry, &plugin.root_path),
+            )
+            .await?;
+        if plugin.lang.has_external_dependencies() {
+            // the dependencies are not inside the cached directory,
+            // and the interpreter environment can be a new one, so
+            // only the requirements of the built tree are installed.
+            log::debug!("installing the dependencies of the cached plugin `{name}`");
+            let options = options.clone().with_output(progress.output(&name));
+            let install = plugin
+                .lang
+                .install_dependencies(&plugin.root_path, verbose, &options);
+            progress
+                .phase(&name, Phase::Building, install)
+                .await
+                .map_err(|err| err.with_kind(ErrorKind::BuildFailed))?;
+        }
+        Ok(())
+    }
+
+    async fn restore(&self, entry: &CacheEntry, root_path: &str) -> Result<(), CoffeeError> {
+        let from = format!("{}/{ENTRY_PLUGIN_DIR}", self.entry_path(&entry.key));
+        run_command("cp", &["-a", &from, root_path]).await
+    }
+
+    async fn build(
+        &self,
+        plugin: &mut Plugin,
+        source: &str,
+        toolchain: &str,
+        verbose: bool,
+        options: &BuildOptions,
+        progress: &Progress,
+    ) -> Result<String, CoffeeError> {
+        let name = plugin.name();
+        let root_path = plugin.root_path.clone();
+        let copy = async {
+            log::debug!("Start! copying directory from {source} inside the new one {root_path}");
+            run_command("cp", &["-r", source, &root_path]).await?;
+            log::debug!("Done! copying directory from {source} inside the new one {root_path}");
+            Ok(())
+        };
+        progress.phase(&name, Phase::Copying, copy).await?;
+
+        let options = options.clone().with_output(progress.output(&name));
+        let path = progress
+            .phase(&name, Phase::Building, plugin.configure(verbose, &options))
+            .await
+            .map_err(|err| err.with_kind(ErrorKind::BuildFailed))?;
+        if let Err(err) = self.store(plugin, &path, toolchain).await {
+            // the cache is an optimization, so we do not fail the install.
+            log::warn!(
+                "unable to store the plugin `{}` inside the build cache: {err}",
+                plugin.name()
+            );
+        }
+        Ok(path)
+    }
+
+    async fn store(
+        &self,
+        plugin: &Plugin,
+        exec_path: &str,
+        toolchain: &str,
+    ) -> Result<(), CoffeeError> {
+        let Some(key) = plugin.cache_key.clone() else {
+            return Err(error!("cache key not computed"));
+        };
+        let Some(relative_path) = exec_path.strip_prefix(&format!("{}/", plugin.root_path)) else {
+            return Err(error!(
+                "exec path `{exec_path}` is outside the plugin directory"
+            ));
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as i64)
+            .unwrap_or_default();
+        let date = chrono::Utc
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .map(|date| date.format("%d/%m/%Y").to_string())
+            .unwrap_or_default();
+        let entry = CacheEntry {
+            key: key.clone(),
+            plugin: plugin.name(),
+            lang: plugin.lang.to_string(),
+            exec_path: relative_path.to_owned(),
+            toolchain: toolchain.to_owned(),
+            date,
+            size: 0,
+            in_use: true,
+        };
+
+        // the entry is written inside a private directory and then
+        // moved in place, so two builds with the same key can not
+        // write inside the same entry.
+        let tmp_path = self.tmp_path(&key);
+        fs::create_dir_all(&tmp_path).await?;
+        let result = self.write_entry(&entry, &plugin.root_path, &tmp_path).await;
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&tmp_path).await;
+            return result;
+        }
+        let entry_path = self.entry_path(&key);
+        if self.lookup(&key).await.is_some() {
+            log::debug!("the build cache contains already the entry {key}, keeping it");
+            let _ = fs::remove_dir_all(&tmp_path).await;
+            return Ok(());
+        }
+        // an incomplete entry is never used, so it can be replaced.
+        if Path::new(&entry_path).exists() {
+            fs::remove_dir_all(&entry_path).await?;
+        }
+        if let Err(err) = fs::rename(&tmp_path, &entry_path).await {
+            let _ = fs::remove_dir_all(&tmp_path).await;
+            return Err(err.into());
+        }
+        log::debug!(
+            "plugin `{}` stored in the build cache ({key})",
+            plugin.name()
+        );
+        Ok(())
+    }
+
+    async fn write_entry(
+        &self,
+        entry: &CacheEntry,
+        root_path: &str,
+        entry_path: &str,
+    ) -> Result<(), CoffeeError> {
+        let to = format!("{entry_path}/{ENTRY_PLUGIN_DIR}");
+        run_command("cp", &["-a", root_path, &to]).await?;
+        // the entry information are written at the end, so
+        // an entry without them is never used.
+        let meta = serde_json::to_string(entry).map_err(|err| error!("{err}"))?;
+        fs::write(format!("{entry_path}/{ENTRY_META_FILE}"), meta).await?;
+        Ok(())
+    }
+
+    /// List all the entries inside the cache, marking the
+    /// ones that are used by the installed plugins.
+    pub async fn list(&self, in_use: &HashSet<String>) -> Result<Vec<CacheEntry>, CoffeeError> {
+        let mut entries = vec![];
+        if !Path::new(&self.path).exists() {
+            return Ok(entries);
+        }
+        let mut dirs = fs::read_dir(&self.path).await?;
+        while let Some(dir) = dirs.next_entry().await? {
+            let key = dir.file_name().to_string_lossy().to_string();
+            let Some(mut entry) = self.lookup(&key).await else {
+                log::debug!("skipping incomplete cache entry {key}");
+                continue;
+            };
+            let path = dir.path();
+            entry.size = tokio::task::spawn_blocking(move || dir_size(&path))
+                .await
+                .map_err(|err| error!("{err}"))??;
+            entry.in_use = in_use.contains(&key);
+            entries.push(entry);
+        }
+        entries.sort_by(|a, b| a.plugin.cmp(&b.plugin));
+        Ok(entries)
+    }
+
+    /// Remove the entry with the given key from the cache.
+    pub async fn remove(&self, key: &str) -> Result<(), CoffeeError> {
+        fs::remove_dir_all(self.entry_path(key)).await?;
+        Ok(())
+    }
+}
+
+/// Return the version of the toolchain used to build the plugin.
+async fn toolchain_version(plugin: &Plugin) -> String {
+    let Some(cmd) = plugin.lang.toolchain_version_cmd() else {
+        return "unknown".to_owned();
+    };
+    match Command::new("sh").args(["-c", cmd]).output().await {
+        // some toolchain (e.g: java) print the version on stderr
+        Ok(output) => format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .trim()
+        .to_owned(),
+        Err(_) => "unknown".to_owned(),
+    }
+}
+
+/// Append to the manifest the description of every file
+/// inside the directory, in a stable order.
+fn hash_tree(root: &Path, dir: &Path, manifest: &mut String) -> Result<(), CoffeeError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .to_string();
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            *manifest += &format!("link {relative} {}\n", target.display());
+        } else if file_type.is_dir() {
+            *manifest += &format!("dir {relative}\n");
+            hash_tree(root, &path, manifest)?;
+        } else {
+            let oid = git2::Oid::hash_file(git2::ObjectType::Blob, &path)
+                .map_err(|err| error!("{}", err.message()))?;
+            let executable = entry.metadata()?.permissions().mode() & 0o111 != 0;
+            *manifest += &format!("file {relative} {oid} {executable}\n");
+        }
+    }
+    Ok(())
+}
+
+/// Return the size on disk (in bytes) of the directory.
+fn dir_size(dir: &Path) -> Result<u64, CoffeeError> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = std::fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use coffee_lib::plugin::{Plugin, PluginLang};
+
+    use super::BuildCache;
+
+    #[test]
+    fn test_cache_key_depends_on_sources() {
+        let dir = std::env::temp_dir().join("coffee-cache-key-test");
+        let source = dir.join("helloworld");
+        create_dir_all(&source).unwrap();
+        write(source.join("helloworld.py"), "print('hello')").unwrap();
+
+        let source_str = source.to_string_lossy().to_string();
+        let plugin = Plugin::new(
+            "helloworld",
+            &source_str,
+            &format!("{source_str}/helloworld.py"),
+            PluginLang::PyPip,
+            None,
+            None,
+            None,
+        );
+        let cache = BuildCache::new(&dir.to_string_lossy());
+        let key = cache.key(&plugin, &source_str, "Python 3").unwrap();
+        assert_eq!(key, cache.key(&plugin, &source_str, "Python 3").unwrap());
+        assert_ne!(key, cache.key(&plugin, &source_str, "Python 4").unwrap());
+
+        write(source.join("helloworld.py"), "print('hello world')").unwrap();
+        assert_ne!(key, cache.key(&plugin, &source_str, "Python 3").unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,8 +1,14 @@
 //! Coffee mod implementation
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use std::vec::Vec;
 use tokio::fs;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 use async_trait::async_trait;
 use clightningrpc_common::client::Client;
@@ -12,26 +18,43 @@ use log;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::process::Command;
 
 use coffee_github::repository::Github;
-use coffee_lib::errors::CoffeeError;
+use coffee_github::Checkout;
+use coffee_lib::build::{BuildCanceller, BuildOptions};
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_conf::{check_option_name, PluginOption};
 use coffee_lib::plugin_manager::PluginManager;
 use coffee_lib::repository::Repository;
+use coffee_lib::types::request::HistoryFilter;
 use coffee_lib::types::response::*;
 use coffee_lib::url::URL;
-use coffee_lib::{commit_id, error, get_repo_info, sh};
+use coffee_lib::utils::{find_process_pid, is_same_path};
+use coffee_lib::{commit_id, error, get_repo_info};
+use coffee_storage::migration::{conf_plugins, MigrationChain, SCHEMA_VERSION};
 use coffee_storage::model::repository::{Kind, Repository as RepositoryInfo};
 use coffee_storage::nosql_db::NoSQlStorage;
 use coffee_storage::storage::StorageManager;
 
 use super::config;
+use crate::backup::{self, BackupManifest, BACKUP_MANIFEST};
+use crate::cache::BuildCache;
 use crate::config::CoffeeConf;
-use crate::nurse::chain::RecoveryChainOfResponsibility;
+use crate::history::{self, Operation};
+use crate::lock::{DataDirLock, LockAccess, LockEndpoint};
+use crate::nurse::chain::{Handler, RecoveryChainOfResponsibility};
+use crate::progress::{Phase, Progress};
 use crate::CoffeeArgs;
 
 pub type RepoName = String;
 
+/// Number of times that core lightning is asked for the
+/// state of a plugin after a start or a stop.
+const PLUGIN_STATE_RETRIES: usize = 50;
+/// Time to wait between two requests of the plugin state.
+const PLUGIN_STATE_INTERVAL: Duration = Duration::from_millis(200);
+
 #[derive(Serialize, Deserialize)]
 /// FIXME: move the list of plugin
 /// and the list of repository inside this struct.
@@ -40,6 +63,92 @@ pub struct CoffeeStorageInfo {
     pub repositories: HashMap<RepoName, RepositoryInfo>,
 }
 
+/// Plugins that are ready to be built, see
+/// [`CoffeeManager::prepare_install_plugins`].
+pub struct PendingInstall {
+    names: Vec<String>,
+    /// The plugins with the path of their sources.
+    builds: Vec<(Plugin, String)>,
+    results: HashMap<String, Result<String, CoffeeError>>,
+    operations: HashMap<String, Operation>,
+    verbose: bool,
+    try_dynamic: bool,
+    cache: BuildCache,
+    options: BuildOptions,
+    progress: Progress,
+    jobs: usize,
+}
+
+impl PendingInstall {
+    /// Build the plugins concurrently, with at most `jobs` builds
+    /// running at the same time.
+    pub async fn build(self) -> BuiltInstall {
+        let PendingInstall {
+            names,
+            builds: pending,
+            mut results,
+            operations,
+            verbose,
+            try_dynamic,
+            cache,
+            options,
+            progress,
+            jobs,
+        } = self;
+        let semaphore = Arc::new(Semaphore::new(jobs));
+        let mut builds = JoinSet::new();
+        for (mut plugin, source) in pending {
+            let cache = cache.clone();
+            let options = options.clone();
+            let progress = progress.clone();
+            let semaphore = semaphore.clone();
+            builds.spawn(async move {
+                // the semaphore is never closed, so the permit is always acquired
+                let _permit = semaphore.acquire_owned().await;
+                log::info!("building plugin `{}`", plugin.name());
+                let result = cache
+                    .install(&mut plugin, &source, verbose, &options, &progress)
+                    .await;
+                (plugin, result)
+            });
+        }
+
+        let mut built = vec![];
+        while let Some(build) = builds.join_next().await {
+            match build {
+                Ok((plugin, Ok(path))) => {
+                    log::info!("plugin `{}` built", plugin.name());
+                    log::debug!("runnable plugin path {path}");
+                    built.push((plugin, path));
+                }
+                Ok((plugin, Err(err))) => {
+                    log::info!("plugin `{}` build failed: {err}", plugin.name());
+                    results.insert(plugin.name(), Err(err));
+                }
+                Err(err) => log::error!("plugin build task failed: {err}"),
+            }
+        }
+        BuiltInstall {
+            names,
+            built,
+            results,
+            operations,
+            try_dynamic,
+        }
+    }
+}
+
+/// Plugins that are built, and that are waiting to be registered
+/// with [`CoffeeManager::finish_install_plugins`].
+pub struct BuiltInstall {
+    names: Vec<String>,
+    /// The plugins with the path of their executable.
+    built: Vec<(Plugin, String)>,
+    results: HashMap<String, Result<String, CoffeeError>>,
+    operations: HashMap<String, Operation>,
+    try_dynamic: bool,
+}
+
 impl From<&CoffeeManager> for CoffeeStorageInfo {
     fn from(value: &CoffeeManager) -> Self {
         let mut repos = HashMap::new();
@@ -75,11 +184,27 @@ pub struct CoffeeManager {
     pub rpc: Option<Client>,
     /// Recovery Strategies for the nurse command.
     pub recovery_strategies: RecoveryChainOfResponsibility,
+    /// Handle to cancel the running plugin builds.
+    pub canceller: BuildCanceller,
+    /// Build cache shared between the networks.
+    pub cache: BuildCache,
+    /// Lock of the data directory, held until coffee is dropped.
+    pub lock: DataDirLock,
+    /// Who is asking for the operations, stored inside the history.
+    pub caller: Caller,
+    /// Progress of the running operations.
+    pub progress: Progress,
+    /// Number of the requests to core lightning that failed,
+    /// shared with whoever wants to monitor coffee.
+    pub cln_errors: Arc<AtomicU64>,
 }
 
 impl CoffeeManager {
     pub async fn new(conf: &dyn CoffeeArgs) -> Result<Self, CoffeeError> {
         let conf = CoffeeConf::new(conf).await?;
+        // the lock is taken before opening the storage, because
+        // the storage can be opened by one process at the time.
+        let lock = DataDirLock::acquire(&conf.root_path)?;
         let mut coffee = CoffeeManager {
             config: conf.clone(),
             coffee_cln_config: CLNConf::new(conf.config_path, true),
@@ -88,6 +213,12 @@ impl CoffeeManager {
             cln_config: None,
             rpc: None,
             recovery_strategies: RecoveryChainOfResponsibility::new().await?,
+            canceller: BuildCanceller::new(),
+            cache: BuildCache::new(&conf.root_path),
+            lock,
+            caller: Caller::Cli,
+            progress: Progress::new(),
+            cln_errors: Arc::new(AtomicU64::new(0)),
         };
         coffee.inventory().await?;
         Ok(coffee)
@@ -96,27 +227,17 @@ impl CoffeeManager {
     /// when coffee is configured, run an inventory to collect all the necessary information
     /// about the coffee ecosystem.
     async fn inventory(&mut self) -> Result<(), CoffeeError> {
-        let _ = self
-            .storage
-            .load::<CoffeeStorageInfo>(&self.config.network)
-            .await
-            .map(|store| {
-                self.config = store.config;
-            });
-        // FIXME: check if this exist in a better wai
-        let _ = self
-            .storage
-            .load::<HashMap<RepoName, RepositoryInfo>>("repositories")
+        // upgrade the data stored by an older version of coffee
+        // before reading it.
+        let conf = fs::read_to_string(&self.config.config_path)
             .await
-            .map(|item| {
-                log::debug!("repositories in store {:?}", item);
-                item.iter().for_each(|repo| match repo.1.kind {
-                    Kind::Git => {
-                        let repo = Github::from(repo.1);
-                        self.repos.insert(repo.name(), Box::new(repo));
-                    }
-                });
-            });
+            .unwrap_or_default();
+        let migrations = MigrationChain::new(conf_plugins(&conf));
+        let backup_dir = format!("{}/backups", self.config.root_path);
+        for key in [self.config.network.as_str(), "repositories"] {
+            migrations.run(&self.storage, key, &backup_dir).await?;
+        }
+        self.load_storage().await;
 
         if let Err(err) = self.coffee_cln_config.parse() {
             log::error!("{}", err.cause);
@@ -126,7 +247,7 @@ impl CoffeeManager {
             let status = self.recovery_strategies.scan(self).await?;
             log::debug!("Chain of responsibility status: {:?}", status);
             // if any defect is found, we print a warning message (we don't take action)
-            if !status.defects.is_empty() {
+            if status.is_blocked() {
                 return Err(
                     error!("Coffee found some defects in the configuration. Please run `coffee nurse` to fix them.
                     If you are want to skip the verification, please add the `--skip-verify ` flag to the command.")
@@ -134,34 +255,87 @@ impl CoffeeManager {
             };
         }
 
-        self.load_cln_conf().await?;
+        if let Err(err) = self.load_cln_conf().await {
+            // when the verification is skipped (e.g. by nurse), a broken
+            // link with core lightning should not stop coffee.
+            if !self.config.skip_verify {
+                return Err(err);
+            }
+            log::warn!(
+                "the link with core lightning is broken, run `coffee nurse --verify` to see the defects: {err}"
+            );
+        }
         log::debug!("cln conf {:?}", self.coffee_cln_config);
         log::debug!("finish plugin manager inventory");
         Ok(())
     }
 
+    /// Load the coffee information made persistent inside the storage.
+    async fn load_storage(&mut self) {
+        let _ = self
+            .storage
+            .load::<CoffeeStorageInfo>(&self.config.network)
+            .await
+            .map(|mut store| {
+                // the build limits are given by the command line
+                // so we do not override them with the stored one.
+                store.config.build_limits = self.config.build_limits.clone();
+                store.config.jobs = self.config.jobs;
+                self.config = store.config;
+            });
+        // FIXME: check if this exist in a better wai
+        let _ = self
+            .storage
+            .load::<HashMap<RepoName, RepositoryInfo>>("repositories")
+            .await
+            .map(|item| {
+                log::debug!("repositories in store {:?}", item);
+                item.iter().for_each(|repo| match repo.1.kind {
+                    Kind::Git => {
+                        let repo = Github::from(repo.1);
+                        self.repos.insert(repo.name(), Box::new(repo));
+                    }
+                });
+            });
+    }
+
     pub async fn cln<T: Serialize, U: DeserializeOwned + Debug>(
         &self,
         method: &str,
         payload: T,
     ) -> Result<U, CoffeeError> {
         if let Some(rpc) = &self.rpc {
-            let response = rpc
-                .send_request(method, payload)
-                .map_err(|err| error!("{}", &format!("{err}")))?;
+            let response = rpc.send_request(method, payload).map_err(|err| {
+                self.cln_errors.fetch_add(1, Ordering::Relaxed);
+                error!(kind = ClnUnavailable, "{}", &format!("{err}"))
+            })?;
             log::debug!("cln answer with {:?}", response);
             if let Some(err) = response.error {
+                self.cln_errors.fetch_add(1, Ordering::Relaxed);
                 return Err(error!("{}", &format!("cln error: {}", err.message)));
             }
             return Ok(response.result.unwrap());
         }
-        Err(error!("rpc connection to core lightning not available"))
+        Err(error!(
+            kind = ClnUnavailable,
+            "rpc connection to core lightning not available"
+        ))
     }
 
-    pub async fn start_plugin(&self, path: &str) -> Result<(), CoffeeError> {
+    /// Start the plugin with the given options, core lightning reads
+    /// the configuration file only at startup, so the options need to
+    /// be passed to the `plugin start` command.
+    pub async fn start_plugin(
+        &self,
+        path: &str,
+        options: &[(String, String)],
+    ) -> Result<(), CoffeeError> {
         let mut payload = json_utils::init_payload();
         json_utils::add_str(&mut payload, "subcommand", "start");
         json_utils::add_str(&mut payload, "plugin", path);
+        for (option, value) in options {
+            json_utils::add_str(&mut payload, option, value);
+        }
         let response = self
             .cln::<serde_json::Value, serde_json::Value>("plugin", payload)
             .await?;
@@ -180,6 +354,561 @@ impl CoffeeManager {
         Ok(())
     }
 
+    /// Return the plugins that core lightning is running.
+    pub async fn cln_plugins(&self) -> Result<Vec<ClnPlugin>, CoffeeError> {
+        let mut payload = json_utils::init_payload();
+        json_utils::add_str(&mut payload, "subcommand", "list");
+        let response = self
+            .cln::<serde_json::Value, ClnPluginList>("plugin", payload)
+            .await?;
+        Ok(response.plugins)
+    }
+
+    /// Return the installed plugin that can be started or stopped
+    /// at runtime.
+    fn runtime_plugin(&self, name: &str) -> Result<Plugin, CoffeeError> {
+        let plugin = self.installed_plugin(name)?;
+        if plugin.important() {
+            return Err(error!(
+                kind = InvalidRequest,
+                "Plugin `{name}` is an important plugin, it can not be started or stopped at runtime"
+            ));
+        }
+        Ok(plugin)
+    }
+
+    /// Return the installed plugin with the given name.
+    fn installed_plugin(&self, name: &str) -> Result<Plugin, CoffeeError> {
+        self.config
+            .plugins
+            .iter()
+            .find(|plugin| plugin.name() == name)
+            .cloned()
+            .ok_or(error!(
+                kind = PluginNotFound,
+                "No plugin with name `{name}` found in the plugins installed"
+            ))
+    }
+
+    /// Return the values of the key inside the coffee cln configuration.
+    pub fn cln_conf_values(&self, key: &str) -> Vec<String> {
+        self.coffee_cln_config
+            .fields
+            .get(key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace the values of the key inside the coffee cln
+    /// configuration, the duplicated values are dropped.
+    fn rewrite_cln_conf_values(&mut self, key: &str, values: &[String]) -> Result<(), CoffeeError> {
+        if !self.cln_conf_values(key).is_empty() {
+            self.coffee_cln_config
+                .rm_conf(key, None)
+                .map_err(|err| error!("{}", err.cause))?;
+        }
+        let mut written: Vec<&String> = vec![];
+        for value in values {
+            if written.contains(&value) {
+                continue;
+            }
+            self.coffee_cln_config
+                .add_conf(key, value)
+                .map_err(|err| error!("{}", err.cause))?;
+            written.push(value);
+        }
+        Ok(())
+    }
+
+    /// Return the value of the option inside the coffee configuration.
+    fn option_value(&self, option: &str) -> Option<String> {
+        self.coffee_cln_config
+            .fields
+            .get(option)
+            .and_then(|values| values.last().cloned())
+    }
+
+    /// Refuse an option declared by more than one installed plugin, because
+    /// the options are global keys of the core lightning configuration.
+    fn check_option_owner(&self, option: &str) -> Result<(), CoffeeError> {
+        let plugins: Vec<String> = self
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| plugin.options().contains_key(option))
+            .map(|plugin| plugin.name())
+            .collect();
+        if plugins.len() > 1 {
+            return Err(error!(
+                kind = Conflict,
+                "Option `{option}` is declared by the plugins {}, it can not be configured for only one of them",
+                plugins.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Return the options declared by the plugin with the configured values.
+    fn plugin_options(&self, plugin: &Plugin) -> Vec<PluginOptionValue> {
+        plugin
+            .options()
+            .into_iter()
+            .map(|(name, option)| PluginOptionValue {
+                value: self.option_value(&name),
+                name,
+                kind: option.kind.to_string(),
+                default: option.default,
+                description: option.description,
+            })
+            .collect()
+    }
+
+    /// Return the options of the plugin that have a value
+    /// inside the coffee configuration.
+    fn configured_options(&self, plugin: &Plugin) -> Vec<(String, String)> {
+        plugin
+            .options()
+            .into_keys()
+            .filter_map(|option| self.option_value(&option).map(|value| (option, value)))
+            .collect()
+    }
+
+    /// Stop the plugin, if running, and start it again with
+    /// the options inside the coffee configuration.
+    async fn restart_plugin(&self, plugin: &Plugin) -> Result<PluginStatus, CoffeeError> {
+        if self.cln_plugin(&plugin.exec_path).await?.is_some() {
+            self.stop_plugin(&plugin.exec_path).await?;
+            self.wait_plugin_state(plugin, false).await?;
+        }
+        let options = self.configured_options(plugin);
+        self.start_plugin(&plugin.exec_path, &options).await?;
+        self.wait_plugin_state(plugin, true).await
+    }
+
+    /// Apply the change of an option to the running plugin.
+    ///
+    /// The option is pushed with `setconfig` when the plugin declares it
+    /// dynamic, otherwise the plugin is restarted with the new options.
+    async fn apply_option(
+        &self,
+        plugin: &Plugin,
+        option: &str,
+        declared: &PluginOption,
+    ) -> ConfigApplied {
+        match self.cln_plugin(&plugin.exec_path).await {
+            Ok(Some(cln_plugin)) if cln_plugin.active => {}
+            Ok(_) => {
+                return ConfigApplied::NextStart(format!(
+                    "plugin `{}` is not running",
+                    plugin.name()
+                ))
+            }
+            Err(err) => {
+                return ConfigApplied::NextStart(format!("unable to reach core lightning: {err}"))
+            }
+        }
+        if declared.dynamic == Some(true) {
+            let value = self
+                .option_value(option)
+                .or(declared.default.as_ref().map(|default| match default {
+                    serde_json::Value::String(default) => default.to_owned(),
+                    default => default.to_string(),
+                }));
+            if let Some(value) = value {
+                let result = self
+                    .cln::<serde_json::Value, serde_json::Value>(
+                        "setconfig",
+                        json!({
+                            "config": option,
+                            "val": value,
+                        }),
+                    )
+                    .await;
+                match result {
+                    Ok(_) => return ConfigApplied::SetConfig,
+                    Err(err) => log::warn!("unable to set `{option}` with setconfig: {err}"),
+                }
+            }
+        }
+        if plugin.important() {
+            return ConfigApplied::NextStart(format!(
+                "plugin `{}` is an important plugin and it can not be restarted, restart core lightning to apply the change",
+                plugin.name()
+            ));
+        }
+        match self.restart_plugin(plugin).await {
+            Ok(_) => ConfigApplied::Restarted,
+            Err(err) => ConfigApplied::NextStart(format!(
+                "unable to restart plugin `{}` ({err}), restart core lightning to apply the change",
+                plugin.name()
+            )),
+        }
+    }
+
+    /// Return true if core lightning loads the plugin from the
+    /// coffee configuration.
+    ///
+    /// The options of a plugin that is not loaded at startup make
+    /// core lightning fail, so only these plugins can be configured.
+    fn is_configured(&self, plugin: &Plugin) -> bool {
+        ["plugin", "important-plugin"].iter().any(|key| {
+            self.coffee_cln_config
+                .fields
+                .get(*key)
+                .map(|paths| paths.contains(&plugin.exec_path))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Return the plugin as reported by core lightning, if present.
+    async fn cln_plugin(&self, exec_path: &str) -> Result<Option<ClnPlugin>, CoffeeError> {
+        let plugin = self
+            .cln_plugins()
+            .await?
+            .into_iter()
+            .find(|cln_plugin| is_same_path(&cln_plugin.name, exec_path));
+        Ok(plugin)
+    }
+
+    /// Wait until core lightning confirms that the plugin
+    /// is running (or not running).
+    async fn wait_plugin_state(
+        &self,
+        plugin: &Plugin,
+        running: bool,
+    ) -> Result<PluginStatus, CoffeeError> {
+        for _ in 0..PLUGIN_STATE_RETRIES {
+            let cln_plugin = self.cln_plugin(&plugin.exec_path).await?;
+            let active = cln_plugin
+                .as_ref()
+                .map(|cln_plugin| cln_plugin.active)
+                .unwrap_or(false);
+            if active == running {
+                return Ok(PluginStatus {
+                    name: plugin.name(),
+                    exec_path: plugin.exec_path.clone(),
+                    state: if running {
+                        PluginState::Running
+                    } else {
+                        PluginState::Stopped
+                    },
+                    dynamic: cln_plugin.map(|cln_plugin| cln_plugin.dynamic),
+                    pid: find_process_pid(&plugin.exec_path),
+                });
+            }
+            tokio::time::sleep(PLUGIN_STATE_INTERVAL).await;
+        }
+        let state = if running { "running" } else { "stopped" };
+        Err(error!(
+            "core lightning did not report the plugin `{}` as {state}",
+            plugin.name()
+        ))
+    }
+
+    /// Return the options used to build the plugins.
+    pub fn build_options(&self) -> BuildOptions {
+        BuildOptions::new(self.config.build_limits.clone(), self.canceller.clone())
+    }
+
+    /// Return the number of plugins that can be built concurrently.
+    pub fn jobs(&self) -> usize {
+        self.config
+            .jobs
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|jobs| jobs.get())
+                    .unwrap_or(1)
+            })
+            .max(1)
+    }
+
+    /// Look up the plugin inside the repositories and prepare
+    /// it to be installed inside the network directory.
+    ///
+    /// Return the plugin with the install paths, and the path
+    /// where the plugin sources are stored.
+    fn prepare_install(
+        &self,
+        plugin: &str,
+        try_dynamic: bool,
+    ) -> Result<(Plugin, String), CoffeeError> {
+        if self.config.plugins.iter().any(|p| p.name() == plugin) {
+            return Err(error!(
+                kind = AlreadyInstalled,
+                "Plugin with name `{plugin}` already installed"
+            ));
+        }
+
+        for repo in self.repos.values() {
+            if let Some(mut plugin) = repo.get_plugin_by_name(plugin) {
+                log::trace!("{:?}", plugin);
+
+                if try_dynamic && plugin.important() {
+                    return Err(error!(
+                        kind = InvalidRequest,
+                        "plugin is important, can't be dynamically installed"
+                    ));
+                }
+
+                // old_root_path is the path where the plugin is cloned and currently stored
+                // eg. ~/.coffee/repositories/<repo_name>/<plugin_name>
+                let old_root_path = plugin.root_path.clone();
+                // new_root_path is the path where the plugin will be installed specific to the network
+                // eg. ~/.coffee/<network>/plugins/<plugin_name>
+                let new_root_path = format!(
+                    "{}/{}/plugins/{}",
+                    self.config.root_path,
+                    self.config.network,
+                    plugin.name()
+                );
+
+                let Some(relative_path) = plugin.exec_path.strip_prefix(&old_root_path) else {
+                    return Err(error!("exec path not found"));
+                };
+                plugin.exec_path = format!("{}{}", new_root_path, relative_path);
+                plugin.root_path = new_root_path;
+                log::debug!("plugin: {:?}", plugin);
+                return Ok((plugin, old_root_path));
+            }
+        }
+        Err(error!(
+            kind = PluginNotFound,
+            "plugin `{plugin}` are not present inside the repositories"
+        ))
+    }
+
+    /// Check that the built plugin can still be registered, the
+    /// httpd releases coffee while the plugins are building so
+    /// the plugin (or its repository) can change in the meantime.
+    async fn check_built_plugin(&self, plugin: &Plugin) -> Result<(), CoffeeError> {
+        let name = plugin.name();
+        if self.config.plugins.iter().any(|p| p.name() == name) {
+            return Err(error!(
+                kind = AlreadyInstalled,
+                "Plugin with name `{name}` already installed"
+            ));
+        }
+        if !self
+            .repos
+            .values()
+            .any(|repo| repo.get_plugin_by_name(&name).is_some())
+        {
+            // nobody owns the built sources anymore.
+            if let Err(err) = fs::remove_dir_all(&plugin.root_path).await {
+                log::warn!("unable to remove `{}`: {err}", plugin.root_path);
+            }
+            return Err(error!(
+                kind = RepositoryNotFound,
+                "the repository of plugin `{name}` was removed while the plugin was building"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Register a built plugin inside the coffee configuration.
+    fn register_plugin(
+        &mut self,
+        mut plugin: Plugin,
+        path: &str,
+        try_dynamic: bool,
+    ) -> Result<(), CoffeeError> {
+        if !try_dynamic {
+            let plugin_conf_key = if plugin.important() {
+                "important-plugin"
+            } else {
+                "plugin"
+            };
+            log::debug!("path coffee conf: {}", self.coffee_cln_config.path);
+            self.coffee_cln_config
+                .add_conf(plugin_conf_key, path)
+                .map_err(|err| error!("{}", err.cause))?;
+            log::debug!("coffee conf updated: {}", self.coffee_cln_config);
+            // mark the plugin enabled
+            plugin.enabled = Some(true);
+        }
+        self.config.plugins.push(plugin);
+        Ok(())
+    }
+
+    /// Look up the plugins that are going to be installed, the
+    /// plugins can then be built without borrowing coffee.
+    ///
+    /// A plugin that can not be installed keeps its error, so it is
+    /// reported together with the result of the other plugins.
+    pub fn prepare_install_plugins(
+        &self,
+        plugins: &[String],
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> PendingInstall {
+        let mut pending = PendingInstall {
+            names: vec![],
+            builds: vec![],
+            results: HashMap::new(),
+            operations: HashMap::new(),
+            verbose,
+            try_dynamic,
+            cache: self.cache.clone(),
+            options: self.build_options(),
+            progress: self.progress.clone(),
+            jobs: self.jobs(),
+        };
+        for name in plugins {
+            if pending.names.contains(name) {
+                continue;
+            }
+            pending.names.push(name.to_owned());
+            let operation = Operation::new("install", Some(name)).arg("try_dynamic", try_dynamic);
+            pending
+                .operations
+                .insert(name.to_owned(), self.operation(operation));
+            match self.prepare_install(name, try_dynamic) {
+                Ok(prepared) => pending.builds.push(prepared),
+                Err(err) => {
+                    pending.results.insert(name.to_owned(), Err(err));
+                }
+            }
+        }
+        pending
+    }
+
+    /// Register the built plugins inside the coffee configuration.
+    ///
+    /// Return for each plugin the path of the executable or the error.
+    async fn register_built_plugins(
+        &mut self,
+        built: BuiltInstall,
+    ) -> Vec<(String, Result<String, CoffeeError>)> {
+        let BuiltInstall {
+            names,
+            built,
+            mut results,
+            try_dynamic,
+            ..
+        } = built;
+        let mut registered = vec![];
+        for (plugin, path) in built {
+            let name = plugin.name();
+            self.progress.started(&name, Phase::Configuring);
+            let registered_plugin = match self.check_built_plugin(&plugin).await {
+                Ok(_) => self.register_plugin(plugin, &path, try_dynamic),
+                Err(err) => Err(err),
+            };
+            match registered_plugin {
+                Ok(_) => registered.push((name, path)),
+                Err(err) => {
+                    self.progress.failed(&name, Phase::Configuring, &err);
+                    results.insert(name, Err(err));
+                }
+            }
+        }
+        if !registered.is_empty() {
+            let flushed = match self.flush().await {
+                Ok(_) => self.update_conf().await,
+                Err(err) => Err(err),
+            };
+            for (name, path) in registered {
+                let result = match &flushed {
+                    Ok(_) => {
+                        self.progress.finished(&name, Phase::Configuring);
+                        if try_dynamic {
+                            self.progress
+                                .phase(&name, Phase::Starting, self.start_plugin(&path, &[]))
+                                .await
+                                .map(|_| path)
+                        } else {
+                            Ok(path)
+                        }
+                    }
+                    Err(err) => {
+                        self.progress.failed(&name, Phase::Configuring, err);
+                        Err(err.clone())
+                    }
+                };
+                results.insert(name, result);
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|name| {
+                let result = results.remove(&name).unwrap_or_else(|| {
+                    Err(error!("build of plugin `{name}` stopped unexpectedly"))
+                });
+                (name, result)
+            })
+            .collect()
+    }
+
+    /// Register the built plugins inside the coffee configuration,
+    /// and store the install of each plugin inside the history.
+    pub async fn finish_install_plugins(
+        &mut self,
+        mut built: BuiltInstall,
+    ) -> Vec<(String, Result<String, CoffeeError>)> {
+        let mut operations = std::mem::take(&mut built.operations);
+        let results = self.register_built_plugins(built).await;
+        for (plugin, result) in results.iter() {
+            if let Some(operation) = operations.remove(plugin) {
+                self.journal(operation, result).await;
+            }
+        }
+        results
+    }
+
+    /// Install the plugins by name, building them concurrently with at
+    /// most `jobs` builds running at the same time.
+    ///
+    /// A failure in one plugin does not abort the install of the others,
+    /// only the update of the coffee configuration is serialized.
+    ///
+    /// The installs are not stored inside the history, so the caller
+    /// has to store the operation that needs them.
+    ///
+    /// Return for each plugin the path of the executable or the error.
+    pub async fn install_plugins(
+        &mut self,
+        plugins: &[String],
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> Vec<(String, Result<String, CoffeeError>)> {
+        let built = self
+            .prepare_install_plugins(plugins, verbose, try_dynamic)
+            .build()
+            .await;
+        self.register_built_plugins(built).await
+    }
+
+    /// Collect the build cache keys used by the plugins
+    /// installed in any network.
+    pub async fn cache_keys_in_use(&self) -> Result<HashSet<String>, CoffeeError> {
+        let mut keys: HashSet<String> = self
+            .config
+            .plugins
+            .iter()
+            .filter_map(|plugin| plugin.cache_key.clone())
+            .collect();
+        let mut dirs = fs::read_dir(&self.config.root_path).await?;
+        while let Some(dir) = dirs.next_entry().await? {
+            let network = dir.file_name().to_string_lossy().to_string();
+            if network == self.config.network || !dir.file_type().await?.is_dir() {
+                continue;
+            }
+            // directories that are not a network do not
+            // have anything inside the storage.
+            if let Ok(store) = self.storage.load::<CoffeeStorageInfo>(&network).await {
+                keys.extend(
+                    store
+                        .config
+                        .plugins
+                        .iter()
+                        .filter_map(|plugin| plugin.cache_key.clone()),
+                );
+            }
+        }
+        Ok(keys)
+    }
+
     pub fn storage_info(&self) -> CoffeeStorageInfo {
         CoffeeStorageInfo::from(self)
     }
@@ -208,11 +937,14 @@ impl CoffeeManager {
         let root = self.config.cln_root.clone().unwrap();
         // We check if there is some problem we the path that we know
         if !fs::try_exists(root.clone()).await? {
-            return Err(error!("lightning root path `{}` do not exist", root));
+            return Err(error!(
+                kind = NotFound,
+                "lightning root path `{}` do not exist", root
+            ));
         } else if !fs::try_exists(format!("{root}/{}", self.config.network)).await? {
             return Err(error!(
-                "lightning network path `{root}/{}` do not exist",
-                self.config.network
+                kind = NotFound,
+                "lightning network path `{root}/{}` do not exist", self.config.network
             ));
         }
         // All safe, we can move with the logic
@@ -228,10 +960,248 @@ impl CoffeeManager {
         Ok(())
     }
 
-    pub async fn link_with_cln(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
-        if self.cln_config.is_some() {
-            log::warn!("you are overriding the previous set up");
-        }
+    /// Remove from the coffee cln configuration the plugins
+    /// that are not installed.
+    pub async fn patch_plugin_conf_entry_unknown(
+        &mut self,
+        paths: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        for key in ["plugin", "important-plugin"] {
+            let values: Vec<String> = self
+                .cln_conf_values(key)
+                .into_iter()
+                .filter(|path| !paths.contains(path))
+                .collect();
+            self.rewrite_cln_conf_values(key, &values)?;
+        }
+        self.update_conf().await?;
+        Ok(vec![NurseStatus::PluginConfEntryRemoved(paths)])
+    }
+
+    /// Add back to the coffee cln configuration the plugins
+    /// installed that are missing.
+    pub async fn patch_plugin_conf_entry_missing(
+        &mut self,
+        plugins: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let missing: Vec<Plugin> = self
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| plugins.contains(&plugin.name()))
+            .cloned()
+            .collect();
+        for plugin in missing {
+            let key = if plugin.important() {
+                "important-plugin"
+            } else {
+                "plugin"
+            };
+            let mut values = self.cln_conf_values(key);
+            values.push(plugin.exec_path.clone());
+            self.rewrite_cln_conf_values(key, &values)?;
+        }
+        self.update_conf().await?;
+        Ok(vec![NurseStatus::PluginConfEntryRestored(plugins)])
+    }
+
+    /// Make the `disable-plugin` lines of the coffee cln configuration
+    /// match the plugins that are disabled.
+    pub async fn patch_disable_plugin_entry_mismatch(
+        &mut self,
+        paths: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let disabled: Vec<String> = self
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| plugin.enabled == Some(false))
+            .map(|plugin| plugin.exec_path.clone())
+            .collect();
+        let mut values: Vec<String> = self
+            .cln_conf_values("disable-plugin")
+            .into_iter()
+            .filter(|path| !paths.contains(path) || disabled.contains(path))
+            .collect();
+        for path in paths.iter() {
+            if disabled.contains(path) {
+                values.push(path.to_owned());
+            }
+        }
+        self.rewrite_cln_conf_values("disable-plugin", &values)?;
+        self.update_conf().await?;
+        Ok(vec![NurseStatus::DisablePluginEntryFixed(paths)])
+    }
+
+    /// Remove the duplicated lines from the coffee cln configuration.
+    pub async fn patch_duplicated_conf_entries(
+        &mut self,
+        entries: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let mut keys: Vec<&str> = entries
+            .iter()
+            .filter_map(|entry| entry.split_once('=').map(|(key, _)| key))
+            .collect();
+        keys.dedup();
+        for key in keys {
+            let values = self.cln_conf_values(key);
+            self.rewrite_cln_conf_values(key, &values)?;
+        }
+        self.update_conf().await?;
+        Ok(vec![NurseStatus::DuplicatedConfEntriesRemoved(entries)])
+    }
+
+    /// Prepare the sources of the plugin at the commit used to
+    /// install it, checking out the repository in a temporary
+    /// directory when the repository moved to another commit.
+    ///
+    /// Return the path of the plugin sources, and the temporary
+    /// directory to clean up.
+    fn plugin_sources_at_commit(
+        &self,
+        plugin: &Plugin,
+    ) -> Result<(String, Option<String>), CoffeeError> {
+        let (repo_path, source) = self
+            .repos
+            .values()
+            .find_map(|repo| {
+                repo.get_plugin_by_name(&plugin.name())
+                    .map(|source| (repo.url().path_string, source))
+            })
+            .ok_or(error!(
+                kind = PluginNotFound,
+                "plugin `{}` is not present inside the repositories",
+                plugin.name()
+            ))?;
+        if !Path::new(&source.root_path).exists() {
+            return Err(error!(
+                kind = NotFound,
+                "the sources of plugin `{}` are missing",
+                plugin.name()
+            ));
+        }
+        let Some(commit) = plugin.commit.clone() else {
+            return Ok((source.root_path, None));
+        };
+        let repository =
+            git2::Repository::open(&repo_path).map_err(|err| error!("{}", err.message()))?;
+        let (head, _) = get_repo_info!(repository);
+        if head == commit {
+            return Ok((source.root_path, None));
+        }
+
+        log::debug!("checkout plugin `{}` at commit {commit}", plugin.name());
+        let tmp_path = format!("{}/tmp/{}-{commit}", self.config.root_path, plugin.name());
+        let _ = std::fs::remove_dir_all(&tmp_path);
+        let repository = git2::Repository::clone(&repo_path, &tmp_path)
+            .map_err(|err| error!("{}", err.message()))?;
+        let checkout = || -> Result<(), git2::Error> {
+            let oid = git2::Oid::from_str(&commit)?;
+            let target = repository.find_commit(oid)?;
+            repository.checkout_tree(
+                target.as_object(),
+                Some(git2::build::CheckoutBuilder::new().force()),
+            )?;
+            repository.set_head_detached(oid)
+        };
+        if let Err(err) = checkout() {
+            let _ = std::fs::remove_dir_all(&tmp_path);
+            let kind = if err.code() == git2::ErrorCode::NotFound {
+                ErrorKind::NotFound
+            } else {
+                ErrorKind::Internal
+            };
+            return Err(error!(
+                "unable to checkout plugin `{}` at commit {commit}: {}",
+                plugin.name(),
+                err.message()
+            )
+            .with_kind(kind));
+        }
+        let Some(relative_path) = source.root_path.strip_prefix(&repo_path) else {
+            let _ = std::fs::remove_dir_all(&tmp_path);
+            return Err(error!("plugin path outside the repository"));
+        };
+        Ok((format!("{tmp_path}{relative_path}"), Some(tmp_path)))
+    }
+
+    /// Rebuild an installed plugin from the stored repository
+    /// at the commit used to install it.
+    pub async fn rebuild_plugin(&mut self, name: &str) -> Result<(), CoffeeError> {
+        let mut plugin = self.installed_plugin(name)?;
+        let (source, tmp_path) = self.plugin_sources_at_commit(&plugin)?;
+        if Path::new(&plugin.root_path).exists() {
+            fs::remove_dir_all(&plugin.root_path).await?;
+        }
+        let result = self
+            .cache
+            .install(
+                &mut plugin,
+                &source,
+                false,
+                &self.build_options(),
+                &self.progress,
+            )
+            .await;
+        if let Some(tmp_path) = tmp_path {
+            let _ = fs::remove_dir_all(tmp_path).await;
+        }
+        let exec_path = result?;
+        if !is_same_path(&exec_path, &plugin.exec_path) {
+            return Err(error!(
+                "plugin `{name}` rebuilt in `{exec_path}` instead of `{}`",
+                plugin.exec_path
+            ));
+        }
+        if let Some(installed) = self
+            .config
+            .plugins
+            .iter_mut()
+            .find(|installed| installed.name() == name)
+        {
+            installed.cache_key = plugin.cache_key;
+        }
+        self.flush().await?;
+        Ok(())
+    }
+
+    /// Rebuild the plugins that are broken on disk, the plugins
+    /// are removed only when their sources are not available anymore.
+    pub async fn patch_plugin_directory_broken(
+        &mut self,
+        plugins: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let mut nurse_actions: Vec<NurseStatus> = vec![];
+        for plugin in plugins {
+            match self.rebuild_plugin(&plugin).await {
+                Ok(_) => {
+                    log::info!("plugin {plugin} rebuilt");
+                    nurse_actions.push(NurseStatus::PluginRebuilt(vec![plugin]));
+                }
+                // the repository, the sources or the commit are gone,
+                // so the plugin can not be rebuilt anymore.
+                Err(err)
+                    if matches!(err.kind(), ErrorKind::PluginNotFound | ErrorKind::NotFound) =>
+                {
+                    log::info!("unable to rebuild plugin {plugin}: {err}, removing it");
+                    self.do_remove(&plugin).await?;
+                    nurse_actions.push(NurseStatus::PluginRemoved(vec![plugin]));
+                }
+                // e.g. a network error or a build timeout, the
+                // plugin is kept so it can be repaired again.
+                Err(err) => {
+                    log::warn!("unable to rebuild plugin {plugin}: {err}");
+                    nurse_actions.push(NurseStatus::PluginRebuildFailed(vec![plugin]));
+                }
+            }
+        }
+        Ok(nurse_actions)
+    }
+
+    pub async fn link_with_cln(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+        if self.cln_config.is_some() {
+            log::warn!("you are overriding the previous set up");
+        }
         let path_with_network = format!("{cln_dir}/{}/config", self.config.network);
         log::info!("configure coffee in the following cln config {path_with_network}");
         self.config.cln_config_path = Some(path_with_network);
@@ -244,122 +1214,660 @@ impl CoffeeManager {
         Ok(())
     }
 
-    /// Unlink coffee from the core lightning configuration file
-    pub async fn unlink_from_cln(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
-        if self.cln_config.is_none() {
-            return Err(error!("no cln configuration found"));
-        }
-        let path_with_network = format!("{cln_dir}/{}/config", self.config.network);
-        log::info!("teardown coffee in the following cln config {path_with_network}");
-        let mut conf = self.cln_config.clone().unwrap();
-        conf.rm_subconf(&self.coffee_cln_config.clone().path)
-            .map_err(|err| error!("{}", &err.cause))?;
-        conf.flush()?;
-        Ok(())
+    /// Include again the coffee configuration inside the
+    /// core lightning configuration.
+    pub async fn patch_cln_config_unlinked(
+        &mut self,
+        paths: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        for path in paths.iter() {
+            log::info!("linking again coffee to {path}");
+            let mut conf = CLNConf::new(path.to_owned(), true);
+            conf.parse()
+                .map_err(|err| CoffeeError::new(err.core, &err.cause))?;
+            conf.add_subconf(self.coffee_cln_config.clone())
+                .map_err(|err| error!("{}", &err.cause))?;
+            conf.flush()?;
+            if self.config.cln_config_path.as_ref() == Some(path) {
+                self.cln_config = Some(conf);
+            }
+        }
+        Ok(vec![NurseStatus::ClnConfigRelinked(paths)])
+    }
+
+    /// Link coffee again to the core lightning directory that contains
+    /// the configuration of the network, looking inside the linked
+    /// directory and its parents (e.g. when coffee was linked to the
+    /// directory of another network).
+    ///
+    /// The link is kept when the configuration is not found, so
+    /// the user can link coffee again with `coffee link`.
+    pub async fn patch_cln_path_missing(
+        &mut self,
+        paths: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let Some(cln_root) = self.config.cln_root.clone() else {
+            return Ok(vec![]);
+        };
+        let network = self.config.network.clone();
+        let found = Path::new(&cln_root)
+            .ancestors()
+            .find(|dir| dir.join(&network).join("config").exists())
+            .map(|dir| dir.to_string_lossy().to_string());
+        let Some(cln_dir) = found else {
+            log::warn!("core lightning configuration of `{network}` not found near {cln_root}");
+            return Ok(vec![NurseStatus::ClnLinkNotRepaired(paths)]);
+        };
+        let cln_config_path = format!("{cln_dir}/{network}/config");
+        log::info!("linking again coffee to {cln_config_path}");
+        let content = fs::read_to_string(&cln_config_path).await?;
+        let linked = content.lines().any(|line| {
+            line.trim()
+                .strip_prefix("include")
+                .is_some_and(|path| is_same_path(path.trim(), &self.config.config_path))
+        });
+        if linked {
+            // the configuration includes coffee already, only the link is fixed.
+            self.config.cln_config_path = Some(cln_config_path.clone());
+            self.config.cln_root = Some(cln_dir);
+            self.load_cln_conf().await?;
+        } else {
+            self.link_with_cln(&cln_dir).await?;
+        }
+        self.flush().await?;
+        Ok(vec![NurseStatus::ClnConfigRelinked(vec![cln_config_path])])
+    }
+
+    /// Write the backup content inside the `staging` directory,
+    /// and compress it inside the `archive`.
+    async fn write_backup(
+        &self,
+        staging: &str,
+        archive: &str,
+        with_plugins: bool,
+    ) -> Result<(), CoffeeError> {
+        let network = &self.config.network;
+        let mut storage = BTreeMap::new();
+        for key in [network.as_str(), "repositories"] {
+            if self.storage.contains(key) {
+                let value = self.storage.load::<serde_json::Value>(key).await?;
+                storage.insert(key.to_owned(), value);
+            }
+        }
+        let entries = history::entries(&self.storage, network).await?;
+        let entries = serde_json::to_value(entries).map_err(|err| error!("{err}"))?;
+        storage.insert(history::history_key(network), entries);
+        let manifest = BackupManifest {
+            schema_version: SCHEMA_VERSION,
+            network: network.to_owned(),
+            root_path: self.config.root_path.clone(),
+            created_at: backup::timestamp(),
+            with_plugins,
+            storage,
+        };
+        let content = serde_json::to_string_pretty(&manifest).map_err(|err| error!("{err}"))?;
+        fs::write(format!("{staging}/{BACKUP_MANIFEST}"), content).await?;
+
+        let network_dir = format!("{staging}/{network}");
+        fs::create_dir_all(&network_dir).await?;
+        if Path::new(&self.config.config_path).exists() {
+            backup::copy(
+                &self.config.config_path,
+                &format!("{network_dir}/coffee.conf"),
+            )
+            .await?;
+        }
+        let plugins_dir = format!("{}/{network}/plugins", self.config.root_path);
+        if with_plugins && Path::new(&plugins_dir).exists() {
+            backup::copy(&plugins_dir, &format!("{network_dir}/plugins")).await?;
+        }
+        backup::pack(staging, archive).await
+    }
+
+    /// Extract the `archive` inside the `staging` directory, and
+    /// replace the coffee state with the content of the backup.
+    async fn read_backup(&mut self, archive: &str, staging: &str) -> Result<(), CoffeeError> {
+        backup::unpack(archive, staging).await?;
+        let content = fs::read_to_string(format!("{staging}/{BACKUP_MANIFEST}"))
+            .await
+            .map_err(|err| {
+                error!(
+                    kind = InvalidRequest,
+                    "`{archive}` is not a coffee backup: {err}"
+                )
+            })?;
+        let manifest: BackupManifest =
+            serde_json::from_str(&content).map_err(|err| error!("{err}"))?;
+        let network = self.config.network.clone();
+        if manifest.network != network {
+            return Err(error!(
+                kind = InvalidRequest,
+                "the backup is for the network `{}`, run coffee with `--network {}` to restore it",
+                manifest.network,
+                manifest.network
+            ));
+        }
+
+        // the backup can come from another host, so the paths
+        // are moved inside the current coffee home.
+        let root_path = self.config.root_path.clone();
+        let network_dir = format!("{staging}/{network}");
+        let conf = format!("{network_dir}/coffee.conf");
+        let conf = match fs::read_to_string(&conf).await {
+            Ok(content) => Some(backup::relocate(&content, &manifest.root_path, &root_path)),
+            Err(_) => None,
+        };
+        let migrations = MigrationChain::new(conf_plugins(conf.as_deref().unwrap_or_default()));
+        let mut values = vec![];
+        for (key, value) in manifest.storage.iter() {
+            let value = backup::relocate_value(value, &manifest.root_path, &root_path)?;
+            let value = migrations.migrate(key, manifest.schema_version, value)?;
+            values.push((key, value));
+        }
+        // the state is changed only when all the values are valid.
+        for (key, value) in values {
+            self.storage.store(key, &value).await?;
+        }
+
+        if let Some(content) = conf {
+            fs::write(&self.config.config_path, content).await?;
+        }
+        let plugins = format!("{network_dir}/plugins");
+        if Path::new(&plugins).exists() {
+            let plugins_dir = format!("{root_path}/{network}/plugins");
+            fs::create_dir_all(&plugins_dir).await?;
+            backup::copy(&format!("{plugins}/."), &plugins_dir).await?;
+        }
+        Ok(())
+    }
+
+    /// Publish where this process accepts the commands, so the
+    /// command line can forward them instead of failing on the lock.
+    pub fn set_lock_endpoint(
+        &mut self,
+        endpoint: LockEndpoint,
+        access: LockAccess,
+    ) -> Result<(), CoffeeError> {
+        self.lock.set_endpoint(endpoint, access)
+    }
+
+    /// Set who is asking for the next operations.
+    pub fn set_caller(&mut self, caller: Caller) {
+        self.caller = caller;
+    }
+
+    /// Commit of the installed plugin, or of the repository,
+    /// with the given name.
+    fn journal_commit(&self, target: &str) -> Option<String> {
+        if let Some(plugin) = self
+            .config
+            .plugins
+            .iter()
+            .find(|plugin| plugin.name() == target)
+        {
+            return plugin.commit.clone();
+        }
+        self.repos
+            .get(target)
+            .and_then(|repo| repo.as_any().downcast_ref::<Github>())
+            .and_then(|repo| RepositoryInfo::from(repo).git_head)
+    }
+
+    /// Local copies of the repositories, to look for the upstream
+    /// changes without holding coffee while fetching them.
+    pub fn checkouts(&self) -> Vec<Checkout> {
+        self.repos
+            .values()
+            .filter_map(|repo| repo.as_any().downcast_ref::<Github>())
+            .map(Github::checkout)
+            .collect()
+    }
+
+    /// Prepare the operation for the history, with the commit
+    /// of the target before the operation runs.
+    fn operation(&self, mut operation: Operation) -> Operation {
+        operation.before = operation
+            .target
+            .as_ref()
+            .and_then(|target| self.journal_commit(target));
+        operation
+    }
+
+    /// Store the operation inside the history, a failure to store
+    /// it does not change the result of the operation.
+    async fn journal<T>(&self, operation: Operation, result: &Result<T, CoffeeError>) {
+        let name = operation.name.clone();
+        let result = HistoryResult::from(result);
+        let after = operation
+            .target
+            .as_ref()
+            .and_then(|target| self.journal_commit(target));
+        if let Err(err) = history::record(
+            &self.storage,
+            &self.config.network,
+            operation,
+            after,
+            result,
+            &self.caller,
+        )
+        .await
+        {
+            log::warn!("unable to store the operation `{name}` inside the history: {err}");
+        }
+    }
+
+    /// Store the operation inside the history, and return its result.
+    async fn journaled<T>(
+        &self,
+        operation: Operation,
+        result: Result<T, CoffeeError>,
+    ) -> Result<T, CoffeeError> {
+        self.journal(operation, &result).await;
+        result
+    }
+
+    /// Register a nurse handler defined outside coffee, the handler
+    /// is used by the next nurse scan.
+    pub fn register_nurse_handler(&mut self, handler: Arc<dyn Handler>) {
+        self.recovery_strategies.register(handler);
+    }
+
+    /// Unlink coffee from the core lightning configuration file
+    pub async fn unlink_from_cln(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+        if self.cln_config.is_none() {
+            return Err(error!(kind = Conflict, "no cln configuration found"));
+        }
+        let path_with_network = format!("{cln_dir}/{}/config", self.config.network);
+        log::info!("teardown coffee in the following cln config {path_with_network}");
+        let mut conf = self.cln_config.clone().unwrap();
+        conf.rm_subconf(&self.coffee_cln_config.clone().path)
+            .map_err(|err| error!("{}", &err.cause))?;
+        conf.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PluginManager for CoffeeManager {
+    async fn configure(&mut self) -> Result<(), CoffeeError> {
+        log::debug!("plugin configured");
+        Ok(())
+    }
+
+    async fn install(
+        &mut self,
+        plugin: &str,
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> Result<(), CoffeeError> {
+        log::debug!("installing plugin: {plugin}");
+        let built = self
+            .prepare_install_plugins(&[plugin.to_owned()], verbose, try_dynamic)
+            .build()
+            .await;
+        let mut results = self.finish_install_plugins(built).await;
+        let (_, result) = results.remove(0);
+        result.map(|_| ())
+    }
+
+    async fn install_many(
+        &mut self,
+        plugins: &[String],
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> Result<CoffeeInstall, CoffeeError> {
+        log::debug!("installing plugins: {:?}", plugins);
+        let built = self
+            .prepare_install_plugins(plugins, verbose, try_dynamic)
+            .build()
+            .await;
+        let results = self.finish_install_plugins(built).await;
+        let plugins = results
+            .into_iter()
+            .map(|(plugin, result)| PluginInstallStatus::new(&plugin, result))
+            .collect();
+        Ok(CoffeeInstall { plugins })
+    }
+
+    async fn remove(&mut self, plugin: &str) -> Result<CoffeeRemove, CoffeeError> {
+        let operation = self.operation(Operation::new("remove", Some(plugin)));
+        let result = self.do_remove(plugin).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn list(&mut self) -> Result<CoffeeList, CoffeeError> {
+        Ok(CoffeeList {
+            plugins: self.config.plugins.clone(),
+        })
+    }
+
+    async fn status(&mut self) -> Result<CoffeeStatus, CoffeeError> {
+        let mut cln_plugins = self.cln_plugins().await?;
+        let mut plugins = vec![];
+        for plugin in self.config.plugins.iter() {
+            let position = cln_plugins
+                .iter()
+                .position(|cln_plugin| is_same_path(&cln_plugin.name, &plugin.exec_path));
+            let status = match position.map(|index| cln_plugins.remove(index)) {
+                Some(cln_plugin) => PluginStatus {
+                    name: plugin.name(),
+                    exec_path: plugin.exec_path.clone(),
+                    state: if cln_plugin.active {
+                        PluginState::Running
+                    } else {
+                        PluginState::Stopped
+                    },
+                    dynamic: Some(cln_plugin.dynamic),
+                    pid: find_process_pid(&plugin.exec_path),
+                },
+                None => PluginStatus {
+                    name: plugin.name(),
+                    exec_path: plugin.exec_path.clone(),
+                    // a plugin installed statically is started with
+                    // core lightning, so if it is not running it crashed.
+                    state: if plugin.enabled == Some(true) {
+                        PluginState::Crashed
+                    } else {
+                        PluginState::Stopped
+                    },
+                    dynamic: None,
+                    pid: None,
+                },
+            };
+            plugins.push(status);
+        }
+        // all the remaining plugins are not managed by coffee
+        for cln_plugin in cln_plugins {
+            let name = Path::new(&cln_plugin.name)
+                .file_stem()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(cln_plugin.name.clone());
+            plugins.push(PluginStatus {
+                name,
+                pid: find_process_pid(&cln_plugin.name),
+                exec_path: cln_plugin.name,
+                state: PluginState::Unmanaged,
+                dynamic: Some(cln_plugin.dynamic),
+            });
+        }
+        Ok(CoffeeStatus { plugins })
+    }
+
+    async fn start(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        let operation = self.operation(Operation::new("start", Some(plugin)));
+        let result = self.do_start(plugin).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn stop(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        let operation = self.operation(Operation::new("stop", Some(plugin)));
+        let result = self.do_stop(plugin).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn restart(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        let operation = self.operation(Operation::new("restart", Some(plugin)));
+        let result = self.do_restart(plugin).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn config_get(
+        &mut self,
+        plugin: &str,
+        option: Option<&str>,
+    ) -> Result<CoffeeConfig, CoffeeError> {
+        let plugin = self.installed_plugin(plugin)?;
+        let mut options = self.plugin_options(&plugin);
+        if let Some(option) = option {
+            options.retain(|value| value.name == option);
+            if options.is_empty() {
+                return Err(error!(
+                    kind = InvalidRequest,
+                    "Plugin `{}` does not declare the option `{option}`",
+                    plugin.name()
+                ));
+            }
+        }
+        Ok(CoffeeConfig {
+            plugin: plugin.name(),
+            options,
+            applied: None,
+        })
+    }
+
+    async fn config_set(
+        &mut self,
+        plugin: &str,
+        option: &str,
+        value: &str,
+    ) -> Result<CoffeeConfig, CoffeeError> {
+        let operation = self.operation(
+            Operation::new("config_set", Some(plugin))
+                .arg("option", option)
+                .arg("value", value),
+        );
+        let result = self.do_config_set(plugin, option, value).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn config_unset(
+        &mut self,
+        plugin: &str,
+        option: &str,
+    ) -> Result<CoffeeConfig, CoffeeError> {
+        let operation =
+            self.operation(Operation::new("config_unset", Some(plugin)).arg("option", option));
+        let result = self.do_config_unset(plugin, option).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn upgrade(&mut self, repo: &str, verbose: bool) -> Result<CoffeeUpgrade, CoffeeError> {
+        let operation = self.operation(Operation::new("upgrade", Some(repo)));
+        let result = self.do_upgrade(repo, verbose).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn link(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("link", None).arg("cln_dir", cln_dir));
+        let result = self.do_link(cln_dir).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn unlink(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("unlink", None).arg("cln_dir", cln_dir));
+        let result = self.do_unlink(cln_dir).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn add_remote(&mut self, name: &str, url: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("add_remote", Some(name)).arg("url", url));
+        let result = self.do_add_remote(name, url).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn rm_remote(&mut self, name: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("rm_remote", Some(name)));
+        let result = self.do_rm_remote(name).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn list_remotes(&mut self) -> Result<CoffeeRemote, CoffeeError> {
+        let mut remote_list = Vec::new();
+        for repo in self.repos.values() {
+            let repository = git2::Repository::open(repo.url().path_string.as_str())
+                .map_err(|err| error!("{}", err.message()))?;
+            let (commit, date) = get_repo_info!(repository);
+            remote_list.push(CoffeeListRemote {
+                local_name: repo.name(),
+                url: repo.url().url_string,
+                plugins: repo.list().await?,
+                commit_id: Some(commit),
+                date: Some(date),
+            });
+        }
+        Ok(CoffeeRemote {
+            remotes: Some(remote_list),
+        })
+    }
+
+    async fn get_plugins_in_remote(&self, name: &str) -> Result<CoffeeList, CoffeeError> {
+        log::debug!("Listing plugins for repository: {}", name);
+        let repo = self.repos.get(name).ok_or_else(|| {
+            error!(
+                kind = RepositoryNotFound,
+                "repository with name: {name} not found"
+            )
+        })?;
+        let plugins = repo.list().await?;
+        Ok(CoffeeList { plugins })
+    }
+
+    async fn show(&mut self, plugin: &str) -> Result<CoffeeShow, CoffeeError> {
+        for repo in self.repos.values() {
+            if let Some(plugin) = repo.get_plugin_by_name(plugin) {
+                // FIXME: there are more README file options?
+                let readme_path = format!("{}/README.md", plugin.root_path);
+                let contents = fs::read_to_string(readme_path).await?;
+                return Ok(CoffeeShow {
+                    readme: contents,
+                    options: self.plugin_options(&plugin),
+                });
+            }
+        }
+        let err = error!(
+            kind = PluginNotFound,
+            "{}",
+            &format!("plugin `{plugin}` are not present inside the repositories"),
+        );
+        Err(err)
+    }
+
+    async fn search(&mut self, plugin: &str) -> Result<CoffeeSearch, CoffeeError> {
+        for repo in self.repos.values() {
+            if let Some(plugin) = repo.get_plugin_by_name(plugin) {
+                return Ok(CoffeeSearch {
+                    repository_url: repo.url().url_string,
+                    plugin,
+                });
+            }
+        }
+        let err = CoffeeError::new(404, &format!("unable to locate plugin `{plugin}`"))
+            .with_kind(ErrorKind::PluginNotFound);
+        Err(err)
+    }
+
+    async fn nurse_verify(&self) -> Result<ChainOfResponsibilityStatus, CoffeeError> {
+        self.recovery_strategies.scan(self).await
+    }
+
+    async fn nurse_plan(&self) -> Result<CoffeeNursePlan, CoffeeError> {
+        let status = self.recovery_strategies.scan(self).await?;
+        Ok(CoffeeNursePlan {
+            repairs: status.defects.iter().flat_map(Defect::repairs).collect(),
+        })
     }
-}
 
-#[async_trait]
-impl PluginManager for CoffeeManager {
-    async fn configure(&mut self) -> Result<(), CoffeeError> {
-        log::debug!("plugin configured");
-        Ok(())
+    async fn nurse(&mut self) -> Result<CoffeeNurse, CoffeeError> {
+        self.nurse_apply(&[]).await
     }
 
-    async fn install(
+    async fn nurse_apply(&mut self, ids: &[String]) -> Result<CoffeeNurse, CoffeeError> {
+        let operation = self.operation(Operation::new("nurse", None).arg("ids", ids.join(" ")));
+        let result = self.do_nurse_apply(ids).await;
+        self.journaled(operation, result).await
+    }
+
+    async fn backup(
         &mut self,
-        plugin: &str,
-        verbose: bool,
-        try_dynamic: bool,
-    ) -> Result<(), CoffeeError> {
-        let mut plugins = self.config.plugins.clone();
-        plugins.retain(|p| p.name().eq(plugin));
+        path: &str,
+        with_plugins: bool,
+    ) -> Result<CoffeeBackup, CoffeeError> {
+        let archive = backup::absolute_path(path)?;
+        // make sure that the storage contains the last state
+        self.flush().await?;
+        let staging = backup::staging_dir(&self.config.root_path, "backup").await?;
+        let result = self.write_backup(&staging, &archive, with_plugins).await;
+        let _ = fs::remove_dir_all(&staging).await;
+        result?;
+        log::info!("backup stored in {archive}");
+        Ok(CoffeeBackup {
+            path: archive,
+            network: self.config.network.clone(),
+            plugins: self
+                .config
+                .plugins
+                .iter()
+                .map(|plugin| plugin.name())
+                .collect(),
+            with_plugins,
+        })
+    }
 
-        if !plugins.is_empty() {
-            return Err(error!("Plugin with name `{plugin}` already installed"));
-        }
+    async fn restore(&mut self, path: &str) -> Result<CoffeeRestore, CoffeeError> {
+        let operation = self.operation(Operation::new("restore", None).arg("path", path));
+        let result = self.do_restore(path).await;
+        self.journaled(operation, result).await
+    }
 
-        log::debug!("installing plugin: {plugin}");
-        // keep track if the plugin is successfully installed
-        for repo in self.repos.values() {
-            if let Some(mut plugin) = repo.get_plugin_by_name(plugin) {
-                log::trace!("{:?}", plugin);
+    async fn patch_repository_locally_absent(
+        &mut self,
+        repos: Vec<String>,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        let operation = self.operation(
+            Operation::new("patch_repository_locally_absent", None)
+                .arg("repositories", repos.join(" ")),
+        );
+        let result = self.do_patch_repository_locally_absent(repos).await;
+        self.journaled(operation, result).await
+    }
 
-                if try_dynamic && plugin.important() {
-                    return Err(error!(
-                        "plugin is important, can't be dynamically installed"
-                    ));
-                }
+    async fn tip(&mut self, plugin: &str, amount_msat: u64) -> Result<CoffeeTip, CoffeeError> {
+        let operation =
+            self.operation(Operation::new("tip", Some(plugin)).arg("amount_msat", amount_msat));
+        let result = self.do_tip(plugin, amount_msat).await;
+        self.journaled(operation, result).await
+    }
 
-                // old_root_path is the path where the plugin is cloned and currently stored
-                // eg. ~/.coffee/repositories/<repo_name>/<plugin_name>
-                let old_root_path = plugin.root_path.clone();
-                // new_root_path is the path where the plugin will be installed specific to the network
-                // eg. ~/.coffee/<network>/plugins/<plugin_name>
-                let new_root_path = format!(
-                    "{}/{}/plugins/{}",
-                    self.config.root_path,
-                    self.config.network,
-                    plugin.name()
-                );
+    async fn cache_list(&mut self) -> Result<CoffeeCache, CoffeeError> {
+        let in_use = self.cache_keys_in_use().await?;
+        let entries = self.cache.list(&in_use).await?;
+        Ok(CoffeeCache { entries })
+    }
 
-                log::debug!(
-                    "Start! copying directory from {} inside the new one {}",
-                    old_root_path,
-                    new_root_path
-                );
-                let script = format!("cp -r {old_root_path} {new_root_path}");
-                sh!(self.config.root_path.clone(), script, verbose);
-                log::debug!(
-                    "Done! copying directory from {} inside the new one {}",
-                    old_root_path,
-                    new_root_path
-                );
-                let old_exec_path = plugin.exec_path.clone();
+    async fn cache_prune(&mut self, all: bool) -> Result<CoffeeCache, CoffeeError> {
+        let operation = self.operation(Operation::new("cache_prune", None).arg("all", all));
+        let result = self.do_cache_prune(all).await;
+        self.journaled(operation, result).await
+    }
 
-                let plugin_conf_key = if plugin.important() {
-                    "important-plugin"
-                } else {
-                    "plugin"
-                };
+    async fn disable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("disable", Some(plugin)));
+        let result = self.do_disable(plugin).await;
+        self.journaled(operation, result).await
+    }
 
-                match old_exec_path.strip_prefix(&old_root_path) {
-                    Some(relative_path) => {
-                        let new_exec_path = format!("{}{}", new_root_path, relative_path);
-                        plugin.root_path = new_root_path;
-                        plugin.exec_path = new_exec_path;
-
-                        log::debug!("plugin: {:?}", plugin);
-                        let path = plugin.configure(verbose).await?;
-                        log::debug!("runnable plugin path {path}");
-                        if !try_dynamic {
-                            // mark the plugin enabled
-                            plugin.enabled = Some(true);
-                            self.config.plugins.push(plugin);
-                            log::debug!("path coffee conf: {}", self.coffee_cln_config.path);
-                            self.coffee_cln_config
-                                .add_conf(plugin_conf_key, &path.to_owned())
-                                .map_err(|err| error!("{}", err.cause))?;
-                            log::debug!("coffee conf updated: {}", self.coffee_cln_config);
-                            self.flush().await?;
-                            self.update_conf().await?;
-                        } else {
-                            self.config.plugins.push(plugin);
-                            self.flush().await?;
-                            self.start_plugin(&path).await?;
-                        }
-                        return Ok(());
-                    }
-                    None => return Err(error!("exec path not found")),
-                };
-            }
-        }
-        Err(error!(
-            "plugin `{plugin}` are not present inside the repositories"
-        ))
+    async fn enable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
+        let operation = self.operation(Operation::new("enable", Some(plugin)));
+        let result = self.do_enable(plugin).await;
+        self.journaled(operation, result).await
     }
 
-    async fn remove(&mut self, plugin: &str) -> Result<CoffeeRemove, CoffeeError> {
+    async fn history(&self, filter: &HistoryFilter) -> Result<CoffeeHistory, CoffeeError> {
+        history::query(&self.storage, &self.config.network, filter).await
+    }
+}
+
+// FIXME: we need to move on but this is not safe and with the coffee
+// implementation is not true!
+unsafe impl Send for CoffeeManager {}
+unsafe impl Sync for CoffeeManager {}
+unsafe impl Send for CoffeeStorageInfo {}
+unsafe impl Sync for CoffeeStorageInfo {}
+
+/// The operations of the plugin manager, the `PluginManager`
+/// implementation stores each of them inside the history.
+impl CoffeeManager {
+    async fn do_remove(&mut self, plugin: &str) -> Result<CoffeeRemove, CoffeeError> {
         log::debug!("removing plugin: {plugin}");
         let plugins = &mut self.config.plugins;
         if let Some(index) = plugins.iter().position(|x| x.name() == plugin) {
@@ -367,12 +1875,21 @@ impl PluginManager for CoffeeManager {
             let exec_path = plugin.exec_path.clone();
             let root_path = plugin.root_path.clone();
             let cloned_repositories_path = format!("{}/repositories", self.config.root_path,);
-            // make sure that we are not deleting the cloned repositories
-            if !root_path.contains(&cloned_repositories_path) {
+            // make sure that we are not deleting the cloned repositories,
+            // and the directory can be already missing if the plugin is broken.
+            if !root_path.contains(&cloned_repositories_path) && Path::new(&root_path).exists() {
                 fs::remove_dir_all(root_path).await?;
             }
             log::debug!("runnable plugin path: {exec_path}");
             plugins.remove(index);
+            // clean up the options of the plugin
+            for option in plugin.options().keys() {
+                if self.option_value(option).is_some() {
+                    self.coffee_cln_config
+                        .rm_conf(option, None)
+                        .map_err(|err| error!("{}", &err.cause))?;
+                }
+            }
             log::debug!("coffee cln config: {}", self.coffee_cln_config);
             let plugin_conf_key = if plugin.important() {
                 "important-plugin"
@@ -396,32 +1913,167 @@ impl PluginManager for CoffeeManager {
             self.update_conf().await?;
             Ok(CoffeeRemove { plugin })
         } else {
-            return Err(error!("plugin `{plugin}` is already not installed"));
+            return Err(error!(
+                kind = PluginNotFound,
+                "plugin `{plugin}` is already not installed"
+            ));
         }
     }
 
-    async fn list(&mut self) -> Result<CoffeeList, CoffeeError> {
-        Ok(CoffeeList {
-            plugins: self.config.plugins.clone(),
-        })
+    async fn do_start(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        log::debug!("starting plugin: {plugin}");
+        let plugin = self.runtime_plugin(plugin)?;
+        if let Some(true) = self
+            .cln_plugin(&plugin.exec_path)
+            .await?
+            .map(|cln_plugin| cln_plugin.active)
+        {
+            return Err(error!(
+                kind = Conflict,
+                "Plugin `{}` is already running",
+                plugin.name()
+            ));
+        }
+        let options = self.configured_options(&plugin);
+        let start = async {
+            self.start_plugin(&plugin.exec_path, &options).await?;
+            self.wait_plugin_state(&plugin, true).await
+        };
+        self.progress
+            .phase(&plugin.name(), Phase::Starting, start)
+            .await
     }
 
-    async fn upgrade(&mut self, repo: &str, verbose: bool) -> Result<CoffeeUpgrade, CoffeeError> {
+    async fn do_stop(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        log::debug!("stopping plugin: {plugin}");
+        let plugin = self.runtime_plugin(plugin)?;
+        let active = self
+            .cln_plugin(&plugin.exec_path)
+            .await?
+            .map(|cln_plugin| cln_plugin.active)
+            .unwrap_or(false);
+        if !active {
+            return Err(error!(
+                kind = Conflict,
+                "Plugin `{}` is not running",
+                plugin.name()
+            ));
+        }
+        self.stop_plugin(&plugin.exec_path).await?;
+        self.wait_plugin_state(&plugin, false).await
+    }
+
+    async fn do_restart(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError> {
+        log::debug!("restarting plugin: {plugin}");
+        let plugin = self.runtime_plugin(plugin)?;
+        self.restart_plugin(&plugin).await
+    }
+
+    async fn do_config_set(
+        &mut self,
+        plugin: &str,
+        option: &str,
+        value: &str,
+    ) -> Result<CoffeeConfig, CoffeeError> {
+        log::debug!("setting option `{option}` of plugin {plugin} to `{value}`");
+        check_option_name(option)?;
+        let plugin = self.installed_plugin(plugin)?;
+        let declared = plugin.options().remove(option).ok_or(error!(
+            kind = InvalidRequest,
+            "Plugin `{}` does not declare the option `{option}`",
+            plugin.name()
+        ))?;
+        self.check_option_owner(option)?;
+        declared.kind.validate(value)?;
+        if !self.is_configured(&plugin) {
+            return Err(error!(
+                kind = InvalidRequest,
+                "Plugin `{}` is not loaded by core lightning at startup, install it without `--dynamic` to configure it",
+                plugin.name()
+            ));
+        }
+        if self.option_value(option).is_some() {
+            self.coffee_cln_config
+                .rm_conf(option, None)
+                .map_err(|err| error!("{}", err.cause))?;
+        }
+        self.coffee_cln_config
+            .add_conf(option, value)
+            .map_err(|err| error!("{}", err.cause))?;
+        self.update_conf().await?;
+        let mut config = self.config_get(&plugin.name(), Some(option)).await?;
+        config.applied = Some(self.apply_option(&plugin, option, &declared).await);
+        Ok(config)
+    }
+
+    async fn do_config_unset(
+        &mut self,
+        plugin: &str,
+        option: &str,
+    ) -> Result<CoffeeConfig, CoffeeError> {
+        log::debug!("unsetting option `{option}` of plugin {plugin}");
+        check_option_name(option)?;
+        let plugin = self.installed_plugin(plugin)?;
+        let declared = plugin.options().remove(option).ok_or(error!(
+            kind = InvalidRequest,
+            "Plugin `{}` does not declare the option `{option}`",
+            plugin.name()
+        ))?;
+        self.check_option_owner(option)?;
+        if self.option_value(option).is_none() {
+            return Err(error!(kind = NotFound, "Option `{option}` is not set"));
+        }
+        self.coffee_cln_config
+            .rm_conf(option, None)
+            .map_err(|err| error!("{}", err.cause))?;
+        self.update_conf().await?;
+        let mut config = self.config_get(&plugin.name(), Some(option)).await?;
+        config.applied = Some(self.apply_option(&plugin, option, &declared).await);
+        Ok(config)
+    }
+
+    async fn do_upgrade(
+        &mut self,
+        repo: &str,
+        verbose: bool,
+    ) -> Result<CoffeeUpgrade, CoffeeError> {
         // TODO: upgrade should now be able to upgrade a single plugin
         // without affecting other plugins installed from the same repo
-        let repository = self
-            .repos
-            .get_mut(repo)
-            .ok_or_else(|| error!("Repository with name: `{}` not found", repo))?;
+        let repository = self.repos.get_mut(repo).ok_or_else(|| {
+            error!(
+                kind = RepositoryNotFound,
+                "Repository with name: `{}` not found", repo
+            )
+        })?;
 
-        let status = repository.upgrade(&self.config.plugins, verbose).await?;
+        let mut status = self
+            .progress
+            .phase(
+                repo,
+                Phase::Fetching,
+                repository.upgrade(&self.config.plugins, verbose),
+            )
+            .await?;
 
         // if status is not up to date, we need to update the plugins as well
         match status.status {
             UpgradeStatus::Updated(_, _) => {
-                for plugins in status.plugins_effected.iter() {
-                    self.remove(plugins).await?;
-                    self.install(plugins, verbose, false).await?;
+                // the history contains only the upgrade, with the commits
+                // before and after it, and not the reinstall of each plugin.
+                let mut plugins = vec![];
+                for plugin in status.plugins_effected.iter() {
+                    match self.do_remove(plugin).await {
+                        Ok(_) => plugins.push(plugin.to_owned()),
+                        Err(err) => status
+                            .plugins_status
+                            .push(PluginInstallStatus::new(plugin, Err(err))),
+                    }
+                }
+                let results = self.install_plugins(&plugins, verbose, false).await;
+                for (plugin, result) in results {
+                    status
+                        .plugins_status
+                        .push(PluginInstallStatus::new(&plugin, result));
                 }
             }
             _ => {}
@@ -431,39 +2083,47 @@ impl PluginManager for CoffeeManager {
         Ok(status)
     }
 
-    async fn link(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+    async fn do_link(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
         self.link_with_cln(cln_dir).await?;
         log::info!("cln configured");
         self.flush().await?;
         Ok(())
     }
 
-    async fn unlink(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
+    async fn do_unlink(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
         self.unlink_from_cln(cln_dir).await?;
         log::info!("cln configuration removed");
         self.flush().await?;
         Ok(())
     }
 
-    async fn add_remote(&mut self, name: &str, url: &str) -> Result<(), CoffeeError> {
+    async fn do_add_remote(&mut self, name: &str, url: &str) -> Result<(), CoffeeError> {
         // FIXME: we should allow some error here like
         // for the add remote command the no found error for the `repository`
         // directory is fine.
 
         if self.repos.contains_key(name) {
-            return Err(error!("repository with name: {name} already exists"));
+            return Err(error!(
+                kind = AlreadyExists,
+                "repository with name: {name} already exists"
+            ));
         }
         let url = URL::new(&self.config.root_path, url, name);
         log::debug!("remote adding: {} {}", name, &url.url_string);
         let mut repo = Github::new(name, &url);
-        repo.init().await?;
+        self.progress
+            .phase(name, Phase::Cloning, repo.clone_repository())
+            .await?;
+        self.progress
+            .phase(name, Phase::Indexing, repo.index_repository())
+            .await?;
         self.repos.insert(repo.name(), Box::new(repo));
         log::debug!("remote added: {} {}", name, &url.url_string);
         self.flush().await?;
         Ok(())
     }
 
-    async fn rm_remote(&mut self, name: &str) -> Result<(), CoffeeError> {
+    async fn do_rm_remote(&mut self, name: &str) -> Result<(), CoffeeError> {
         log::debug!("remote removing: {}", name);
         match self.repos.get(name) {
             Some(repo) => {
@@ -476,7 +2136,7 @@ impl PluginManager for CoffeeManager {
                         .position(|elem| elem.name() == *plugin.name())
                     {
                         let plugin_name = &plugins[ind].name().clone();
-                        match self.remove(plugin_name).await {
+                        match self.do_remove(plugin_name).await {
                             Ok(_) => {}
                             Err(err) => return Err(err),
                         }
@@ -488,82 +2148,86 @@ impl PluginManager for CoffeeManager {
                 self.flush().await?;
             }
             None => {
-                return Err(error!("repository with name: {name} not found"));
+                return Err(error!(
+                    kind = RepositoryNotFound,
+                    "repository with name: {name} not found"
+                ));
             }
         };
         Ok(())
     }
 
-    async fn list_remotes(&mut self) -> Result<CoffeeRemote, CoffeeError> {
-        let mut remote_list = Vec::new();
-        for repo in self.repos.values() {
-            let repository = git2::Repository::open(repo.url().path_string.as_str())
-                .map_err(|err| error!("{}", err.message()))?;
-            let (commit, date) = get_repo_info!(repository);
-            remote_list.push(CoffeeListRemote {
-                local_name: repo.name(),
-                url: repo.url().url_string,
-                plugins: repo.list().await?,
-                commit_id: Some(commit),
-                date: Some(date),
-            });
-        }
-        Ok(CoffeeRemote {
-            remotes: Some(remote_list),
-        })
-    }
-
-    async fn get_plugins_in_remote(&self, name: &str) -> Result<CoffeeList, CoffeeError> {
-        log::debug!("Listing plugins for repository: {}", name);
-        let repo = self
-            .repos
-            .get(name)
-            .ok_or_else(|| error!("repository with name: {name} not found"))?;
-        let plugins = repo.list().await?;
-        Ok(CoffeeList { plugins })
-    }
-
-    async fn show(&mut self, plugin: &str) -> Result<CoffeeShow, CoffeeError> {
-        for repo in self.repos.values() {
-            if let Some(plugin) = repo.get_plugin_by_name(plugin) {
-                // FIXME: there are more README file options?
-                let readme_path = format!("{}/README.md", plugin.root_path);
-                let contents = fs::read_to_string(readme_path).await?;
-                return Ok(CoffeeShow { readme: contents });
-            }
-        }
-        let err = error!(
-            "{}",
-            &format!("plugin `{plugin}` are not present inside the repositories"),
-        );
-        Err(err)
-    }
-
-    async fn search(&mut self, plugin: &str) -> Result<CoffeeSearch, CoffeeError> {
-        for repo in self.repos.values() {
-            if let Some(plugin) = repo.get_plugin_by_name(plugin) {
-                return Ok(CoffeeSearch {
-                    repository_url: repo.url().url_string,
-                    plugin,
-                });
+    async fn do_nurse_apply(&mut self, ids: &[String]) -> Result<CoffeeNurse, CoffeeError> {
+        let mut defects = self.recovery_strategies.scan_handlers(self).await?;
+        if !ids.is_empty() {
+            if let Some(id) = ids
+                .iter()
+                .find(|id| !defects.iter().any(|(_, defect)| defect.matches(id)))
+            {
+                return Err(error!(
+                    kind = NotFound,
+                    "defect `{id}` not found, run `coffee nurse --plan` to see the defects"
+                ));
             }
+            // a repair can be applied to some of the elements
+            // affected by the defect, e.g. a single plugin.
+            defects = defects
+                .into_iter()
+                .filter_map(|(handler, defect)| defect.select(ids).map(|defect| (handler, defect)))
+                .collect();
         }
-        let err = CoffeeError::new(404, &format!("unable to locate plugin `{plugin}`"));
-        Err(err)
-    }
-
-    async fn nurse_verify(&self) -> Result<ChainOfResponsibilityStatus, CoffeeError> {
-        self.recovery_strategies.scan(self).await
-    }
-
-    async fn nurse(&mut self) -> Result<CoffeeNurse, CoffeeError> {
-        let status = self.recovery_strategies.scan(self).await?;
         let mut nurse_actions: Vec<NurseStatus> = vec![];
-        for defect in status.defects.iter() {
+        for (handler, defect) in defects.iter() {
             log::debug!("defect: {:?}", defect);
             match defect {
                 Defect::RepositoryLocallyAbsent(repos) => {
-                    let mut actions = self.patch_repository_locally_absent(repos.to_vec()).await?;
+                    let mut actions = self
+                        .do_patch_repository_locally_absent(repos.to_vec())
+                        .await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::PluginConfEntryUnknown(paths) => {
+                    let mut actions = self.patch_plugin_conf_entry_unknown(paths.to_vec()).await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::PluginConfEntryMissing(plugins) => {
+                    let mut actions = self
+                        .patch_plugin_conf_entry_missing(plugins.to_vec())
+                        .await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::DisablePluginEntryMismatch(paths) => {
+                    let mut actions = self
+                        .patch_disable_plugin_entry_mismatch(paths.to_vec())
+                        .await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::DuplicatedConfEntries(entries) => {
+                    let mut actions = self.patch_duplicated_conf_entries(entries.to_vec()).await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::PluginDirectoryBroken(plugins) => {
+                    let mut actions = self.patch_plugin_directory_broken(plugins.to_vec()).await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::ClnConfigUnlinked(paths) => {
+                    let mut actions = self.patch_cln_config_unlinked(paths.to_vec()).await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::ClnPathMissing(paths) => {
+                    let mut actions = self.patch_cln_path_missing(paths.to_vec()).await?;
+                    nurse_actions.append(&mut actions);
+                }
+                Defect::ClnRpcSocketMissing(paths) => {
+                    // nothing to patch, core lightning needs to be started.
+                    log::info!("core lightning is not running, socket {:?} missing", paths);
+                }
+                Defect::ConflictingPluginOptions(options) => {
+                    // nothing to patch, one of the plugins needs to be removed.
+                    log::info!("options declared by more than one plugin: {:?}", options);
+                }
+                Defect::External(_) => {
+                    let mut actions = handler.clone().patch(self, defect).await?;
                     nurse_actions.append(&mut actions);
                 }
             }
@@ -575,7 +2239,42 @@ impl PluginManager for CoffeeManager {
         Ok(nurse)
     }
 
-    async fn patch_repository_locally_absent(
+    async fn do_restore(&mut self, path: &str) -> Result<CoffeeRestore, CoffeeError> {
+        let archive = backup::absolute_path(path)?;
+        if !Path::new(&archive).exists() {
+            return Err(error!(kind = NotFound, "backup `{path}` not found"));
+        }
+        let staging = backup::staging_dir(&self.config.root_path, "restore").await?;
+        let result = self.read_backup(&archive, &staging).await;
+        let _ = fs::remove_dir_all(&staging).await;
+        result?;
+
+        // reload the restored state
+        self.repos.clear();
+        self.load_storage().await;
+        self.coffee_cln_config = CLNConf::new(self.config.config_path.clone(), true);
+        if let Err(err) = self.coffee_cln_config.parse() {
+            log::error!("{}", err.cause);
+        }
+        if let Err(err) = self.load_cln_conf().await {
+            log::warn!("{err}");
+        }
+        // the nurse clones the repositories that are missing at the
+        // stored commit, and rebuilds the plugins that are missing.
+        let nurse = self.nurse().await?;
+        Ok(CoffeeRestore {
+            network: self.config.network.clone(),
+            plugins: self
+                .config
+                .plugins
+                .iter()
+                .map(|plugin| plugin.name())
+                .collect(),
+            nurse,
+        })
+    }
+
+    async fn do_patch_repository_locally_absent(
         &mut self,
         repos: Vec<String>,
     ) -> Result<Vec<NurseStatus>, CoffeeError> {
@@ -589,12 +2288,18 @@ impl PluginManager for CoffeeManager {
         //   we remove the repository from the coffee configuration.
         for repo_name in repos.iter() {
             // Get the repository from the name
-            let repo = self
-                .repos
-                .get_mut(repo_name)
-                .ok_or_else(|| error!("repository with name: {repo_name} not found"))?;
+            let repo = self.repos.get_mut(repo_name).ok_or_else(|| {
+                error!(
+                    kind = RepositoryNotFound,
+                    "repository with name: {repo_name} not found"
+                )
+            })?;
 
-            match repo.recover().await {
+            match self
+                .progress
+                .phase(repo_name, Phase::Cloning, repo.recover())
+                .await
+            {
                 Ok(_) => {
                     log::info!("repository {} recovered", repo_name.clone());
                     nurse_actions.push(NurseStatus::RepositoryLocallyRestored(vec![
@@ -626,7 +2331,7 @@ impl PluginManager for CoffeeManager {
         Ok(nurse_actions)
     }
 
-    async fn tip(&mut self, plugin: &str, amount_msat: u64) -> Result<CoffeeTip, CoffeeError> {
+    async fn do_tip(&mut self, plugin: &str, amount_msat: u64) -> Result<CoffeeTip, CoffeeError> {
         let plugins = self
             .config
             .plugins
@@ -634,11 +2339,15 @@ impl PluginManager for CoffeeManager {
             .filter(|repo_plugin| plugin == repo_plugin.name())
             .collect::<Vec<_>>();
         let plugin = plugins.first().ok_or(error!(
+            kind = PluginNotFound,
             "No plugin with name `{plugin}` found in the plugins installed"
         ))?;
 
         let Some(tipping) = plugin.tipping_info() else {
-            return Err(error!("Plugin `{plugin}` has no tipping information"));
+            return Err(error!(
+                kind = NotFound,
+                "Plugin `{plugin}` has no tipping information"
+            ));
         };
         // FIXME write a tip_plugin method as method
         #[derive(Debug, Deserialize)]
@@ -674,7 +2383,20 @@ impl PluginManager for CoffeeManager {
         Ok(tip)
     }
 
-    async fn disable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
+    async fn do_cache_prune(&mut self, all: bool) -> Result<CoffeeCache, CoffeeError> {
+        let in_use = self.cache_keys_in_use().await?;
+        let mut removed = vec![];
+        for entry in self.cache.list(&in_use).await? {
+            if all || !entry.in_use {
+                log::debug!("removing cache entry {} ({})", entry.key, entry.plugin);
+                self.cache.remove(&entry.key).await?;
+                removed.push(entry);
+            }
+        }
+        Ok(CoffeeCache { entries: removed })
+    }
+
+    async fn do_disable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
         log::debug!("disabling plugin: {plugin}");
 
         let plugin = self
@@ -683,11 +2405,15 @@ impl PluginManager for CoffeeManager {
             .iter_mut()
             .find(|repo_plugin| plugin == repo_plugin.name())
             .ok_or(error!(
+                kind = PluginNotFound,
                 "No plugin with name `{plugin}` found in the plugins installed"
             ))?;
         log::debug!("plugin: {:?}", plugin);
         if plugin.enabled == Some(false) {
-            return Err(error!("Plugin `{plugin}` is already disabled"));
+            return Err(error!(
+                kind = Conflict,
+                "Plugin `{plugin}` is already disabled"
+            ));
         }
         self.coffee_cln_config
             .add_conf("disable-plugin", &plugin.exec_path)
@@ -704,7 +2430,7 @@ impl PluginManager for CoffeeManager {
         Ok(())
     }
 
-    async fn enable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
+    async fn do_enable(&mut self, plugin: &str) -> Result<(), CoffeeError> {
         log::debug!("enabling plugin: {plugin}");
 
         let plugin = self
@@ -713,11 +2439,13 @@ impl PluginManager for CoffeeManager {
             .iter_mut()
             .find(|repo_plugin| plugin == repo_plugin.name())
             .ok_or(error!(
+                kind = PluginNotFound,
                 "No plugin with name `{plugin}` found in the plugins installed"
             ))?;
         log::debug!("plugin: {:?}", plugin);
         if plugin.enabled.is_none() || plugin.enabled == Some(true) {
             return Err(error!(
+                kind = Conflict,
                 "Plugin `{plugin}` is already enabled or enabled by default"
             ));
         }
@@ -736,10 +2464,3 @@ impl PluginManager for CoffeeManager {
         Ok(())
     }
 }
-
-// FIXME: we need to move on but this is not safe and with the coffee
-// implementation is not true!
-unsafe impl Send for CoffeeManager {}
-unsafe impl Sync for CoffeeManager {}
-unsafe impl Send for CoffeeStorageInfo {}
-unsafe impl Sync for CoffeeStorageInfo {}
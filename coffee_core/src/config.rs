@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 use std::env;
 
 use crate::CoffeeOperation;
+use coffee_lib::build::BuildLimits;
 use coffee_lib::utils::check_dir_or_make_if_missing;
 use coffee_lib::{errors::CoffeeError, plugin::Plugin};
 
@@ -33,19 +34,22 @@ pub struct CoffeeConf {
     /// user wants to skip the verification
     /// of nurse.
     pub skip_verify: bool,
+    /// Limits applied to the plugins install scripts.
+    ///
+    /// This is a command line option, so it is not
+    /// made persistent on disk.
+    #[serde(skip)]
+    pub build_limits: BuildLimits,
+    /// Number of plugins that can be built concurrently,
+    /// by default the number of CPUs available.
+    #[serde(skip)]
+    pub jobs: Option<usize>,
 }
 
 impl CoffeeConf {
     /// Create a new instance of the coffee configuration from the args.
     pub async fn new(conf: &dyn CoffeeArgs) -> Result<Self, CoffeeError> {
-        #[allow(deprecated)]
-        let mut def_path = env::home_dir().unwrap().to_str().unwrap().to_string();
-        if let Some(data_dir) = &conf.data_dir() {
-            def_path = data_dir.to_owned();
-        }
-
-        def_path = def_path.strip_suffix('/').unwrap_or(&def_path).to_string();
-        def_path += "/.coffee";
+        let def_path = Self::root_path(conf.data_dir());
         check_dir_or_make_if_missing(def_path.to_string()).await?;
         info!("creating coffee home at {def_path}");
 
@@ -57,6 +61,8 @@ impl CoffeeConf {
             cln_config_path: None,
             cln_root: None,
             skip_verify: false,
+            build_limits: BuildLimits::default(),
+            jobs: None,
         };
 
         // check the command line arguments and bind them
@@ -74,6 +80,20 @@ impl CoffeeConf {
         Ok(coffee)
     }
 
+    /// Return the coffee home inside the data directory, by
+    /// default the home directory of the user.
+    pub fn root_path(data_dir: Option<String>) -> String {
+        #[allow(deprecated)]
+        let mut def_path = env::home_dir().unwrap().to_str().unwrap().to_string();
+        if let Some(data_dir) = data_dir {
+            def_path = data_dir;
+        }
+
+        def_path = def_path.strip_suffix('/').unwrap_or(&def_path).to_string();
+        def_path += "/.coffee";
+        def_path
+    }
+
     async fn load_from_file(&mut self) -> Result<(), CoffeeError> {
         Ok(())
     }
@@ -88,12 +108,19 @@ impl CoffeeConf {
             self.config_path = config.to_owned();
         }
 
+        self.build_limits = conf.build_limits();
+        self.jobs = conf.jobs();
+
         // If the command is nurse we skip the verification
         // because nurse is the command that needs
         // to solve the configuration problems.
         if !conf.skip_verify() {
             match conf.command() {
-                CoffeeOperation::Nurse(_) => {
+                // the restore replaces the state, and then runs the nurse,
+                // while the history is useful also when the state is broken.
+                CoffeeOperation::Nurse(_)
+                | CoffeeOperation::Restore(_)
+                | CoffeeOperation::History(_) => {
                     self.skip_verify = true;
                 }
                 _ => {
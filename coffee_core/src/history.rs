@@ -0,0 +1,198 @@
+//! Journal of the operations that changed the coffee state.
+//!
+//! Every mutation of the plugin manager is stored inside the
+//! storage with the caller that asked for it, so it is possible
+//! to know who changed what, and when.
+use std::collections::BTreeMap;
+
+use chrono::TimeZone;
+use serde::{Deserialize, Serialize};
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::types::request::HistoryFilter;
+use coffee_lib::types::response::{Caller, CoffeeHistory, HistoryEntry, HistoryResult};
+use coffee_storage::nosql_db::NoSQlStorage;
+use coffee_storage::storage::StorageManager;
+
+use crate::backup;
+
+/// Maximum number of entries kept inside the journal,
+/// the oldest entries are dropped first.
+pub const HISTORY_LIMIT: usize = 10_000;
+
+/// Storage key of the whole journal of the network, used by the
+/// backups and by the older versions of coffee.
+pub fn history_key(network: &str) -> String {
+    format!("{network}.history")
+}
+
+/// Operation that is going to be stored inside the journal.
+pub struct Operation {
+    pub name: String,
+    pub target: Option<String>,
+    pub arguments: BTreeMap<String, String>,
+    pub before: Option<String>,
+}
+
+impl Operation {
+    pub fn new(name: &str, target: Option<&str>) -> Self {
+        Operation {
+            name: name.to_owned(),
+            target: target.map(|target| target.to_owned()),
+            arguments: BTreeMap::new(),
+            before: None,
+        }
+    }
+
+    pub fn arg<T: ToString>(mut self, key: &str, value: T) -> Self {
+        self.arguments.insert(key.to_owned(), value.to_string());
+        self
+    }
+}
+
+/// Position of the journal inside the storage, the entries
+/// with an id inside `first..next` are stored in their own key.
+#[derive(Serialize, Deserialize)]
+struct HistoryIndex {
+    first: u64,
+    next: u64,
+}
+
+impl Default for HistoryIndex {
+    fn default() -> Self {
+        HistoryIndex { first: 1, next: 1 }
+    }
+}
+
+fn index_key(network: &str) -> String {
+    format!("{network}.history.index")
+}
+
+/// Storage key of the entry, the keys are reused once the
+/// journal is full so the oldest entries are overwritten.
+fn entry_key(network: &str, id: u64) -> String {
+    format!("{network}.history.{}", id % HISTORY_LIMIT as u64)
+}
+
+/// Store the entries inside their own key, the older
+/// versions (and the backups) keep the whole journal
+/// under the `history_key`.
+async fn import(
+    storage: &NoSQlStorage,
+    network: &str,
+    entries: Vec<HistoryEntry>,
+) -> Result<HistoryIndex, CoffeeError> {
+    let skip = entries.len().saturating_sub(HISTORY_LIMIT);
+    let mut index = HistoryIndex::default();
+    for entry in entries.into_iter().skip(skip) {
+        if index.first == index.next {
+            index.first = entry.id;
+        }
+        index.next = entry.id + 1;
+        storage.store(&entry_key(network, entry.id), &entry).await?;
+    }
+    storage.store(&index_key(network), &index).await?;
+    Ok(index)
+}
+
+async fn load_index(storage: &NoSQlStorage, network: &str) -> Result<HistoryIndex, CoffeeError> {
+    let legacy_key = history_key(network);
+    if storage.contains(&legacy_key) {
+        let entries: Vec<HistoryEntry> = storage.load(&legacy_key).await?;
+        if !entries.is_empty() {
+            let index = import(storage, network, entries).await?;
+            storage
+                .store(&legacy_key, &Vec::<HistoryEntry>::new())
+                .await?;
+            return Ok(index);
+        }
+    }
+    let key = index_key(network);
+    if !storage.contains(&key) {
+        return Ok(HistoryIndex::default());
+    }
+    storage.load(&key).await
+}
+
+/// Return the entries of the journal of the network, oldest first.
+pub async fn entries(
+    storage: &NoSQlStorage,
+    network: &str,
+) -> Result<Vec<HistoryEntry>, CoffeeError> {
+    let index = load_index(storage, network).await?;
+    let first = index
+        .first
+        .max(index.next.saturating_sub(HISTORY_LIMIT as u64));
+    let mut entries = vec![];
+    for id in first..index.next {
+        let key = entry_key(network, id);
+        if !storage.contains(&key) {
+            continue;
+        }
+        let entry: HistoryEntry = storage.load(&key).await?;
+        // a slot can contain an older entry when the store
+        // of the index failed after the store of the entry.
+        if entry.id == id {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Append the operation to the journal of the network.
+pub async fn record(
+    storage: &NoSQlStorage,
+    network: &str,
+    operation: Operation,
+    after: Option<String>,
+    result: HistoryResult,
+    caller: &Caller,
+) -> Result<(), CoffeeError> {
+    let mut index = load_index(storage, network).await?;
+    let timestamp = backup::timestamp();
+    let date = chrono::Utc
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|date| date.format("%d/%m/%Y %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let id = index.next;
+    let entry = HistoryEntry {
+        id,
+        timestamp,
+        date,
+        operation: operation.name,
+        target: operation.target,
+        arguments: operation.arguments,
+        before: operation.before,
+        after,
+        result,
+        caller: caller.to_owned(),
+    };
+    storage.store(&entry_key(network, id), &entry).await?;
+    index.next = id + 1;
+    index.first = index
+        .first
+        .max(index.next.saturating_sub(HISTORY_LIMIT as u64));
+    storage.store(&index_key(network), &index).await
+}
+
+/// Return the entries of the journal that match the filter, newest first.
+pub async fn query(
+    storage: &NoSQlStorage,
+    network: &str,
+    filter: &HistoryFilter,
+) -> Result<CoffeeHistory, CoffeeError> {
+    let entries: Vec<HistoryEntry> = entries(storage, network)
+        .await?
+        .into_iter()
+        .rev()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let total = entries.len();
+    let entries = entries
+        .into_iter()
+        .skip(filter.offset)
+        .take(filter.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(CoffeeHistory { entries, total })
+}
@@ -1,20 +1,37 @@
+pub mod backup;
+pub mod cache;
 pub mod coffee;
 pub mod config;
+pub mod history;
+pub mod lock;
+pub mod progress;
+pub mod token;
 
-mod nurse;
+pub mod nurse;
 
 pub use coffee_lib as lib;
 
+use coffee_lib::build::BuildLimits;
+use coffee_lib::types::request::HistoryFilter;
+
 #[derive(Clone, Debug)]
 pub enum CoffeeOperation {
     /// Link coffee to the lightning configuration file
     Link(String),
     /// Unlink coffee from the lightning configuration file
     Unlink(String),
-    /// Install(plugin name, verbose run, dynamic installation)
-    Install(String, bool, bool),
+    /// Install(plugins names, verbose run, dynamic installation)
+    Install(Vec<String>, bool, bool),
     /// List
     List,
+    /// Status of the plugins inside core lightning
+    Status,
+    /// Start(plugin name)
+    Start(String),
+    /// Stop(plugin name)
+    Stop(String),
+    /// Restart(plugin name)
+    Restart(String),
     // Upgrade(name of the repository, verbose run)
     Upgrade(String, bool),
     Remove(String),
@@ -23,7 +40,7 @@ pub enum CoffeeOperation {
     Show(String),
     /// Search(plugin name)
     Search(String),
-    Nurse(bool),
+    Nurse(NurseAction),
     /// Tip operation
     ///
     /// (plugin_name, amount_msat)
@@ -32,6 +49,43 @@ pub enum CoffeeOperation {
     Disable(String),
     /// Enable a plugin(plugin name)
     Enable(String),
+    /// Manage the build cache
+    Cache(CacheAction),
+    /// Manage the plugins options
+    Config(ConfigAction),
+    /// Backup(archive path, include the built plugins)
+    Backup(String, bool),
+    /// Restore(archive path)
+    Restore(String),
+    /// Show the operations that changed the coffee state
+    History(HistoryFilter),
+}
+
+#[derive(Clone, Debug)]
+pub enum ConfigAction {
+    /// Get(plugin name, option name)
+    Get(String, Option<String>),
+    /// Set(plugin name, option name, value)
+    Set(String, String, String),
+    /// Unset(plugin name, option name)
+    Unset(String, String),
+}
+
+#[derive(Clone, Debug)]
+pub enum NurseAction {
+    /// Apply the repairs, all of them when the list of ids is empty
+    Apply(Vec<String>),
+    /// Verify the configuration without taking any action
+    Verify,
+    /// Show the repairs without applying them
+    Plan,
+}
+
+#[derive(Clone, Debug)]
+pub enum CacheAction {
+    List,
+    /// Prune(remove also the entries in use)
+    Prune(bool),
 }
 
 #[derive(Clone, Debug)]
@@ -53,4 +107,12 @@ pub trait CoffeeArgs: Send + Sync {
     fn data_dir(&self) -> Option<String>;
     /// return the skip verify flag
     fn skip_verify(&self) -> bool;
+    /// return the limits applied to the plugins install scripts
+    fn build_limits(&self) -> BuildLimits {
+        BuildLimits::default()
+    }
+    /// return the number of plugins that can be built concurrently
+    fn jobs(&self) -> Option<usize> {
+        None
+    }
 }
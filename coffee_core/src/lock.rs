@@ -0,0 +1,157 @@
+//! Advisory lock of the coffee data directory.
+//!
+//! The command line, the httpd daemon and the core lightning
+//! plugin can open the same data directory, but the storage
+//! can be opened by one process at the time. So the process
+//! that opens the data directory holds a lock on it, and it
+//! writes inside the lock file the information to reach it,
+//! so the other processes can forward the commands to it.
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+
+/// Lock file inside the coffee data directory.
+pub const LOCK_FILE: &str = "coffee.lock";
+
+/// Where the process that holds the lock accepts the commands.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", content = "address", rename_all = "lowercase")]
+pub enum LockEndpoint {
+    /// Url of the coffee httpd daemon.
+    Httpd(String),
+    /// Path of the core lightning rpc socket where the
+    /// coffee plugin is running.
+    Plugin(String),
+}
+
+/// What the other processes need to authenticate
+/// the commands forwarded to the endpoint.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LockAccess {
+    /// File with the tokens of the httpd, when it is
+    /// not the default one inside the coffee home.
+    pub token_file: Option<String>,
+    /// PEM file with the TLS certificate of the httpd,
+    /// trusted by the processes that forward the commands.
+    pub tls_cert: Option<String>,
+}
+
+/// Information about the process that holds the lock.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub program: String,
+    pub endpoint: Option<LockEndpoint>,
+    #[serde(default)]
+    pub access: LockAccess,
+}
+
+/// Lock of the data directory, released on drop.
+#[derive(Debug)]
+pub struct DataDirLock {
+    file: File,
+    owner: LockOwner,
+}
+
+fn lock_path(root_path: &str) -> String {
+    format!("{root_path}/{LOCK_FILE}")
+}
+
+fn read_owner(file: &mut File) -> Option<LockOwner> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+impl DataDirLock {
+    /// Take the lock of the data directory, return an error with
+    /// the process that is using the directory if it is locked.
+    pub fn acquire(root_path: &str) -> Result<Self, CoffeeError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(root_path))?;
+        if file.try_lock_exclusive().is_err() {
+            let err = match read_owner(&mut file) {
+                Some(owner) => {
+                    let hint = if owner.endpoint.is_some() {
+                        ", use `--forward` to send the command to it"
+                    } else {
+                        ""
+                    };
+                    error!(
+                        "coffee data directory `{root_path}` is in use by PID {} ({}){hint}",
+                        owner.pid, owner.program
+                    )
+                }
+                None => error!("coffee data directory `{root_path}` is in use by another process"),
+            };
+            return Err(err);
+        }
+        let program = std::env::current_exe()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or(String::from("coffee"));
+        let mut lock = DataDirLock {
+            file,
+            owner: LockOwner {
+                pid: std::process::id(),
+                program,
+                endpoint: None,
+                access: LockAccess::default(),
+            },
+        };
+        lock.write_owner()?;
+        log::debug!("coffee data directory `{root_path}` locked");
+        Ok(lock)
+    }
+
+    /// Return the process that holds the lock of the data directory,
+    /// or `None` if the directory is not in use.
+    pub fn owner(root_path: &str) -> Option<LockOwner> {
+        let mut file = File::open(lock_path(root_path)).ok()?;
+        if file.try_lock_shared().is_ok() {
+            let _ = file.unlock();
+            return None;
+        }
+        read_owner(&mut file)
+    }
+
+    /// Publish where the process accepts the commands forwarded
+    /// by the other coffee processes, and how to authenticate them.
+    pub fn set_endpoint(
+        &mut self,
+        endpoint: LockEndpoint,
+        access: LockAccess,
+    ) -> Result<(), CoffeeError> {
+        self.owner.endpoint = Some(endpoint);
+        self.owner.access = access;
+        self.write_owner()
+    }
+
+    fn write_owner(&mut self) -> Result<(), CoffeeError> {
+        let content = serde_json::to_string(&self.owner).map_err(|err| error!("{err}"))?;
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(content.as_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
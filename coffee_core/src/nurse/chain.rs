@@ -30,10 +30,16 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 
+use coffee_lib::error;
 use coffee_lib::errors::CoffeeError;
-use coffee_lib::types::response::{ChainOfResponsibilityStatus, Defect};
+use coffee_lib::types::response::{ChainOfResponsibilityStatus, Defect, NurseStatus};
 
-use super::strategy::GitRepositoryLocallyAbsentStrategy;
+use super::strategy::{
+    ClnConfigUnlinkedStrategy, ClnPathMissingStrategy, ClnRpcSocketMissingStrategy,
+    ConflictingPluginOptionsStrategy, DisablePluginEntryMismatchStrategy,
+    DuplicatedConfEntriesStrategy, GitRepositoryLocallyAbsentStrategy,
+    PluginConfEntryMissingStrategy, PluginConfEntryUnknownStrategy, PluginDirectoryBrokenStrategy,
+};
 use crate::coffee::CoffeeManager;
 
 #[async_trait]
@@ -42,6 +48,21 @@ pub trait Handler: Send + Sync {
         self: Arc<Self>,
         coffee: &CoffeeManager,
     ) -> Result<Option<Defect>, CoffeeError>;
+
+    /// Apply the repair for a `Defect::External` found by the handler.
+    ///
+    /// The defects of coffee are patched by the `CoffeeManager`, so only
+    /// the handlers registered from outside coffee need to implement it.
+    async fn patch(
+        self: Arc<Self>,
+        _coffee: &mut CoffeeManager,
+        defect: &Defect,
+    ) -> Result<Vec<NurseStatus>, CoffeeError> {
+        Err(error!(
+            "no repair available for the defect `{}`",
+            defect.id()
+        ))
+    }
 }
 
 pub struct RecoveryChainOfResponsibility {
@@ -52,10 +73,27 @@ impl RecoveryChainOfResponsibility {
     /// Create a new instance of the chain of responsibility
     pub async fn new() -> Result<Self, CoffeeError> {
         Ok(Self {
-            handlers: vec![Arc::new(GitRepositoryLocallyAbsentStrategy)],
+            handlers: vec![
+                Arc::new(GitRepositoryLocallyAbsentStrategy),
+                Arc::new(PluginConfEntryUnknownStrategy),
+                Arc::new(PluginConfEntryMissingStrategy),
+                Arc::new(DisablePluginEntryMismatchStrategy),
+                Arc::new(DuplicatedConfEntriesStrategy),
+                Arc::new(PluginDirectoryBrokenStrategy),
+                Arc::new(ClnPathMissingStrategy),
+                Arc::new(ClnConfigUnlinkedStrategy),
+                Arc::new(ClnRpcSocketMissingStrategy),
+                Arc::new(ConflictingPluginOptionsStrategy),
+            ],
         })
     }
 
+    /// Register a handler at the end of the chain, so an application
+    /// that embeds coffee can add its own checks.
+    pub fn register(&mut self, handler: Arc<dyn Handler>) {
+        self.handlers.push(handler);
+    }
+
     /// Scan the chain of responsibility to see what can be applied
     /// and return the status of the chain of responsibility
     /// with the list of defects
@@ -63,12 +101,27 @@ impl RecoveryChainOfResponsibility {
         &self,
         coffee: &CoffeeManager,
     ) -> Result<ChainOfResponsibilityStatus, CoffeeError> {
-        let mut defects: Vec<Defect> = vec![];
+        let defects = self
+            .scan_handlers(coffee)
+            .await?
+            .into_iter()
+            .map(|(_, defect)| defect)
+            .collect();
+        Ok(ChainOfResponsibilityStatus { defects })
+    }
+
+    /// Scan the chain of responsibility and return every defect
+    /// with the handler that found it.
+    pub async fn scan_handlers(
+        &self,
+        coffee: &CoffeeManager,
+    ) -> Result<Vec<(Arc<dyn Handler>, Defect)>, CoffeeError> {
+        let mut defects = vec![];
         for handler in self.handlers.iter() {
             if let Some(defect) = handler.clone().can_be_applied(coffee).await? {
-                defects.push(defect);
+                defects.push((handler.clone(), defect));
             }
         }
-        Ok(ChainOfResponsibilityStatus { defects })
+        Ok(defects)
     }
 }
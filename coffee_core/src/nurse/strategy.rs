@@ -21,6 +21,7 @@
 //! be able to choose the algorithm at runtime.
 //!
 //! Author: Vincenzo Palazzo <vincenzopalazzo@member.fsf.org>
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -28,6 +29,7 @@ use async_trait::async_trait;
 
 use coffee_lib::errors::CoffeeError;
 use coffee_lib::types::response::Defect;
+use coffee_lib::utils::is_same_path;
 
 use crate::coffee::CoffeeManager;
 use crate::nurse::chain::Handler;
@@ -74,3 +76,318 @@ impl Handler for GitRepositoryLocallyAbsentStrategy {
         }
     }
 }
+
+/// Strategy for handling the situation when the coffee cln configuration
+/// contains a `plugin` or `important-plugin` line with the exec path of
+/// a plugin that is not installed.
+///
+/// This can happen when the storage is restored from an old version or
+/// the configuration is edited by hand, and core lightning will try to
+/// load a plugin that coffee does not know.
+pub struct PluginConfEntryUnknownStrategy;
+
+#[async_trait]
+impl Handler for PluginConfEntryUnknownStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let installed: HashSet<String> = coffee
+            .config
+            .plugins
+            .iter()
+            .map(|plugin| plugin.exec_path.clone())
+            .collect();
+        let mut paths: Vec<String> = vec![];
+        for key in ["plugin", "important-plugin"] {
+            for path in coffee.cln_conf_values(key) {
+                if !installed.contains(&path) && !paths.contains(&path) {
+                    log::debug!("Plugin {path} configured but not installed");
+                    paths.push(path);
+                }
+            }
+        }
+        if paths.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Defect::PluginConfEntryUnknown(paths)))
+        }
+    }
+}
+
+/// Strategy for handling the situation when a plugin installed and
+/// enabled is not present inside the coffee cln configuration, so
+/// core lightning does not load it at startup.
+pub struct PluginConfEntryMissingStrategy;
+
+#[async_trait]
+impl Handler for PluginConfEntryMissingStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let mut configured = coffee.cln_conf_values("plugin");
+        configured.append(&mut coffee.cln_conf_values("important-plugin"));
+        // the plugins installed dynamically are not inside the
+        // configuration, and they do not have the enabled flag.
+        let plugins: Vec<String> = coffee
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| plugin.enabled == Some(true))
+            .filter(|plugin| !configured.contains(&plugin.exec_path))
+            .map(|plugin| plugin.name())
+            .collect();
+        if plugins.is_empty() {
+            Ok(None)
+        } else {
+            log::debug!("Plugins installed but not configured: {:?}", plugins);
+            Ok(Some(Defect::PluginConfEntryMissing(plugins)))
+        }
+    }
+}
+
+/// Strategy for handling the situation when the `disable-plugin` lines
+/// of the coffee cln configuration do not match the plugins that
+/// coffee knows as disabled.
+pub struct DisablePluginEntryMismatchStrategy;
+
+#[async_trait]
+impl Handler for DisablePluginEntryMismatchStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let configured = coffee.cln_conf_values("disable-plugin");
+        let disabled: Vec<String> = coffee
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| plugin.enabled == Some(false))
+            .map(|plugin| plugin.exec_path.clone())
+            .collect();
+        let mut paths: Vec<String> = vec![];
+        for path in configured.iter().chain(disabled.iter()) {
+            if (!configured.contains(path) || !disabled.contains(path)) && !paths.contains(path) {
+                paths.push(path.to_owned());
+            }
+        }
+        if paths.is_empty() {
+            Ok(None)
+        } else {
+            log::debug!("Plugins with disabled state mismatch: {:?}", paths);
+            Ok(Some(Defect::DisablePluginEntryMismatch(paths)))
+        }
+    }
+}
+
+/// Strategy for handling the situation when the coffee cln configuration
+/// contains the same line more than once.
+pub struct DuplicatedConfEntriesStrategy;
+
+#[async_trait]
+impl Handler for DuplicatedConfEntriesStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let mut entries: Vec<String> = vec![];
+        let mut keys: Vec<&String> = coffee.coffee_cln_config.fields.keys().collect();
+        keys.sort();
+        for key in keys {
+            let mut seen = HashSet::new();
+            for value in coffee.cln_conf_values(key) {
+                let entry = format!("{key}={value}");
+                if !seen.insert(value) && !entries.contains(&entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+        if entries.is_empty() {
+            Ok(None)
+        } else {
+            log::debug!("Duplicated configuration entries: {:?}", entries);
+            Ok(Some(Defect::DuplicatedConfEntries(entries)))
+        }
+    }
+}
+
+/// Strategy for handling the situation when an installed plugin is broken
+/// on disk, because the plugin directory was removed or the exec path is
+/// missing or not executable.
+///
+/// In this case core lightning fails at startup while coffee still
+/// lists the plugin as installed.
+pub struct PluginDirectoryBrokenStrategy;
+
+#[async_trait]
+impl Handler for PluginDirectoryBrokenStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut plugins: Vec<String> = vec![];
+        for plugin in coffee.config.plugins.iter() {
+            log::debug!(
+                "Checking if plugin {} is installed correctly",
+                plugin.name()
+            );
+            if !Path::new(&plugin.root_path).exists() {
+                log::debug!("Plugin directory {} is missing", plugin.root_path);
+                plugins.push(plugin.name());
+                continue;
+            }
+            let executable = std::fs::metadata(&plugin.exec_path)
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if !executable {
+                log::debug!(
+                    "Plugin exec path {} is missing or not executable",
+                    plugin.exec_path
+                );
+                plugins.push(plugin.name());
+            }
+        }
+        if plugins.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Defect::PluginDirectoryBroken(plugins)))
+        }
+    }
+}
+
+/// Strategy for handling the situation when the core lightning configuration
+/// does not include the configuration managed by coffee anymore.
+///
+/// This can happen when the core lightning configuration is edited by hand,
+/// and in this case all the plugins installed with coffee stop loading.
+pub struct ClnConfigUnlinkedStrategy;
+
+#[async_trait]
+impl Handler for ClnConfigUnlinkedStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let Some(cln_config_path) = coffee.config.cln_config_path.clone() else {
+            return Ok(None);
+        };
+        // the missing network directory is reported by another strategy
+        let network_exists = Path::new(&cln_config_path)
+            .parent()
+            .map(|network_path| network_path.exists())
+            .unwrap_or(false);
+        if !network_exists {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&cln_config_path).unwrap_or_default();
+        let linked = content.lines().any(|line| {
+            line.trim()
+                .strip_prefix("include")
+                .map(|path| is_same_path(path.trim(), &coffee.config.config_path))
+                .unwrap_or(false)
+        });
+        if linked {
+            Ok(None)
+        } else {
+            log::debug!("{cln_config_path} does not include the coffee configuration");
+            Ok(Some(Defect::ClnConfigUnlinked(vec![cln_config_path])))
+        }
+    }
+}
+
+/// Strategy for handling the situation when the core lightning root
+/// directory, or the network directory, linked with coffee do not exist.
+///
+/// This can happen when coffee is linked to the wrong network, or the
+/// core lightning directory is moved.
+pub struct ClnPathMissingStrategy;
+
+#[async_trait]
+impl Handler for ClnPathMissingStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let Some(cln_root) = coffee.config.cln_root.clone() else {
+            return Ok(None);
+        };
+        let network_path = format!("{cln_root}/{}", coffee.config.network);
+        let mut paths = vec![];
+        if !Path::new(&cln_root).exists() {
+            paths.push(cln_root);
+        } else if !Path::new(&network_path).exists() {
+            paths.push(network_path);
+        }
+        if paths.is_empty() {
+            Ok(None)
+        } else {
+            log::debug!("Core lightning paths missing: {:?}", paths);
+            Ok(Some(Defect::ClnPathMissing(paths)))
+        }
+    }
+}
+
+/// Strategy for reporting that the core lightning rpc socket does
+/// not exist, so core lightning is not running and the dynamic
+/// operations are not available.
+pub struct ClnRpcSocketMissingStrategy;
+
+#[async_trait]
+impl Handler for ClnRpcSocketMissingStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let Some(cln_root) = coffee.config.cln_root.clone() else {
+            return Ok(None);
+        };
+        let network_path = format!("{cln_root}/{}", coffee.config.network);
+        // the missing network directory is reported by another strategy
+        if !Path::new(&network_path).exists() {
+            return Ok(None);
+        }
+        let socket_path = format!("{network_path}/lightning-rpc");
+        if Path::new(&socket_path).exists() {
+            Ok(None)
+        } else {
+            log::debug!("Core lightning rpc socket {socket_path} missing");
+            Ok(Some(Defect::ClnRpcSocketMissing(vec![socket_path])))
+        }
+    }
+}
+
+/// Strategy for reporting the options declared by more than one
+/// installed plugin.
+///
+/// The options are global keys of the core lightning configuration,
+/// so the plugins read the same value and coffee refuses to set it.
+pub struct ConflictingPluginOptionsStrategy;
+
+#[async_trait]
+impl Handler for ConflictingPluginOptionsStrategy {
+    async fn can_be_applied(
+        self: Arc<Self>,
+        coffee: &CoffeeManager,
+    ) -> Result<Option<Defect>, CoffeeError> {
+        let mut owners: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for plugin in coffee.config.plugins.iter() {
+            for option in plugin.options().into_keys() {
+                owners.entry(option).or_default().push(plugin.name());
+            }
+        }
+        let options: Vec<String> = owners
+            .into_iter()
+            .filter(|(_, plugins)| plugins.len() > 1)
+            .map(|(option, _)| option)
+            .collect();
+        if options.is_empty() {
+            Ok(None)
+        } else {
+            log::debug!("Options declared by more than one plugin: {:?}", options);
+            Ok(Some(Defect::ConflictingPluginOptions(options)))
+        }
+    }
+}
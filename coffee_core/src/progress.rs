@@ -0,0 +1,194 @@
+//! Progress of the operations that coffee is running.
+//!
+//! Coffee emits an event every time that a phase of an operation
+//! starts or ends, and for every line printed by the build of a
+//! plugin. Whoever wants to show the progress (the command line
+//! spinners, the httpd event stream, the core lightning plugin log)
+//! subscribes to the events, and nobody waits for the subscribers.
+use std::fmt;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use coffee_lib::build::OutputSink;
+use coffee_lib::errors::CoffeeError;
+
+/// Number of events kept for a subscriber that is too slow,
+/// the older events are lost for it.
+const PROGRESS_CAPACITY: usize = 1024;
+
+/// Phase of an operation.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Clone a repository.
+    Cloning,
+    /// Fetch the new commits of a repository.
+    Fetching,
+    /// Look for the plugins inside a repository.
+    Indexing,
+    /// Copy the plugin sources (or the cached build) in the network directory.
+    Copying,
+    /// Run the install script of the plugin.
+    Building,
+    /// Store the plugin inside the coffee and core lightning configuration.
+    Configuring,
+    /// Start the plugin inside core lightning.
+    Starting,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            Phase::Cloning => "cloning",
+            Phase::Fetching => "fetching",
+            Phase::Indexing => "indexing",
+            Phase::Copying => "copying",
+            Phase::Building => "building",
+            Phase::Configuring => "configuring",
+            Phase::Starting => "starting",
+        };
+        write!(f, "{phase}")
+    }
+}
+
+/// Event emitted while an operation runs, the target is the
+/// name of the plugin or of the repository.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    Started {
+        target: String,
+        phase: Phase,
+    },
+    Finished {
+        target: String,
+        phase: Phase,
+    },
+    Failed {
+        target: String,
+        phase: Phase,
+        error: String,
+    },
+    /// Line printed by the build of the plugin.
+    Output {
+        target: String,
+        line: String,
+    },
+}
+
+impl ProgressEvent {
+    /// Name of the event, used as event type by the httpd stream.
+    pub fn name(&self) -> &str {
+        match self {
+            ProgressEvent::Started { .. } => "started",
+            ProgressEvent::Finished { .. } => "finished",
+            ProgressEvent::Failed { .. } => "failed",
+            ProgressEvent::Output { .. } => "output",
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        match self {
+            ProgressEvent::Started { target, .. }
+            | ProgressEvent::Finished { target, .. }
+            | ProgressEvent::Failed { target, .. }
+            | ProgressEvent::Output { target, .. } => target,
+        }
+    }
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressEvent::Started { target, phase } => write!(f, "{phase} {target}"),
+            ProgressEvent::Finished { target, phase } => write!(f, "{phase} {target} done"),
+            ProgressEvent::Failed {
+                target,
+                phase,
+                error,
+            } => write!(f, "{phase} {target} failed: {error}"),
+            ProgressEvent::Output { target, line } => write!(f, "{target}: {line}"),
+        }
+    }
+}
+
+/// Sender of the progress events, cheap to clone.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    sender: broadcast::Sender<ProgressEvent>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(PROGRESS_CAPACITY);
+        Progress { sender }
+    }
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive the events emitted from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        log::debug!("progress: {event}");
+        // an error only means that nobody is listening.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn started(&self, target: &str, phase: Phase) {
+        self.emit(ProgressEvent::Started {
+            target: target.to_owned(),
+            phase,
+        });
+    }
+
+    pub fn finished(&self, target: &str, phase: Phase) {
+        self.emit(ProgressEvent::Finished {
+            target: target.to_owned(),
+            phase,
+        });
+    }
+
+    pub fn failed(&self, target: &str, phase: Phase, err: &CoffeeError) {
+        self.emit(ProgressEvent::Failed {
+            target: target.to_owned(),
+            phase,
+            error: format!("{err}"),
+        });
+    }
+
+    /// Run the phase of the target, emitting the events
+    /// for its start and its end.
+    pub async fn phase<T, F>(&self, target: &str, phase: Phase, future: F) -> Result<T, CoffeeError>
+    where
+        F: Future<Output = Result<T, CoffeeError>>,
+    {
+        self.started(target, phase);
+        let result = future.await;
+        match &result {
+            Ok(_) => self.finished(target, phase),
+            Err(err) => self.failed(target, phase, err),
+        }
+        result
+    }
+
+    /// Sink that emits the lines printed by the build of the target.
+    pub fn output(&self, target: &str) -> OutputSink {
+        let progress = self.clone();
+        let target = target.to_owned();
+        OutputSink::new(move |line| {
+            progress.emit(ProgressEvent::Output {
+                target: target.clone(),
+                line: line.to_owned(),
+            })
+        })
+    }
+}
@@ -0,0 +1,126 @@
+//! Tokens used to authenticate the requests to the coffee httpd.
+//!
+//! The tokens are stored inside a JSON file in the coffee data
+//! directory, that is readable only by the user that runs coffee.
+//! When the file is missing coffee creates it with an admin token,
+//! so the local clients (e.g. `coffee --forward`) can read it.
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+
+/// File inside the coffee data directory with the tokens.
+pub const TOKENS_FILE: &str = "httpd-tokens.json";
+
+/// What a token is allowed to do.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Read the coffee state (e.g. list, search, show).
+    ReadOnly,
+    /// Change the coffee state (e.g. install, remove, remote).
+    Admin,
+}
+
+impl Scope {
+    /// Return true if the scope allows the `required` one.
+    pub fn allows(&self, required: Scope) -> bool {
+        *self >= required
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::ReadOnly => write!(f, "readonly"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Token {
+    /// Name of the token, used in the logs.
+    pub name: String,
+    pub token: String,
+    pub scope: Scope,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TokenStore {
+    pub tokens: Vec<Token>,
+}
+
+/// Compare the secrets in constant time.
+fn same_secret(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// Generate a new random token.
+fn random_token() -> Result<String, CoffeeError> {
+    let mut bytes = [0u8; 32];
+    fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+impl TokenStore {
+    /// Default path of the tokens inside the coffee home.
+    pub fn path(root_path: &str) -> String {
+        format!("{root_path}/{TOKENS_FILE}")
+    }
+
+    /// Load the tokens stored in the `path`.
+    pub fn load(path: &str) -> Result<Self, CoffeeError> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| error!("unable to read the tokens from `{path}`: {err}"))?;
+        serde_json::from_str(&content).map_err(|err| error!("invalid tokens file `{path}`: {err}"))
+    }
+
+    /// Load the tokens stored in the `path`, the file is created
+    /// with a new admin token when it is missing.
+    pub fn load_or_create(path: &str) -> Result<Self, CoffeeError> {
+        if Path::new(path).exists() {
+            return Self::load(path);
+        }
+        let store = TokenStore {
+            tokens: vec![Token {
+                name: "default".to_owned(),
+                token: random_token()?,
+                scope: Scope::Admin,
+            }],
+        };
+        let content = serde_json::to_string_pretty(&store).map_err(|err| error!("{err}"))?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(content.as_bytes())?;
+        log::info!("admin token for coffee httpd stored in {path}");
+        Ok(store)
+    }
+
+    /// Return the token with the given secret.
+    pub fn find(&self, secret: &str) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .find(|token| same_secret(&token.token, secret))
+    }
+
+    /// Return the first token with the admin scope.
+    pub fn admin(&self) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.scope == Scope::Admin)
+    }
+}
@@ -3,6 +3,8 @@
 pub mod repository;
 mod utils;
 
+pub use utils::Checkout;
+
 #[cfg(test)]
 mod tests {
     use std::{path::Path, sync::Once};
@@ -22,6 +22,7 @@ use coffee_storage::model::repository::Repository as StorageRepository;
 
 use crate::utils::clone_recursive_fix;
 use crate::utils::git_upgrade;
+use crate::utils::Checkout;
 
 pub struct Github {
     /// the url of the repository to be able
@@ -66,6 +67,39 @@ impl Github {
         }
     }
 
+    /// Local copy of the repository.
+    pub fn checkout(&self) -> Checkout {
+        Checkout {
+            name: self.name.clone(),
+            path: self.url.path_string.clone(),
+            branch: self.branch.clone(),
+        }
+    }
+
+    /// Clone the repository with its submodules, without indexing it.
+    pub async fn clone_repository(&mut self) -> Result<(), CoffeeError> {
+        debug!(
+            "initializing repository: {} {} > {}",
+            self.name, &self.url.url_string, &self.url.path_string,
+        );
+        let res = git2::Repository::clone(&self.url.url_string, &self.url.path_string);
+        match res {
+            Ok(repo) => {
+                self.branch = if repo.find_branch("master", git2::BranchType::Local).is_ok() {
+                    "master".to_owned()
+                } else {
+                    "main".to_owned()
+                };
+                let (commit, date) = get_repo_info!(repo);
+                self.git_head = Some(commit.clone());
+                self.last_activity = Some(date.clone());
+
+                clone_recursive_fix(repo, &self.url).await
+            }
+            Err(err) => Err(error!("{}", err.message())),
+        }
+    }
+
     /// Index the repository to store information
     /// related to the plugins
     pub async fn index_repository(&mut self) -> Result<(), CoffeeError> {
@@ -209,28 +243,8 @@ impl Repository for Github {
     /// Where to store the index is an implementation
     /// details.
     async fn init(&mut self) -> Result<(), CoffeeError> {
-        debug!(
-            "initializing repository: {} {} > {}",
-            self.name, &self.url.url_string, &self.url.path_string,
-        );
-        let res = git2::Repository::clone(&self.url.url_string, &self.url.path_string);
-        match res {
-            Ok(repo) => {
-                self.branch = if repo.find_branch("master", git2::BranchType::Local).is_ok() {
-                    "master".to_owned()
-                } else {
-                    "main".to_owned()
-                };
-                let (commit, date) = get_repo_info!(repo);
-                self.git_head = Some(commit.clone());
-                self.last_activity = Some(date.clone());
-
-                let clone = clone_recursive_fix(repo, &self.url).await;
-                self.index_repository().await?;
-                clone
-            }
-            Err(err) => Err(error!("{}", err.message())),
-        }
+        self.clone_repository().await?;
+        self.index_repository().await
     }
 
     async fn upgrade(
@@ -266,6 +280,7 @@ impl Repository for Github {
             repo: self.name(),
             status,
             plugins_effected,
+            plugins_status: vec![],
         })
     }
 
@@ -48,3 +48,41 @@ pub async fn git_upgrade(
         Ok(UpgradeStatus::Updated(upstream_commit, date))
     }
 }
+
+/// Local copy of a git repository, with the branch that
+/// follows the upstream.
+#[derive(Clone, Debug)]
+pub struct Checkout {
+    pub name: String,
+    pub path: String,
+    pub branch: String,
+}
+
+impl Checkout {
+    /// Fetch the upstream without changing the local copy, and
+    /// return how many commits the local copy is behind it.
+    ///
+    /// This does network I/O, so it must not run on the async runtime.
+    pub fn behind_upstream(&self) -> Result<usize, CoffeeError> {
+        let repo = git2::Repository::open(&self.path).map_err(|err| error!("{}", err.message()))?;
+        let mut remote = repo
+            .find_remote("origin")
+            .map_err(|err| error!("{}", err.message()))?;
+        // an empty list of refspecs uses the ones of the remote,
+        // that update the `refs/remotes/origin` references.
+        remote
+            .fetch::<&str>(&[], None, None)
+            .map_err(|err| error!("{}", err.message()))?;
+        let local = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|err| error!("{}", err.message()))?;
+        let upstream = repo
+            .refname_to_id(&format!("refs/remotes/origin/{}", self.branch))
+            .map_err(|err| error!("{}", err.message()))?;
+        let (_, behind) = repo
+            .graph_ahead_behind(local.id(), upstream)
+            .map_err(|err| error!("{}", err.message()))?;
+        Ok(behind)
+    }
+}
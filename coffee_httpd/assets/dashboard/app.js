@@ -0,0 +1,202 @@
+// Dashboard of coffee, it only uses the httpd API with relative
+// urls, so it works on any address where the daemon listens.
+"use strict";
+
+const JOB_POLL_INTERVAL = 1000;
+
+function token() {
+  return localStorage.getItem("coffee-token") || "";
+}
+
+function element(id) {
+  return document.getElementById(id);
+}
+
+function showMessage(text, error) {
+  const message = element("message");
+  message.textContent = text;
+  message.className = error ? "error" : "";
+  message.hidden = false;
+}
+
+async function api(method, path, body) {
+  const headers = { Authorization: `Bearer ${token()}` };
+  if (body !== undefined) {
+    headers["Content-Type"] = "application/json";
+  }
+  const response = await fetch(path, {
+    method,
+    headers,
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+  const answer = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    throw new Error(answer.message || `${response.status} ${response.statusText}`);
+  }
+  return answer;
+}
+
+// The long operations are jobs, wait until the job is finished.
+async function waitJob(job) {
+  while (job.state === "queued" || job.state === "running") {
+    showMessage(`${job.kind.operation} ${job.state}...`);
+    await new Promise((resolve) => setTimeout(resolve, JOB_POLL_INTERVAL));
+    job = await api("GET", `jobs/${job.id}`);
+  }
+  if (job.state !== "succeeded") {
+    throw new Error(job.error || `job ${job.id} ${job.state}`);
+  }
+  return job;
+}
+
+async function run(action, done) {
+  try {
+    await action();
+    showMessage(done);
+  } catch (err) {
+    showMessage(err.message, true);
+  }
+}
+
+function cell(row, text) {
+  const td = document.createElement("td");
+  td.textContent = text === undefined || text === null ? "" : text;
+  row.appendChild(td);
+  return td;
+}
+
+function button(parent, label, onclick) {
+  const node = document.createElement("button");
+  node.textContent = label;
+  node.onclick = onclick;
+  parent.appendChild(node);
+}
+
+async function loadPlugins() {
+  const rows = element("plugins-rows");
+  rows.replaceChildren();
+  const plugins = await api("GET", "v2/plugins?page_size=500");
+  // the status needs core lightning, the list is shown without it.
+  const status = await api("GET", "status").catch(() => ({ plugins: [] }));
+  for (const plugin of plugins.items) {
+    const state = status.plugins.find((item) => item.name === plugin.name);
+    const enabled = plugin.enabled !== false;
+    const row = document.createElement("tr");
+    cell(row, plugin.name);
+    cell(row, plugin.repository);
+    cell(row, plugin.lang);
+    cell(row, enabled ? "yes" : "no");
+    cell(row, state ? state.state : "unknown");
+    const actions = cell(row);
+    const name = plugin.name;
+    if (enabled) {
+      button(actions, "Disable", () =>
+        run(() => api("POST", "disable", { plugin: name }).then(loadPlugins), `${name} disabled`)
+      );
+    } else {
+      button(actions, "Enable", () =>
+        run(() => api("POST", "enable", { plugin: name }).then(loadPlugins), `${name} enabled`)
+      );
+    }
+    button(actions, "Remove", () => {
+      if (confirm(`Remove ${name}?`)) {
+        run(() => api("POST", "remove", { plugin: name }).then(loadPlugins), `${name} removed`);
+      }
+    });
+    rows.appendChild(row);
+  }
+}
+
+async function loadRemotes() {
+  const rows = element("remotes-rows");
+  rows.replaceChildren();
+  const remotes = await api("GET", "v2/remotes?page_size=500");
+  for (const remote of remotes.items) {
+    const row = document.createElement("tr");
+    cell(row, remote.local_name);
+    cell(row, remote.url);
+    cell(row, (remote.commit_id || "").slice(0, 8));
+    const actions = cell(row);
+    button(actions, `${remote.plugins.length} plugins`, () =>
+      run(() => loadRemotePlugins(remote.local_name), `${remote.local_name} loaded`)
+    );
+    rows.appendChild(row);
+  }
+}
+
+async function loadRemotePlugins(remote) {
+  const plugins = await api("GET", `v2/remotes/${encodeURIComponent(remote)}/plugins?page_size=500`);
+  const list = element("remote-plugins-list");
+  list.replaceChildren();
+  for (const plugin of plugins.items) {
+    const item = document.createElement("li");
+    const link = document.createElement("a");
+    link.textContent = plugin.name;
+    link.onclick = () => run(() => loadReadme(remote, plugin.name), `${plugin.name} loaded`);
+    item.appendChild(link);
+    list.appendChild(item);
+  }
+  element("remote-name").textContent = remote;
+  element("remote-plugins").hidden = false;
+  element("readme").hidden = true;
+}
+
+async function loadReadme(remote, plugin) {
+  const path = `v2/remotes/${encodeURIComponent(remote)}/plugins/${encodeURIComponent(plugin)}`;
+  const details = await api("GET", path);
+  element("readme-name").textContent = plugin;
+  element("readme-content").textContent = details.readme || "No README";
+  element("readme").hidden = false;
+}
+
+async function loadNurse() {
+  const rows = element("nurse-rows");
+  rows.replaceChildren();
+  const plan = await api("GET", "nurse/plan");
+  element("nurse-summary").textContent =
+    plan.repairs.length === 0 ? "Coffee is sane" : `${plan.repairs.length} defects found`;
+  for (const repair of plan.repairs) {
+    const row = document.createElement("tr");
+    cell(row, repair.id);
+    cell(row, repair.defect);
+    cell(row, repair.repair);
+    cell(row, repair.risk);
+    cell(row, repair.affected.join(", "));
+    rows.appendChild(row);
+  }
+}
+
+const views = { plugins: loadPlugins, remotes: loadRemotes, nurse: loadNurse };
+
+function show(view) {
+  for (const name of Object.keys(views)) {
+    element(name).hidden = name !== view;
+  }
+  for (const node of document.querySelectorAll("nav button")) {
+    node.classList.toggle("active", node.dataset.view === view);
+  }
+  run(views[view], `${view} loaded`);
+}
+
+for (const node of document.querySelectorAll("nav button")) {
+  node.onclick = () => show(node.dataset.view);
+}
+
+element("token").value = token();
+element("token-form").onsubmit = (event) => {
+  event.preventDefault();
+  localStorage.setItem("coffee-token", element("token").value.trim());
+  show("plugins");
+};
+
+element("install-form").onsubmit = (event) => {
+  event.preventDefault();
+  const plugin = element("install-name").value.trim();
+  const request = { plugin, try_dynamic: element("install-dynamic").checked };
+  run(
+    () => api("POST", "install", request).then(waitJob).then(loadPlugins),
+    `${plugin} installed`
+  );
+};
+
+show("plugins");
@@ -1,6 +1,11 @@
 //! Coffee http daemon arguments definition.
 use clap::Parser;
 
+use coffee_lib::build::BuildLimits;
+
+use crate::httpd::tls::TlsPaths;
+use crate::httpd::Bind;
+
 /// Coffee main command line definition for the command line tools.
 #[derive(Debug, Parser)]
 #[clap(name = "coffee_httpd")]
@@ -14,10 +19,78 @@ pub struct HttpdArgs {
     pub cln_path: String,
     #[clap(long, value_parser)]
     pub data_dir: Option<String>,
+    /// Address where the daemon listens (IPv4 or IPv6), it can be
+    /// repeated, by default 127.0.0.1
+    #[clap(long, value_parser)]
+    pub host: Vec<String>,
+    #[clap(long, value_parser)]
+    pub port: Option<u16>,
+    #[clap(long, value_parser)]
+    pub build_timeout: Option<u64>,
     #[clap(long, value_parser)]
-    pub host: Option<String>,
+    pub build_memory_limit: Option<u64>,
     #[clap(long, value_parser)]
-    pub port: Option<u64>,
+    pub build_cpu_limit: Option<u64>,
+    #[clap(long, value_parser)]
+    pub jobs: Option<usize>,
+    /// Do not report the defects of the coffee configuration at startup
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub skip_verify: bool,
+    /// File with the tokens allowed to use the API, by default
+    /// `httpd-tokens.json` inside the coffee home
+    #[clap(long, value_parser)]
+    pub token_file: Option<String>,
+    /// Accept the core lightning runes, validated by the node
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub rune_auth: bool,
+    /// Allow every request without authentication
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub no_auth: bool,
+    /// Listen also on the unix domain socket at the given path
+    #[clap(long, value_parser)]
+    pub unix_socket: Option<String>,
+    /// PEM file with the TLS certificate chain
+    #[clap(long, value_parser, requires = "tls_key")]
+    pub tls_cert: Option<String>,
+    /// PEM file with the TLS private key
+    #[clap(long, value_parser, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// PEM file with the CA that signs the client certificates,
+    /// when given the clients must present a certificate
+    #[clap(long, value_parser, requires = "tls_cert")]
+    pub tls_client_ca: Option<String>,
+}
+
+impl HttpdArgs {
+    /// Return the addresses where the daemon listens.
+    pub fn binds(&self) -> Vec<Bind> {
+        let port = self.port.unwrap_or(8080);
+        let mut hosts = self.host.clone();
+        if hosts.is_empty() {
+            hosts.push(String::from("127.0.0.1"));
+        }
+        let mut binds: Vec<Bind> = hosts
+            .into_iter()
+            .map(|host| {
+                // accept also the IPv6 address inside the brackets
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                Bind::Tcp(host.to_owned(), port)
+            })
+            .collect();
+        if let Some(path) = &self.unix_socket {
+            binds.push(Bind::Unix(path.to_owned()));
+        }
+        binds
+    }
+
+    /// Return the paths of the TLS files, when TLS is enabled.
+    pub fn tls(&self) -> Option<TlsPaths> {
+        Some(TlsPaths {
+            cert: self.tls_cert.clone()?,
+            key: self.tls_key.clone()?,
+            client_ca: self.tls_client_ca.clone(),
+        })
+    }
 }
 
 impl coffee_core::CoffeeArgs for HttpdArgs {
@@ -37,10 +110,22 @@ impl coffee_core::CoffeeArgs for HttpdArgs {
         self.network.clone()
     }
 
-    // We don't need to verify the nurse in the httpd
-    // daemon.
-    // there is no endpoint for `nurse` in the httpd
+    // The daemon starts also when the configuration is broken,
+    // because the defects can be repaired with the `/nurse`
+    // endpoint, so they are only reported at startup.
     fn skip_verify(&self) -> bool {
         true
     }
+
+    fn build_limits(&self) -> BuildLimits {
+        BuildLimits {
+            timeout: self.build_timeout,
+            memory_limit: self.build_memory_limit,
+            cpu_limit: self.build_cpu_limit,
+        }
+    }
+
+    fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
 }
@@ -1,3 +1,10 @@
+pub mod auth;
+pub mod dashboard;
+pub mod error;
+pub mod jobs;
+pub mod metrics;
 pub mod server;
+pub mod tls;
+pub mod v2;
 pub use server::*;
 pub mod macros;
@@ -0,0 +1,196 @@
+//! Authentication and authorization of the httpd requests.
+//!
+//! A request is authenticated with a token of the local token
+//! store (`Authorization: Bearer <token>`), or with a core lightning
+//! rune (`Rune: <rune>`) that is validated by the node with the
+//! `checkrune` command.
+//!
+//! The `GET` endpoints only read the coffee state, so they require
+//! the read-only scope, while all the other endpoints change the
+//! state and require the admin scope.
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest};
+use clightningrpc_common::client::Client;
+use serde_json::{json, Value};
+
+use coffee_core::token::{Scope, TokenStore};
+use coffee_lib::error;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+
+use super::error::HttpdError;
+
+/// Header that contains the core lightning rune.
+const RUNE_HEADER: &str = "Rune";
+
+/// Endpoints that are available without authentication,
+/// they only contain the API documentation and the static
+/// files of the dashboard, that asks for the token itself.
+const PUBLIC_PATHS: [&str; 3] = ["/", "/api/v1", "/swagger"];
+/// Prefixes of the public static files.
+const PUBLIC_PREFIXES: [&str; 2] = ["/dashboard/", "/swagger/"];
+
+/// How the httpd authenticates the requests.
+pub struct Auth {
+    /// When false every request is allowed.
+    enabled: bool,
+    tokens: TokenStore,
+    /// Path of the core lightning rpc socket used to validate
+    /// the runes, the runes are refused when it is missing.
+    rune_rpc: Option<String>,
+}
+
+impl Auth {
+    pub fn new(tokens: TokenStore, rune_rpc: Option<String>) -> Self {
+        Auth {
+            enabled: true,
+            tokens,
+            rune_rpc,
+        }
+    }
+
+    /// Allow every request.
+    pub fn disabled() -> Self {
+        Auth {
+            enabled: false,
+            tokens: TokenStore::default(),
+            rune_rpc: None,
+        }
+    }
+
+    /// Check the credentials of the request, and return the
+    /// name of who is calling.
+    async fn authorize(
+        &self,
+        request: &HttpRequest,
+        required: Scope,
+    ) -> Result<String, HttpdError> {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_owned())
+        };
+        if let Some(authorization) = header("Authorization") {
+            // the scheme is case insensitive (RFC 9110).
+            let secret = match authorization.split_once(' ') {
+                Some((scheme, secret)) if scheme.eq_ignore_ascii_case("Bearer") => secret.trim(),
+                _ => {
+                    return Err(HttpdError::unauthorized(
+                        "Unsupported authorization scheme, use `Authorization: Bearer <token>`",
+                    ))
+                }
+            };
+            let Some(token) = self.tokens.find(secret) else {
+                return Err(HttpdError::unauthorized("Invalid token"));
+            };
+            if !token.scope.allows(required) {
+                return Err(HttpdError::forbidden(&format!(
+                    "The token `{}` has the {} scope, but the {required} scope is required",
+                    token.name, token.scope
+                )));
+            }
+            return Ok(format!("token `{}`", token.name));
+        }
+        if let Some(rune) = header(RUNE_HEADER) {
+            let Some(rpc) = &self.rune_rpc else {
+                return Err(HttpdError::unauthorized(
+                    "The authentication with runes is not enabled",
+                ));
+            };
+            // the rpc client is blocking, so it does not run
+            // on the threads that serve the requests.
+            let rpc = rpc.to_owned();
+            let checked = web::block(move || check_rune(&rpc, &rune, required))
+                .await
+                .map_err(|err| HttpdError::from(error!("{err}")))?;
+            if let Err(err) = checked {
+                if err.kind() == ErrorKind::ClnUnavailable {
+                    return Err(HttpdError::from(err));
+                }
+                return Err(HttpdError::forbidden(&format!(
+                    "The rune is not valid for the {required} scope: {}",
+                    err.message()
+                )));
+            }
+            return Ok(String::from("rune"));
+        }
+        Err(HttpdError::unauthorized(
+            "Missing credentials, use the `Authorization: Bearer <token>` or the `Rune: <rune>` header",
+        ))
+    }
+}
+
+/// Scope required by the endpoint, `None` if the endpoint is public.
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let public = PUBLIC_PATHS.contains(&path)
+        || PUBLIC_PREFIXES
+            .iter()
+            .any(|prefix| path.starts_with(prefix));
+    if public && method == Method::GET {
+        return None;
+    }
+    if method == Method::GET {
+        Some(Scope::ReadOnly)
+    } else {
+        Some(Scope::Admin)
+    }
+}
+
+/// Validate the rune with core lightning, the rune must allow
+/// the method `coffee-readonly` or `coffee-admin`.
+fn check_rune(rpc: &str, rune: &str, scope: Scope) -> Result<(), CoffeeError> {
+    let client = Client::new(rpc.to_owned());
+    let response = client
+        .send_request::<Value, Value>(
+            "checkrune",
+            json!({ "rune": rune, "method": format!("coffee-{scope}") }),
+        )
+        .map_err(|err| error!(kind = ClnUnavailable, "{err}"))?;
+    if let Some(err) = response.error {
+        return Err(error!("{}", err.message));
+    }
+    let valid = response
+        .result
+        .and_then(|result| result.get("valid").and_then(|valid| valid.as_bool()));
+    if valid != Some(true) {
+        return Err(error!("rune refused by core lightning"));
+    }
+    Ok(())
+}
+
+/// Middleware that refuses the requests without the
+/// credentials for the scope of the endpoint.
+pub async fn authorize(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(auth) = request.app_data::<web::Data<Auth>>().cloned() else {
+        return next.call(request).await;
+    };
+    let Some(required) = required_scope(request.method(), request.path()) else {
+        return next.call(request).await;
+    };
+    if !auth.enabled {
+        return next.call(request).await;
+    }
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or(String::from("unknown"));
+    let method = request.method().to_string();
+    let path = request.path().to_owned();
+    match auth.authorize(request.request(), required).await {
+        Ok(who) => {
+            log::info!("auth: {method} {path} from {peer} allowed to {who} ({required} scope)");
+            next.call(request).await
+        }
+        Err(err) => {
+            log::warn!("auth: {method} {path} from {peer} denied: {err}");
+            Err(err.into())
+        }
+    }
+}
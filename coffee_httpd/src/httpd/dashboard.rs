@@ -0,0 +1,100 @@
+//! Static files of the web dashboard and of the API documentation.
+//!
+//! The files are embedded inside the binary, so the daemon does
+//! not need the internet or any file next to it to serve them.
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use paperclip::actix::{get, web, HttpResponseWrapper};
+
+struct Asset {
+    path: &'static str,
+    content_type: &'static str,
+    content: &'static [u8],
+}
+
+const ASSETS: [Asset; 8] = [
+    Asset {
+        path: "dashboard/index.html",
+        content_type: "text/html; charset=utf-8",
+        content: include_bytes!("../../assets/dashboard/index.html"),
+    },
+    Asset {
+        path: "dashboard/app.js",
+        content_type: "text/javascript; charset=utf-8",
+        content: include_bytes!("../../assets/dashboard/app.js"),
+    },
+    Asset {
+        path: "dashboard/style.css",
+        content_type: "text/css; charset=utf-8",
+        content: include_bytes!("../../assets/dashboard/style.css"),
+    },
+    Asset {
+        path: "swagger/index.html",
+        content_type: "text/html; charset=utf-8",
+        content: include_bytes!("../../assets/swagger/index.html"),
+    },
+    Asset {
+        path: "swagger/swagger-ui-bundle.js",
+        content_type: "text/javascript; charset=utf-8",
+        content: include_bytes!("../../assets/swagger/swagger-ui-bundle.js"),
+    },
+    Asset {
+        path: "swagger/swagger-ui-standalone-preset.js",
+        content_type: "text/javascript; charset=utf-8",
+        content: include_bytes!("../../assets/swagger/swagger-ui-standalone-preset.js"),
+    },
+    Asset {
+        path: "swagger/swagger-ui.css",
+        content_type: "text/css; charset=utf-8",
+        content: include_bytes!("../../assets/swagger/swagger-ui.css"),
+    },
+    Asset {
+        path: "swagger/LICENSE",
+        content_type: "text/plain; charset=utf-8",
+        content: include_bytes!("../../assets/swagger/LICENSE"),
+    },
+];
+
+/// Answer with the embedded file, or with not found.
+fn asset(path: &str) -> HttpResponseWrapper {
+    let response = match ASSETS.iter().find(|asset| asset.path == path) {
+        Some(asset) => HttpResponse::Ok()
+            .content_type(asset.content_type)
+            // revalidate, so a new version of the daemon is picked up.
+            .insert_header((header::CACHE_CONTROL, "no-cache"))
+            .body(asset.content),
+        None => HttpResponse::NotFound().finish(),
+    };
+    HttpResponseWrapper(response)
+}
+
+#[get("/")]
+pub(crate) async fn index() -> HttpResponseWrapper {
+    asset("dashboard/index.html")
+}
+
+#[get("/dashboard/{file}")]
+pub(crate) async fn dashboard(file: web::Path<String>) -> HttpResponseWrapper {
+    asset(&format!("dashboard/{file}"))
+}
+
+/// The swagger files are relative to the page, so the page
+/// must be served from the directory.
+#[get("/swagger")]
+pub(crate) async fn swagger_redirect() -> HttpResponseWrapper {
+    HttpResponseWrapper(
+        HttpResponse::PermanentRedirect()
+            .insert_header((header::LOCATION, "swagger/"))
+            .finish(),
+    )
+}
+
+#[get("/swagger/")]
+pub(crate) async fn swagger() -> HttpResponseWrapper {
+    asset("swagger/index.html")
+}
+
+#[get("/swagger/{file}")]
+pub(crate) async fn swagger_file(file: web::Path<String>) -> HttpResponseWrapper {
+    asset(&format!("swagger/{file}"))
+}
@@ -0,0 +1,132 @@
+//! Errors of the httpd.
+//!
+//! Every failure is answered with an [`ErrorResponse`] in json, and
+//! with the status code of the coffee error category, so the clients
+//! do not need to parse the message to know what went wrong.
+use std::fmt;
+
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use paperclip::actix::api_v2_errors;
+
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::types::response::ErrorResponse;
+
+#[api_v2_errors(
+    code = 401,
+    description = "Missing or invalid credentials",
+    code = 403,
+    description = "The credentials do not allow the request",
+    code = 404,
+    description = "The plugin, the repository or the resource does not exist",
+    code = 409,
+    description = "The request conflicts with the current state of coffee",
+    code = 422,
+    description = "The request is not valid",
+    code = 500,
+    description = "Coffee failed to run the request",
+    code = 503,
+    description = "Core lightning is not reachable, or coffee is too busy",
+    default_schema = "ErrorResponse"
+)]
+#[derive(Debug)]
+pub struct HttpdError {
+    status: StatusCode,
+    body: ErrorResponse,
+}
+
+impl HttpdError {
+    pub fn new(status: StatusCode, code: &str, message: &str) -> Self {
+        HttpdError {
+            status,
+            body: ErrorResponse::new(code, message),
+        }
+    }
+
+    pub fn unauthorized(message: &str) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    pub fn forbidden(message: &str) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    pub fn invalid_request(message: &str) -> Self {
+        Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::InvalidRequest.code(),
+            message,
+        )
+    }
+
+    pub fn with_detail(mut self, key: &str, value: &str) -> Self {
+        self.body = self.body.with_detail(key, value);
+        self
+    }
+}
+
+/// Status code of the coffee error category.
+pub fn status_code(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::PluginNotFound | ErrorKind::RepositoryNotFound | ErrorKind::NotFound => {
+            StatusCode::NOT_FOUND
+        }
+        ErrorKind::AlreadyInstalled | ErrorKind::AlreadyExists | ErrorKind::Conflict => {
+            StatusCode::CONFLICT
+        }
+        ErrorKind::InvalidRequest => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorKind::ClnUnavailable | ErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::BuildFailed | ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl From<CoffeeError> for HttpdError {
+    fn from(err: CoffeeError) -> Self {
+        HttpdError {
+            status: status_code(err.kind()),
+            body: ErrorResponse::from(&err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for HttpdError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorKind::Internal.code(),
+            &format!("{err}"),
+        )
+    }
+}
+
+impl fmt::Display for HttpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body)
+    }
+}
+
+impl ResponseError for HttpdError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(&self.body)
+    }
+}
+
+/// Answer to a request with an invalid json body.
+pub fn json_error(err: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    HttpdError::invalid_request(&format!("invalid json body: {err}")).into()
+}
+
+/// Answer to a request with an invalid query string.
+pub fn query_error(err: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    HttpdError::invalid_request(&format!("invalid query: {err}")).into()
+}
+
+/// Answer to a request with an invalid path parameter.
+pub fn path_error(err: PathError, _: &HttpRequest) -> actix_web::Error {
+    HttpdError::invalid_request(&format!("invalid path: {err}")).into()
+}
@@ -0,0 +1,464 @@
+//! Background jobs of the httpd.
+//!
+//! The operations that can take minutes (install, upgrade, remote
+//! add and nurse) do not run inside the request: the request puts
+//! a job inside the queue and returns it, then the client polls
+//! `/jobs/{id}` until the job is finished. A single worker runs
+//! the jobs in order.
+//!
+//! The queue is stored inside the coffee data directory when a job
+//! changes state, so the queued jobs survive a restart of the daemon.
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+
+use super::metrics::Metrics;
+use coffee_core::backup::timestamp;
+use coffee_core::coffee::CoffeeManager;
+use coffee_lib::build::BuildCanceller;
+use coffee_lib::error;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::types::response::{
+    Caller, CoffeeInstall, CoffeeJob, CoffeeJobLog, CoffeeJobs, JobKind, JobLogLine, JobState,
+    PluginInstallStatus,
+};
+
+/// File inside the coffee data directory with the jobs.
+pub const JOBS_FILE: &str = "httpd-jobs.json";
+/// Maximum number of jobs that are queued or running.
+pub const MAX_PENDING_JOBS: usize = 32;
+/// Number of finished jobs that are kept, the oldest are dropped first.
+pub const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Jobs {
+    /// Id of the last job submitted.
+    last_id: u64,
+    /// The jobs, oldest first.
+    jobs: Vec<CoffeeJob>,
+}
+
+impl Jobs {
+    fn get_mut(&mut self, id: u64) -> Option<&mut CoffeeJob> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+}
+
+fn push_log(job: &mut CoffeeJob, message: String) {
+    log::info!("job {}: {message}", job.id);
+    job.log.push(JobLogLine {
+        timestamp: timestamp(),
+        message,
+    });
+}
+
+pub struct JobQueue {
+    path: String,
+    jobs: Mutex<Jobs>,
+    /// Wake up the worker when a job is submitted.
+    wakeup: Notify,
+    /// The running job with the canceller of its builds.
+    running: Mutex<Option<(u64, BuildCanceller)>>,
+    /// Number of the last snapshot of the jobs that is stored.
+    snapshots: AtomicU64,
+    /// Number of the snapshot that is written on disk, so an older
+    /// snapshot never replaces a newer one.
+    written: Arc<Mutex<u64>>,
+}
+
+/// Write the jobs inside the `path`, replacing the file at once.
+fn write_jobs(path: &str, content: &str) -> Result<(), CoffeeError> {
+    let tmp = format!("{path}.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl JobQueue {
+    /// Default path of the jobs inside the coffee home.
+    pub fn path(root_path: &str) -> String {
+        format!("{root_path}/{JOBS_FILE}")
+    }
+
+    /// Load the jobs stored in the `path`.
+    ///
+    /// The jobs that were running when the daemon stopped are marked
+    /// as failed, because we do not know how far they went, while
+    /// the queued jobs are going to run again.
+    pub fn load(path: &str) -> Result<Self, CoffeeError> {
+        let mut jobs = if Path::new(path).exists() {
+            let content = fs::read_to_string(path)
+                .map_err(|err| error!("unable to read the jobs from `{path}`: {err}"))?;
+            serde_json::from_str(&content)
+                .map_err(|err| error!("invalid jobs file `{path}`: {err}"))?
+        } else {
+            Jobs::default()
+        };
+        for job in jobs
+            .jobs
+            .iter_mut()
+            .filter(|job| job.state == JobState::Running)
+        {
+            job.state = JobState::Failed;
+            job.finished_at = Some(timestamp());
+            job.error = Some(String::from("interrupted by a restart of the daemon"));
+            job.error_code = Some(ErrorKind::Internal.code().to_owned());
+            push_log(job, String::from("interrupted by a restart of the daemon"));
+        }
+        let queue = JobQueue {
+            path: path.to_owned(),
+            jobs: Mutex::new(jobs),
+            wakeup: Notify::new(),
+            running: Mutex::new(None),
+            snapshots: AtomicU64::new(0),
+            written: Arc::new(Mutex::new(0)),
+        };
+        let content =
+            serde_json::to_string_pretty(&*queue.jobs()).map_err(|err| error!("{err}"))?;
+        write_jobs(path, &content)
+            .map_err(|err| error!("unable to store the jobs inside `{path}`: {err}"))?;
+        Ok(queue)
+    }
+
+    fn jobs(&self) -> MutexGuard<'_, Jobs> {
+        // a panic while the lock is held can not leave the jobs
+        // half updated, so the poisoned lock is still usable.
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write the jobs on disk outside the async threads, a failure
+    /// is only logged because the jobs in memory are still valid.
+    ///
+    /// The jobs are written when a job changes state, the lines
+    /// of the log are kept in memory until then.
+    fn store(&self, jobs: &Jobs) {
+        let content = match serde_json::to_string_pretty(jobs) {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!("unable to store the jobs inside `{}`: {err}", self.path);
+                return;
+            }
+        };
+        let snapshot = self.snapshots.fetch_add(1, Ordering::SeqCst) + 1;
+        let path = self.path.clone();
+        let written = self.written.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap_or_else(PoisonError::into_inner);
+            // a newer snapshot is already on disk.
+            if *written > snapshot {
+                return;
+            }
+            if let Err(err) = write_jobs(&path, &content) {
+                log::warn!("unable to store the jobs inside `{path}`: {err}");
+            }
+            *written = snapshot;
+        });
+    }
+
+    /// Put a new job inside the queue, and return it.
+    pub fn submit(&self, kind: JobKind, caller: Caller) -> Result<CoffeeJob, CoffeeError> {
+        let mut jobs = self.jobs();
+        let pending = jobs
+            .jobs
+            .iter()
+            .filter(|job| !job.state.is_finished())
+            .count();
+        if pending >= MAX_PENDING_JOBS {
+            return Err(error!(
+                kind = Unavailable,
+                "the job queue is full ({pending} jobs pending), retry later"
+            ));
+        }
+        jobs.last_id += 1;
+        let mut job = CoffeeJob {
+            id: jobs.last_id,
+            kind,
+            state: JobState::Queued,
+            caller,
+            created_at: timestamp(),
+            started_at: None,
+            finished_at: None,
+            cancel_requested: false,
+            result: None,
+            error: None,
+            error_code: None,
+            log: vec![],
+        };
+        push_log(&mut job, format!("{} queued by {}", job.kind, job.caller));
+        jobs.jobs.push(job.clone());
+        self.store(&jobs);
+        self.wakeup.notify_one();
+        Ok(job)
+    }
+
+    pub fn get(&self, id: u64) -> Option<CoffeeJob> {
+        self.jobs().jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    /// Return all the jobs without their log.
+    pub fn list(&self) -> CoffeeJobs {
+        let jobs = self
+            .jobs()
+            .jobs
+            .iter()
+            .map(|job| CoffeeJob {
+                log: vec![],
+                ..job.clone()
+            })
+            .collect();
+        CoffeeJobs { jobs }
+    }
+
+    pub fn log(&self, id: u64) -> Option<CoffeeJobLog> {
+        self.get(id).map(|job| CoffeeJobLog {
+            id: job.id,
+            state: job.state,
+            log: job.log,
+        })
+    }
+
+    /// Cancel the job. A queued job is removed from the queue, while
+    /// a running job is marked and its builds are stopped.
+    pub fn cancel(&self, id: u64) -> Result<CoffeeJob, CoffeeError> {
+        let mut jobs = self.jobs();
+        let job = jobs
+            .get_mut(id)
+            .ok_or_else(|| error!(kind = NotFound, "job `{id}` not found"))?;
+        match job.state {
+            JobState::Queued => {
+                job.state = JobState::Cancelled;
+                job.finished_at = Some(timestamp());
+                push_log(job, String::from("cancelled before running"));
+            }
+            JobState::Running => {
+                job.cancel_requested = true;
+                push_log(job, String::from("cancel requested"));
+                if let Some((_, canceller)) = self
+                    .running()
+                    .as_ref()
+                    .filter(|(running, _)| *running == id)
+                {
+                    canceller.cancel();
+                }
+            }
+            state => return Err(error!(kind = Conflict, "job `{id}` is already {state}")),
+        }
+        let job = job.clone();
+        self.store(&jobs);
+        Ok(job)
+    }
+
+    fn running(&self) -> MutexGuard<'_, Option<(u64, BuildCanceller)>> {
+        self.running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Cancel the builds of the running job, and return
+    /// the number of builds that are cancelled.
+    pub fn cancel_builds(&self) -> usize {
+        self.running()
+            .as_ref()
+            .map(|(_, canceller)| canceller.cancel())
+            .unwrap_or(0)
+    }
+
+    /// Append a message to the log of the job.
+    fn append_log(&self, id: u64, message: String) {
+        let mut jobs = self.jobs();
+        if let Some(job) = jobs.get_mut(id) {
+            push_log(job, message);
+        }
+    }
+
+    /// Take the oldest queued job, and mark it as running.
+    /// Start the next queued job, and return it with the canceller
+    /// of its builds, that is published while the jobs are locked
+    /// so a cancel can not miss it.
+    fn next(&self) -> Option<(CoffeeJob, BuildCanceller)> {
+        let mut jobs = self.jobs();
+        let job = jobs
+            .jobs
+            .iter_mut()
+            .find(|job| job.state == JobState::Queued)?;
+        job.state = JobState::Running;
+        job.started_at = Some(timestamp());
+        push_log(job, String::from("started"));
+        let job = job.clone();
+        let canceller = BuildCanceller::once();
+        *self.running() = Some((job.id, canceller.clone()));
+        self.store(&jobs);
+        Some((job, canceller))
+    }
+
+    fn finish(&self, id: u64, result: Option<Value>, err: Option<CoffeeError>) {
+        let mut jobs = self.jobs();
+        if let Some(job) = jobs.get_mut(id) {
+            job.state = match &err {
+                None => JobState::Succeeded,
+                Some(_) if job.cancel_requested => JobState::Cancelled,
+                Some(_) => JobState::Failed,
+            };
+            job.finished_at = Some(timestamp());
+            job.result = result;
+            job.error_code = err.as_ref().map(|err| err.kind().code().to_owned());
+            job.error = err.map(|err| format!("{err}"));
+            let state = job.state;
+            push_log(job, format!("{state}"));
+        }
+        let finished = jobs
+            .jobs
+            .iter()
+            .filter(|job| job.state.is_finished())
+            .count();
+        if finished > MAX_FINISHED_JOBS {
+            let mut drop = finished - MAX_FINISHED_JOBS;
+            jobs.jobs.retain(|job| {
+                if drop > 0 && job.state.is_finished() {
+                    drop -= 1;
+                    return false;
+                }
+                true
+            });
+        }
+        self.store(&jobs);
+    }
+}
+
+/// Split the result of an operation in the response and the error.
+fn outcome<T: Serialize>(result: Result<T, CoffeeError>) -> (Option<Value>, Option<CoffeeError>) {
+    match result.map(serde_json::to_value) {
+        Ok(Ok(value)) => (Some(value), None),
+        Ok(Err(err)) => (None, Some(error!("{err}"))),
+        Err(err) => (None, Some(err)),
+    }
+}
+
+/// Use the canceller of the job for the builds started by coffee,
+/// and return the previous one, so cancelling the job does not
+/// stop the builds of the others.
+fn swap_canceller(coffee: &mut CoffeeManager, canceller: &BuildCanceller) -> BuildCanceller {
+    std::mem::replace(&mut coffee.canceller, canceller.clone())
+}
+
+/// Run the job, and return the response and the error.
+async fn execute(
+    queue: &JobQueue,
+    coffee: &AsyncMutex<CoffeeManager>,
+    job: &CoffeeJob,
+    canceller: &BuildCanceller,
+) -> (Option<Value>, Option<CoffeeError>) {
+    match &job.kind {
+        JobKind::Install {
+            plugins,
+            try_dynamic,
+        } => {
+            // the coffee lock is released while the plugins are
+            // building, so the other requests are not blocked.
+            let pending = {
+                let mut coffee = coffee.lock().await;
+                coffee.set_caller(job.caller.clone());
+                let previous = swap_canceller(&mut coffee, canceller);
+                let pending = coffee.prepare_install_plugins(plugins, false, *try_dynamic);
+                coffee.canceller = previous;
+                pending
+            };
+            queue.append_log(job.id, format!("building {}", plugins.join(", ")));
+            let built = pending.build().await;
+            let results = {
+                let mut coffee = coffee.lock().await;
+                coffee.set_caller(job.caller.clone());
+                coffee.finish_install_plugins(built).await
+            };
+            let mut errors = vec![];
+            let mut kind = None;
+            for (plugin, result) in results.iter() {
+                match result {
+                    Ok(path) => {
+                        queue.append_log(job.id, format!("plugin `{plugin}` installed in {path}"))
+                    }
+                    Err(err) => {
+                        queue.append_log(job.id, format!("plugin `{plugin}` not installed: {err}"));
+                        errors.push(format!("{plugin}: {err}"));
+                        kind.get_or_insert(err.kind());
+                    }
+                }
+            }
+            let install = CoffeeInstall {
+                plugins: results
+                    .into_iter()
+                    .map(|(plugin, result)| PluginInstallStatus::new(&plugin, result))
+                    .collect(),
+            };
+            let (result, _) = outcome(Ok(install));
+            // the job reports the category of the first failure.
+            let err = kind.map(|kind| error!("{}", errors.join(", ")).with_kind(kind));
+            (result, err)
+        }
+        JobKind::Upgrade { repository_name } => {
+            let mut coffee = coffee.lock().await;
+            coffee.set_caller(job.caller.clone());
+            let previous = swap_canceller(&mut coffee, canceller);
+            let result = coffee.upgrade(repository_name, false).await;
+            coffee.canceller = previous;
+            if let Ok(upgrade) = &result {
+                for status in upgrade.plugins_status.iter() {
+                    let message = match &status.error {
+                        Some(err) => format!("plugin `{}` not reinstalled: {err}", status.plugin),
+                        None => format!("plugin `{}` reinstalled", status.plugin),
+                    };
+                    queue.append_log(job.id, message);
+                }
+            }
+            outcome(result)
+        }
+        JobKind::RemoteAdd {
+            repository_name,
+            repository_url,
+        } => {
+            let mut coffee = coffee.lock().await;
+            coffee.set_caller(job.caller.clone());
+            let result = coffee.add_remote(repository_name, repository_url).await;
+            outcome(result.map(|_| format!("Repository '{repository_name}' added successfully")))
+        }
+        JobKind::Nurse { ids } => {
+            let mut coffee = coffee.lock().await;
+            coffee.set_caller(job.caller.clone());
+            let previous = swap_canceller(&mut coffee, canceller);
+            let result = coffee.nurse_apply(ids).await;
+            coffee.canceller = previous;
+            if let Ok(nurse) = &result {
+                for status in nurse.status.iter() {
+                    queue.append_log(job.id, format!("{status}"));
+                }
+            }
+            outcome(result)
+        }
+    }
+}
+
+/// Run the queued jobs one at the time, forever.
+pub async fn run_worker(
+    queue: Arc<JobQueue>,
+    coffee: Arc<AsyncMutex<CoffeeManager>>,
+    metrics: Arc<Metrics>,
+) {
+    loop {
+        let Some((job, canceller)) = queue.next() else {
+            queue.wakeup.notified().await;
+            continue;
+        };
+        let start = Instant::now();
+        let (result, err) = execute(&queue, &coffee, &job, &canceller).await;
+        metrics.observe_operation(job.kind.operation(), start.elapsed(), err.is_some());
+        if let Some(err) = &err {
+            queue.append_log(job.id, format!("failed: {err}"));
+        }
+        queue.finish(job.id, result, err);
+    }
+}
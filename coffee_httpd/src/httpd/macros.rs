@@ -1,26 +1,22 @@
-/// handle_httpd_response macro is the macro that handles HTTPD responses
+/// handle_httpd_response macro is the macro that handles HTTPD responses,
+/// the error is converted in a [`HttpdError`](crate::httpd::error::HttpdError)
+/// with the status code of its category.
 #[macro_export]
 macro_rules! handle_httpd_response {
     ($result:expr, $msg:expr) => {
         match $result {
-            Ok(_) => Ok(HttpResponse::Ok().body(format!($msg))),
-            Err(err) => Err(actix_web::error::ErrorInternalServerError(format!(
-                "Error: {}",
-                err
-            ))),
+            Ok(_) => Ok(Json(serde_json::json!({ "message": format!($msg) }))),
+            Err(err) => Err($crate::httpd::error::HttpdError::from(err)),
         }
     };
     ($result:expr) => {
         match $result {
             Ok(val) => {
-                let val = serde_json::to_value(val).map_err(|err| {
-                    actix_web::error::ErrorInternalServerError(format!("Failure: {err}"))
-                })?;
+                let val = serde_json::to_value(val)
+                    .map_err($crate::httpd::error::HttpdError::from)?;
                 Ok(Json(val))
             }
-            Err(err) => Err(actix_web::error::ErrorInternalServerError(format!(
-                "Failure: {err}"
-            ))),
+            Err(err) => Err($crate::httpd::error::HttpdError::from(err)),
         }
     };
 }
@@ -0,0 +1,239 @@
+//! Prometheus metrics of the httpd.
+//!
+//! The metrics of the plugins and of the repositories are read from
+//! coffee when `/metrics` is scraped, unless coffee is busy with an
+//! operation: in this case the last values are returned, so a scrape
+//! never waits for a build. The commits behind the upstream and the
+//! nurse defects need the network or a scan of the data directory,
+//! so they are refreshed in background every few minutes.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::sync::Mutex;
+
+use coffee_core::coffee::CoffeeManager;
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::PluginManager;
+
+/// Interval between two refreshes of the upstream and nurse metrics.
+pub const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Buckets of the operation durations, the builds can take minutes.
+const OPERATION_BUCKETS: [f64; 9] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+pub struct Metrics {
+    registry: Registry,
+    plugins: IntGaugeVec,
+    remotes: IntGauge,
+    remote_commits_behind: IntGaugeVec,
+    operation_duration: HistogramVec,
+    operation_failures: IntCounterVec,
+    nurse_defects: IntGauge,
+    request_duration: HistogramVec,
+    cln_rpc_errors: IntCounter,
+    /// Failed requests to core lightning counted by coffee.
+    cln_errors: Arc<AtomicU64>,
+}
+
+impl Metrics {
+    pub fn new(cln_errors: Arc<AtomicU64>) -> Result<Self, CoffeeError> {
+        let plugins = IntGaugeVec::new(
+            Opts::new("coffee_plugins_installed", "Number of installed plugins"),
+            &["enabled", "lang", "repository"],
+        )
+        .map_err(|err| error!("{err}"))?;
+        let remotes = IntGauge::new("coffee_remotes", "Number of plugin repositories")
+            .map_err(|err| error!("{err}"))?;
+        let remote_commits_behind = IntGaugeVec::new(
+            Opts::new(
+                "coffee_remote_commits_behind",
+                "Number of commits of the upstream that are not in the local repository",
+            ),
+            &["repository"],
+        )
+        .map_err(|err| error!("{err}"))?;
+        let operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "coffee_operation_duration_seconds",
+                "Duration of the install, upgrade, remote add and nurse operations",
+            )
+            .buckets(OPERATION_BUCKETS.to_vec()),
+            &["operation", "state"],
+        )
+        .map_err(|err| error!("{err}"))?;
+        let operation_failures = IntCounterVec::new(
+            Opts::new(
+                "coffee_operation_failures_total",
+                "Number of the operations that failed",
+            ),
+            &["operation"],
+        )
+        .map_err(|err| error!("{err}"))?;
+        let nurse_defects = IntGauge::new(
+            "coffee_nurse_defects",
+            "Number of defects found by the nurse",
+        )
+        .map_err(|err| error!("{err}"))?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "coffee_http_request_duration_seconds",
+                "Latency of the http requests",
+            ),
+            &["method", "path", "status"],
+        )
+        .map_err(|err| error!("{err}"))?;
+        let cln_rpc_errors = IntCounter::new(
+            "coffee_cln_rpc_errors_total",
+            "Number of the requests to core lightning that failed",
+        )
+        .map_err(|err| error!("{err}"))?;
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(plugins.clone()))
+            .and_then(|_| registry.register(Box::new(remotes.clone())))
+            .and_then(|_| registry.register(Box::new(remote_commits_behind.clone())))
+            .and_then(|_| registry.register(Box::new(operation_duration.clone())))
+            .and_then(|_| registry.register(Box::new(operation_failures.clone())))
+            .and_then(|_| registry.register(Box::new(nurse_defects.clone())))
+            .and_then(|_| registry.register(Box::new(request_duration.clone())))
+            .and_then(|_| registry.register(Box::new(cln_rpc_errors.clone())))
+            .map_err(|err| error!("{err}"))?;
+        Ok(Metrics {
+            registry,
+            plugins,
+            remotes,
+            remote_commits_behind,
+            operation_duration,
+            operation_failures,
+            nurse_defects,
+            request_duration,
+            cln_rpc_errors,
+            cln_errors,
+        })
+    }
+
+    /// Record an operation that ran inside a job.
+    pub fn observe_operation(&self, operation: &str, duration: Duration, failed: bool) {
+        let state = if failed { "failed" } else { "succeeded" };
+        self.operation_duration
+            .with_label_values(&[operation, state])
+            .observe(duration.as_secs_f64());
+        if failed {
+            self.operation_failures
+                .with_label_values(&[operation])
+                .inc();
+        }
+    }
+
+    /// Update the plugins and the repositories with the state of coffee.
+    async fn update(&self, coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+        let remotes = coffee.list_remotes().await?.remotes.unwrap_or_default();
+        let plugins = coffee.list().await?.plugins;
+        self.plugins.reset();
+        for plugin in plugins.iter() {
+            let repository = remotes
+                .iter()
+                .find(|remote| remote.plugins.iter().any(|p| p.name() == plugin.name()))
+                .map(|remote| remote.local_name.as_str())
+                .unwrap_or("unknown");
+            // the plugins installed before the flag existed are enabled.
+            let enabled = plugin.enabled.unwrap_or(true).to_string();
+            let lang = plugin.lang.to_string();
+            self.plugins
+                .with_label_values(&[enabled.as_str(), lang.as_str(), repository])
+                .inc();
+        }
+        self.remotes.set(remotes.len() as i64);
+        Ok(())
+    }
+
+    /// Render the metrics in the prometheus text format.
+    pub async fn render(&self, coffee: &Mutex<CoffeeManager>) -> Result<String, CoffeeError> {
+        if let Ok(mut coffee) = coffee.try_lock() {
+            if let Err(err) = self.update(&mut coffee).await {
+                log::warn!("unable to update the metrics: {err}");
+            }
+        }
+        // the counter of coffee only grows, so we add what is missing.
+        let errors = self.cln_errors.load(Ordering::Relaxed);
+        self.cln_rpc_errors
+            .inc_by(errors.saturating_sub(self.cln_rpc_errors.get()));
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|err| error!("{err}"))?;
+        String::from_utf8(buffer).map_err(|err| error!("{err}"))
+    }
+
+    /// Refresh the commits behind the upstream and the nurse defects.
+    async fn refresh(&self, coffee: &Mutex<CoffeeManager>) {
+        let checkouts = {
+            let coffee = coffee.lock().await;
+            match coffee.nurse_verify().await {
+                Ok(status) => self.nurse_defects.set(status.defects.len() as i64),
+                Err(err) => log::warn!("unable to verify coffee for the metrics: {err}"),
+            }
+            coffee.checkouts()
+        };
+        // the fetch runs without coffee, so the operations are not blocked.
+        self.remote_commits_behind.reset();
+        for checkout in checkouts {
+            let name = checkout.name.clone();
+            let behind = tokio::task::spawn_blocking(move || checkout.behind_upstream()).await;
+            match behind {
+                Ok(Ok(behind)) => self
+                    .remote_commits_behind
+                    .with_label_values(&[name.as_str()])
+                    .set(behind as i64),
+                Ok(Err(err)) => log::warn!("unable to fetch the upstream of `{name}`: {err}"),
+                Err(err) => log::warn!("unable to fetch the upstream of `{name}`: {err}"),
+            }
+        }
+    }
+}
+
+/// Refresh the slow metrics forever.
+pub async fn run_refresher(metrics: Arc<Metrics>, coffee: Arc<Mutex<CoffeeManager>>) {
+    let mut interval = tokio::time::interval(METRICS_REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        metrics.refresh(&coffee).await;
+    }
+}
+
+/// Middleware that records the latency of the requests.
+pub async fn track(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(metrics) = request.app_data::<web::Data<Metrics>>().cloned() else {
+        return next.call(request).await;
+    };
+    let method = request.method().to_string();
+    // the pattern of the route keeps the labels bounded,
+    // e.g. `/jobs/{id}` for every job.
+    let path = request.match_pattern().unwrap_or(String::from("unmatched"));
+    let start = Instant::now();
+    let response = next.call(request).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(err) => err.as_response_error().status_code(),
+    };
+    metrics
+        .request_duration
+        .with_label_values(&[method.as_str(), path.as_str(), status.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
@@ -7,20 +7,35 @@
 //! coffee core crate! The goal is to give an web
 //! interface to interact with coffee.
 use std::collections::HashMap;
-use std::net::ToSocketAddrs;
+use std::os::unix::fs::FileTypeExt;
 use std::sync::Arc;
+use std::time::Duration;
 
+use rustls::ServerConfig;
 use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
 
+use super::auth::{self, Auth};
+use super::dashboard;
+use super::error::{self, HttpdError};
+use super::jobs::{self, JobQueue};
 use super::macros::handle_httpd_response;
+use super::metrics::{self, Metrics};
+use super::v2;
+use coffee_core::backup;
 use coffee_core::coffee::CoffeeManager;
+use coffee_core::progress::Progress;
+use coffee_lib::build::BuildCanceller;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
 use coffee_lib::plugin_manager::PluginManager;
 use coffee_lib::types::request::*;
+use coffee_lib::types::response::{Caller, JobKind};
 
-use actix_web::{App, HttpResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpRequest, HttpResponse};
 use actix_web::{Error, HttpServer};
-use paperclip::actix::HttpResponseWrapper;
 use paperclip::actix::{
     api_v2_operation,
     // If you prefer the macro syntax for defining routes, import the paperclip macros
@@ -34,24 +49,92 @@ use paperclip::actix::{
 };
 
 // This struct represents state
-struct AppState {
-    #[allow(dead_code)]
-    coffee: Arc<Mutex<CoffeeManager>>,
+pub(crate) struct AppState {
+    pub(crate) coffee: Arc<Mutex<CoffeeManager>>,
+    /// Handle to cancel the running builds, it lives outside
+    /// the mutex because the build is holding the lock.
+    canceller: BuildCanceller,
+    /// Queue of the long running operations.
+    jobs: Arc<JobQueue>,
+    /// Progress of the running operations, it lives outside
+    /// the mutex so the events are streamed while coffee is busy.
+    progress: Progress,
+}
+
+/// Default number of history entries in a page.
+const HISTORY_PAGE_SIZE: usize = 50;
+/// Maximum number of history entries in a page.
+const HISTORY_MAX_PAGE_SIZE: usize = 500;
+/// Interval of the comments that keep the event stream alive
+/// when there are no events.
+const EVENTS_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Who is calling the httpd, stored inside the coffee history.
+fn caller(request: &HttpRequest) -> Caller {
+    Caller::Httpd(request.peer_addr().map(|addr| addr.to_string()))
+}
+
+/// Put the job inside the queue, and return it without waiting,
+/// the client follows the job with `/jobs/{id}`.
+fn submit_job(
+    data: &AppState,
+    request: &HttpRequest,
+    kind: JobKind,
+) -> Result<HttpResponse, HttpdError> {
+    let job = data.jobs.submit(kind, caller(request))?;
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+/// Address where the httpd accepts the connections.
+#[derive(Clone, Debug)]
+pub enum Bind {
+    /// Host (IPv4 or IPv6 address, or name) and port.
+    Tcp(String, u16),
+    /// Path of a unix domain socket.
+    Unix(String),
 }
 
 /// entry point of the httpd to allow
 /// run the server
-pub async fn run_httpd<T: ToSocketAddrs>(
+pub async fn run_httpd(
     coffee: CoffeeManager,
-    host: T,
+    binds: &[Bind],
+    tls: Option<ServerConfig>,
+    auth: Auth,
+    jobs: JobQueue,
 ) -> Result<(), std::io::Error> {
+    let canceller = coffee.canceller.clone();
+    let progress = coffee.progress.clone();
+    let metrics = Metrics::new(coffee.cln_errors.clone())
+        .map_err(|err| std::io::Error::other(err.message().to_owned()))?;
+    let metrics = Arc::new(metrics);
     let rc = Arc::new(Mutex::new(coffee));
-    HttpServer::new(move || {
-        let state = AppState { coffee: rc.clone() };
+    let jobs = Arc::new(jobs);
+    actix_web::rt::spawn(jobs::run_worker(jobs.clone(), rc.clone(), metrics.clone()));
+    actix_web::rt::spawn(metrics::run_refresher(metrics.clone(), rc.clone()));
+    let auth = web::Data::new(auth);
+    let mut server = HttpServer::new(move || {
+        let state = AppState {
+            coffee: rc.clone(),
+            canceller: canceller.clone(),
+            jobs: jobs.clone(),
+            progress: progress.clone(),
+        };
         App::new()
             .app_data(web::Data::new(state))
+            .app_data(auth.clone())
+            .app_data(web::Data::from(metrics.clone()))
+            .app_data(web::JsonConfig::default().error_handler(error::json_error))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error))
+            .app_data(web::PathConfig::default().error_handler(error::path_error))
+            .wrap(from_fn(auth::authorize))
+            .wrap(from_fn(metrics::track))
             .wrap_api()
-            .service(swagger_api)
+            .service(dashboard::index)
+            .service(dashboard::dashboard)
+            .service(dashboard::swagger_redirect)
+            .service(dashboard::swagger)
+            .service(dashboard::swagger_file)
             .service(coffee_help)
             .service(coffee_install)
             .service(coffee_remove)
@@ -64,12 +147,68 @@ pub async fn run_httpd<T: ToSocketAddrs>(
             .service(coffee_list_plugins_in_remote)
             .service(coffee_disable)
             .service(coffee_enable)
+            .service(coffee_start)
+            .service(coffee_stop)
+            .service(coffee_restart)
+            .service(coffee_cancel)
+            .service(coffee_history)
+            .service(coffee_install_many)
+            .service(coffee_status)
+            .service(coffee_upgrade)
+            .service(coffee_link)
+            .service(coffee_unlink)
+            .service(coffee_nurse)
+            .service(coffee_nurse_verify)
+            .service(coffee_nurse_plan)
+            .service(coffee_patch_repository_locally_absent)
+            .service(coffee_tip)
+            .service(coffee_config_get)
+            .service(coffee_config_set)
+            .service(coffee_config_unset)
+            .service(coffee_backup)
+            .service(coffee_restore)
+            .service(coffee_cache_list)
+            .service(coffee_cache_prune)
+            .service(coffee_jobs)
+            .service(coffee_job)
+            .service(coffee_job_log)
+            .service(coffee_job_cancel)
+            .service(coffee_events)
+            .service(coffee_metrics)
+            .service(v2::plugins)
+            .service(v2::plugin)
+            .service(v2::remotes)
+            .service(v2::remote)
+            .service(v2::remote_plugins)
+            .service(v2::remote_plugin)
+            .service(v2::search)
             .with_json_spec_at("/api/v1")
             .build()
-    })
-    .bind(host)?
-    .run()
-    .await?;
+    });
+    for bind in binds {
+        server = match bind {
+            Bind::Tcp(host, port) => {
+                let addr = (host.as_str(), *port);
+                match &tls {
+                    Some(tls) => server.bind_rustls_0_23(addr, tls.clone())?,
+                    None => server.bind(addr)?,
+                }
+            }
+            // the unix socket is protected by the file
+            // permissions, so it does not use TLS.
+            Bind::Unix(path) => {
+                // remove the socket left by a previous run
+                let stale = std::fs::metadata(path)
+                    .map(|metadata| metadata.file_type().is_socket())
+                    .unwrap_or(false);
+                if stale {
+                    std::fs::remove_file(path)?;
+                }
+                server.bind_uds(path)?
+            }
+        };
+    }
+    server.run().await?;
     Ok(())
 }
 
@@ -78,7 +217,7 @@ pub async fn run_httpd<T: ToSocketAddrs>(
 async fn coffee_help(
     _: web::Data<AppState>,
     body: Json<HashMap<String, String>>,
-) -> Result<Json<HashMap<String, String>>, Error> {
+) -> Result<Json<HashMap<String, String>>, HttpdError> {
     // FIXME: the json need to be a struct
     Ok(body)
 }
@@ -86,27 +225,42 @@ async fn coffee_help(
 #[api_v2_operation]
 #[post("/install")]
 async fn coffee_install(
+    request: HttpRequest,
     data: web::Data<AppState>,
     body: Json<Install>,
-) -> Result<HttpResponse, Error> {
-    let plugin = &body.plugin;
-    let try_dynamic = body.try_dynamic;
-
-    let mut coffee = data.coffee.lock().await;
-    let result = coffee.install(plugin, false, try_dynamic).await;
+) -> Result<HttpResponse, HttpdError> {
+    let kind = JobKind::Install {
+        plugins: vec![body.plugin.clone()],
+        try_dynamic: body.try_dynamic,
+    };
+    submit_job(&data, &request, kind)
+}
 
-    handle_httpd_response!(result, "Plugin '{plugin}' installed successfully")
+#[api_v2_operation]
+#[post("/install/many")]
+async fn coffee_install_many(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<InstallMany>,
+) -> Result<HttpResponse, HttpdError> {
+    let kind = JobKind::Install {
+        plugins: body.plugins.clone(),
+        try_dynamic: body.try_dynamic,
+    };
+    submit_job(&data, &request, kind)
 }
 
 #[api_v2_operation]
 #[post("/remove")]
 async fn coffee_remove(
+    request: HttpRequest,
     data: web::Data<AppState>,
     body: Json<Remove>,
-) -> Result<HttpResponse, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let plugin = &body.plugin;
 
     let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
     let result = coffee.remove(plugin).await;
 
     handle_httpd_response!(result, "Plugin '{plugin}' removed successfully")
@@ -114,36 +268,258 @@ async fn coffee_remove(
 
 #[api_v2_operation]
 #[get("/list")]
-async fn coffee_list(data: web::Data<AppState>) -> Result<Json<Value>, Error> {
+async fn coffee_list(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
     let mut coffee = data.coffee.lock().await;
     let result = coffee.list().await;
     handle_httpd_response!(result)
 }
 
 #[api_v2_operation]
-#[post("/remote/add")]
-async fn coffee_remote_add(
+#[get("/status")]
+async fn coffee_status(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.status().await;
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/upgrade")]
+async fn coffee_upgrade(
+    request: HttpRequest,
     data: web::Data<AppState>,
-    body: Json<RemoteAdd>,
-) -> Result<HttpResponse, Error> {
-    let repository_name = &body.repository_name;
-    let repository_url = &body.repository_url;
+    body: Json<Upgrade>,
+) -> Result<HttpResponse, HttpdError> {
+    let kind = JobKind::Upgrade {
+        repository_name: body.repository_name.clone(),
+    };
+    submit_job(&data, &request, kind)
+}
+
+#[api_v2_operation]
+#[post("/link")]
+async fn coffee_link(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Link>,
+) -> Result<Json<Value>, HttpdError> {
+    let cln_path = &body.cln_path;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.link(cln_path).await;
+
+    handle_httpd_response!(result, "Coffee linked to '{cln_path}' successfully")
+}
+
+#[api_v2_operation]
+#[post("/unlink")]
+async fn coffee_unlink(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Unlink>,
+) -> Result<Json<Value>, HttpdError> {
+    let cln_path = &body.cln_path;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.unlink(cln_path).await;
+
+    handle_httpd_response!(result, "Coffee unlinked from '{cln_path}' successfully")
+}
+
+#[api_v2_operation]
+#[post("/nurse")]
+async fn coffee_nurse(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Nurse>,
+) -> Result<HttpResponse, HttpdError> {
+    let kind = JobKind::Nurse {
+        ids: body.ids.clone(),
+    };
+    submit_job(&data, &request, kind)
+}
+
+#[api_v2_operation]
+#[get("/nurse/verify")]
+async fn coffee_nurse_verify(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    let coffee = data.coffee.lock().await;
+    let result = coffee.nurse_verify().await;
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/nurse/plan")]
+async fn coffee_nurse_plan(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    let coffee = data.coffee.lock().await;
+    let result = coffee.nurse_plan().await;
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/nurse/patch_repository_locally_absent")]
+async fn coffee_patch_repository_locally_absent(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<PatchRepositoryLocallyAbsent>,
+) -> Result<Json<Value>, HttpdError> {
+    let repositories = body.repositories.clone();
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.patch_repository_locally_absent(repositories).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/tip")]
+async fn coffee_tip(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Tip>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+    let amount_msat = body.amount_msat;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.tip(plugin, amount_msat).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/config")]
+async fn coffee_config_get(
+    data: web::Data<AppState>,
+    query: web::Query<ConfigGet>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &query.plugin;
+    let option = query.option.as_deref();
+
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.config_get(plugin, option).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/config/set")]
+async fn coffee_config_set(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<ConfigSet>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+    let option = &body.option;
+    let value = &body.value;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.config_set(plugin, option, value).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/config/unset")]
+async fn coffee_config_unset(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<ConfigUnset>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+    let option = &body.option;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.config_unset(plugin, option).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/backup")]
+async fn coffee_backup(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Backup>,
+) -> Result<Json<Value>, HttpdError> {
+    let with_plugins = body.with_plugins;
 
     let mut coffee = data.coffee.lock().await;
-    let result = coffee.add_remote(repository_name, repository_url).await;
+    let path = backup::archive_path(&coffee.config.root_path, &body.name).await?;
+    coffee.set_caller(caller(&request));
+    let result = coffee.backup(&path, with_plugins).await;
 
-    handle_httpd_response!(result, "Repository '{repository_name}' added successfully")
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/restore")]
+async fn coffee_restore(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Restore>,
+) -> Result<Json<Value>, HttpdError> {
+    let mut coffee = data.coffee.lock().await;
+    let path = backup::archive_path(&coffee.config.root_path, &body.name).await?;
+    coffee.set_caller(caller(&request));
+    let result = coffee.restore(&path).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/cache/list")]
+async fn coffee_cache_list(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.cache_list().await;
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/cache/prune")]
+async fn coffee_cache_prune(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<CachePrune>,
+) -> Result<Json<Value>, HttpdError> {
+    let all = body.all;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.cache_prune(all).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/remote/add")]
+async fn coffee_remote_add(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<RemoteAdd>,
+) -> Result<HttpResponse, HttpdError> {
+    let kind = JobKind::RemoteAdd {
+        repository_name: body.repository_name.clone(),
+        repository_url: body.repository_url.clone(),
+    };
+    submit_job(&data, &request, kind)
 }
 
 #[api_v2_operation]
 #[post("/remote/rm")]
 async fn coffee_remote_rm(
+    request: HttpRequest,
     data: web::Data<AppState>,
     body: Json<RemoteRm>,
-) -> Result<HttpResponse, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let repository_name = &body.repository_name;
 
     let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
     let result = coffee.rm_remote(repository_name).await;
 
     handle_httpd_response!(
@@ -154,7 +530,7 @@ async fn coffee_remote_rm(
 
 #[api_v2_operation]
 #[get("/remote/list")]
-async fn coffee_remote_list(data: web::Data<AppState>) -> Result<Json<Value>, Error> {
+async fn coffee_remote_list(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
     let mut coffee = data.coffee.lock().await;
     let result = coffee.list_remotes().await;
 
@@ -166,7 +542,7 @@ async fn coffee_remote_list(data: web::Data<AppState>) -> Result<Json<Value>, Er
 async fn coffee_list_plugins_in_remote(
     data: web::Data<AppState>,
     body: Json<RemotePluginsList>,
-) -> Result<Json<Value>, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let repository_name = &body.repository_name;
 
     let coffee = data.coffee.lock().await;
@@ -177,7 +553,10 @@ async fn coffee_list_plugins_in_remote(
 
 #[api_v2_operation]
 #[get("/show")]
-async fn coffee_show(data: web::Data<AppState>, body: Json<Show>) -> Result<Json<Value>, Error> {
+async fn coffee_show(
+    data: web::Data<AppState>,
+    body: Json<Show>,
+) -> Result<Json<Value>, HttpdError> {
     let plugin = &body.plugin;
 
     let mut coffee = data.coffee.lock().await;
@@ -191,7 +570,7 @@ async fn coffee_show(data: web::Data<AppState>, body: Json<Show>) -> Result<Json
 async fn coffee_search(
     data: web::Data<AppState>,
     body: Json<Search>,
-) -> Result<Json<Value>, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let plugin = &body.plugin;
 
     let mut coffee = data.coffee.lock().await;
@@ -203,12 +582,14 @@ async fn coffee_search(
 #[api_v2_operation]
 #[post("/disable")]
 async fn coffee_disable(
+    request: HttpRequest,
     data: web::Data<AppState>,
     body: Json<Disable>,
-) -> Result<HttpResponse, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let plugin = &body.plugin;
 
     let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
     let result = coffee.disable(plugin).await;
 
     handle_httpd_response!(result, "Plugin '{plugin}' disabled successfully")
@@ -217,56 +598,210 @@ async fn coffee_disable(
 #[api_v2_operation]
 #[post("/enable")]
 async fn coffee_enable(
+    request: HttpRequest,
     data: web::Data<AppState>,
     body: Json<Enable>,
-) -> Result<HttpResponse, Error> {
+) -> Result<Json<Value>, HttpdError> {
     let plugin = &body.plugin;
 
     let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
     let result = coffee.enable(plugin).await;
 
     handle_httpd_response!(result, "Plugin '{plugin}' enabled successfully")
 }
 
-// this is just a hack to support swagger UI with https://paperclip-rs.github.io/paperclip/
-// and the raw html is taken from https://github.com/swagger-api/swagger-ui/blob/master/docs/usage/installation.md#unpkg
-#[get("/")]
-async fn swagger_api() -> HttpResponseWrapper {
-    // FIXME: the url need to change here so we should support a better way
-    let resp = HttpResponse::Ok().body(
-        r#"
-<!DOCTYPE html>
-<html lang="en">
-  <head>
-    <meta charset="utf-8" />
-    <meta name="viewport" content="width=device-width, initial-scale=1" />
-    <meta
-      name="description"
-      content="SwaggerUI"
-    />
-    <title>SwaggerUI</title>
-    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@4.5.0/swagger-ui.css" />
-  </head>
-  <body>
-  <div id="swagger-ui"></div>
-  <script src="https://unpkg.com/swagger-ui-dist@4.5.0/swagger-ui-bundle.js" crossorigin></script>
-  <script src="https://unpkg.com/swagger-ui-dist@4.5.0/swagger-ui-standalone-preset.js" crossorigin></script>
-  <script>
-    window.onload = () => {
-      window.ui = SwaggerUIBundle({
-        url: 'http://localhost:8080/api/v1',
-        dom_id: '#swagger-ui',
-        presets: [
-          SwaggerUIBundle.presets.apis,
-          SwaggerUIStandalonePreset
-        ],
-        layout: "StandaloneLayout",
-      });
+#[api_v2_operation]
+#[post("/start")]
+async fn coffee_start(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Start>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.start(plugin).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/stop")]
+async fn coffee_stop(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Stop>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.stop(plugin).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/restart")]
+async fn coffee_restart(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<Restart>,
+) -> Result<Json<Value>, HttpdError> {
+    let plugin = &body.plugin;
+
+    let mut coffee = data.coffee.lock().await;
+    coffee.set_caller(caller(&request));
+    let result = coffee.restart(plugin).await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/cancel")]
+async fn coffee_cancel(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    // we do not take the coffee lock here, because the
+    // running build is holding it.
+    let cancelled = data.canceller.cancel() + data.jobs.cancel_builds();
+    let message = if cancelled == 0 {
+        String::from("No build running")
+    } else {
+        format!("{cancelled} build(s) cancelled")
     };
-  </script>
-  </body>
-</html>
-"#,
-    );
-    HttpResponseWrapper(resp)
+    Ok(Json(serde_json::json!({ "message": message })))
+}
+
+#[api_v2_operation]
+#[get("/jobs")]
+async fn coffee_jobs(data: web::Data<AppState>) -> Result<Json<Value>, HttpdError> {
+    let result: Result<_, CoffeeError> = Ok(data.jobs.list());
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/jobs/{id}")]
+async fn coffee_job(
+    data: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<Value>, HttpdError> {
+    let id = id.into_inner();
+    let Some(job) = data.jobs.get(id) else {
+        return Err(HttpdError::new(
+            StatusCode::NOT_FOUND,
+            ErrorKind::NotFound.code(),
+            &format!("job `{id}` not found"),
+        ));
+    };
+    let result: Result<_, CoffeeError> = Ok(job);
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/jobs/{id}/log")]
+async fn coffee_job_log(
+    data: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<Value>, HttpdError> {
+    let id = id.into_inner();
+    let Some(log) = data.jobs.log(id) else {
+        return Err(HttpdError::new(
+            StatusCode::NOT_FOUND,
+            ErrorKind::NotFound.code(),
+            &format!("job `{id}` not found"),
+        ));
+    };
+    let result: Result<_, CoffeeError> = Ok(log);
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[post("/jobs/{id}/cancel")]
+async fn coffee_job_cancel(
+    data: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<Value>, HttpdError> {
+    let result = data.jobs.cancel(id.into_inner());
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/events")]
+async fn coffee_events(
+    data: web::Data<AppState>,
+    query: web::Query<Events>,
+) -> Result<HttpResponse, HttpdError> {
+    let target = query.into_inner().target;
+    let receiver = data.progress.subscribe();
+    // every event is sent as a server-sent event, with the
+    // kind of the event and the event in json.
+    let stream = futures_util::stream::unfold(receiver, move |mut receiver| {
+        let target = target.clone();
+        async move {
+            loop {
+                let message = match tokio::time::timeout(EVENTS_KEEP_ALIVE, receiver.recv()).await {
+                    Err(_) => String::from(": keep-alive\n\n"),
+                    Ok(Ok(event)) => {
+                        if target
+                            .as_ref()
+                            .is_some_and(|target| target != event.target())
+                        {
+                            continue;
+                        }
+                        let Ok(json) = serde_json::to_string(&event) else {
+                            continue;
+                        };
+                        format!("event: {}\ndata: {json}\n\n", event.name())
+                    }
+                    Ok(Err(RecvError::Lagged(lost))) => {
+                        format!("event: lagged\ndata: {{\"lost\":{lost}}}\n\n")
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                };
+                return Some((Ok::<_, Error>(web::Bytes::from(message)), receiver));
+            }
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
+}
+
+#[api_v2_operation]
+#[get("/metrics")]
+async fn coffee_metrics(
+    data: web::Data<AppState>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, HttpdError> {
+    let metrics = metrics.render(&data.coffee).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics))
+}
+
+#[api_v2_operation]
+#[get("/history")]
+async fn coffee_history(
+    data: web::Data<AppState>,
+    query: web::Query<History>,
+) -> Result<Json<Value>, HttpdError> {
+    let page_size = query
+        .page_size
+        .unwrap_or(HISTORY_PAGE_SIZE)
+        .clamp(1, HISTORY_MAX_PAGE_SIZE);
+    let filter = HistoryFilter {
+        operation: query.operation.clone(),
+        target: query.target.clone(),
+        caller: query.caller.clone(),
+        failed: query.failed.unwrap_or(false),
+        offset: query.page.unwrap_or(0).saturating_mul(page_size),
+        limit: Some(page_size),
+    };
+
+    let coffee = data.coffee.lock().await;
+    let result = coffee.history(&filter).await;
+
+    handle_httpd_response!(result)
 }
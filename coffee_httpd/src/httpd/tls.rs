@@ -0,0 +1,141 @@
+//! TLS configuration of the httpd.
+//!
+//! The certificate and the key are read from PEM files, and they
+//! are read again when the daemon receives a SIGHUP, so the
+//! certificate can be renewed without restarting the daemon.
+//! When a client CA is given, the clients must present a
+//! certificate signed by it (mutual TLS).
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::CertificateDer;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use tokio::signal::unix::{signal, SignalKind};
+
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+
+/// Paths of the TLS files.
+#[derive(Clone, Debug)]
+pub struct TlsPaths {
+    pub cert: String,
+    pub key: String,
+    /// CA used to verify the client certificates.
+    pub client_ca: Option<String>,
+}
+
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, CoffeeError> {
+    let file = File::open(path).map_err(|err| error!("unable to open `{path}`: {err}"))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| error!("invalid certificate `{path}`: {err}"))?;
+    if certs.is_empty() {
+        return Err(error!("no certificate found inside `{path}`"));
+    }
+    Ok(certs)
+}
+
+fn read_certified_key(
+    paths: &TlsPaths,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, CoffeeError> {
+    let certs = read_certs(&paths.cert)?;
+    let key = &paths.key;
+    let file = File::open(key).map_err(|err| error!("unable to open `{key}`: {err}"))?;
+    let key_der = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| error!("invalid private key `{key}`: {err}"))?
+        .ok_or(error!("no private key found inside `{key}`"))?;
+    let key = provider
+        .key_provider
+        .load_private_key(key_der)
+        .map_err(|err| error!("unsupported private key `{key}`: {err}"))?;
+    let certified_key = CertifiedKey::new(certs, key);
+    // a renewed certificate with the old key (or the opposite)
+    // must not replace the working pair.
+    certified_key.keys_match().map_err(|err| {
+        error!(
+            "the private key `{}` does not match the certificate `{}`: {err}",
+            paths.key, paths.cert
+        )
+    })?;
+    Ok(certified_key)
+}
+
+/// Return the certificate that is currently loaded.
+#[derive(Debug)]
+pub struct CertResolver {
+    paths: TlsPaths,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    fn new(paths: TlsPaths, provider: Arc<CryptoProvider>) -> Result<Self, CoffeeError> {
+        let current = read_certified_key(&paths, &provider)?;
+        Ok(CertResolver {
+            paths,
+            provider,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Read again the certificate and the key, the current ones
+    /// are kept if the new files are not valid.
+    pub fn reload(&self) -> Result<(), CoffeeError> {
+        let key = read_certified_key(&self.paths, &self.provider)?;
+        let mut current = self.current.write().map_err(|err| error!("{err}"))?;
+        *current = Arc::new(key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|key| key.clone())
+    }
+}
+
+/// Build the TLS configuration of the server, and return the
+/// resolver used to reload the certificate.
+pub fn server_config(paths: &TlsPaths) -> Result<(ServerConfig, Arc<CertResolver>), CoffeeError> {
+    let provider = Arc::new(ring::default_provider());
+    let resolver = Arc::new(CertResolver::new(paths.clone(), provider.clone())?);
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| error!("{err}"))?;
+    let builder = match &paths.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(client_ca)? {
+                roots
+                    .add(cert)
+                    .map_err(|err| error!("invalid client CA `{client_ca}`: {err}"))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|err| error!("{err}"))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_cert_resolver(resolver.clone());
+    Ok((config, resolver))
+}
+
+/// Reload the certificate every time that the daemon receives a SIGHUP.
+pub fn reload_on_sighup(resolver: Arc<CertResolver>) -> Result<(), CoffeeError> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    actix_web::rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match resolver.reload() {
+                Ok(_) => log::info!("TLS certificate reloaded"),
+                Err(err) => log::warn!("TLS certificate not reloaded: {err}"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,293 @@
+//! Resource oriented API of the httpd.
+//!
+//! The endpoints of the first version read the arguments of the
+//! `GET` requests from a json body, which most of the http clients
+//! can not send. The `/v2` endpoints take the resource from the path
+//! and the filters from the query string, and they return a page of
+//! the items. The first version is kept for compatibility.
+use paperclip::actix::{
+    api_v2_operation, get,
+    web::{self, Json},
+};
+use serde_json::Value;
+
+use super::error::HttpdError;
+use super::macros::handle_httpd_response;
+use super::server::AppState;
+use coffee_lib::error;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::types::request::{Pagination, PluginsQuery};
+use coffee_lib::types::response::{
+    CoffeeListRemote, CoffeePage, CoffeePlugin, CoffeePluginDetails,
+};
+
+/// Default number of items in a page.
+const PAGE_SIZE: usize = 50;
+/// Maximum number of items in a page.
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Return the requested page of the items.
+fn paginate<T>(items: Vec<T>, page: Option<usize>, page_size: Option<usize>) -> CoffeePage<T> {
+    let page = page.unwrap_or(0);
+    let page_size = page_size.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let total = items.len();
+    let items = items
+        .into_iter()
+        .skip(page.saturating_mul(page_size))
+        .take(page_size)
+        .collect();
+    CoffeePage {
+        items,
+        total,
+        page,
+        page_size,
+    }
+}
+
+/// Check the plugin against the filters of the query.
+fn matches(query: &PluginsQuery, plugin: &CoffeePlugin) -> bool {
+    if let Some(q) = &query.q {
+        if !plugin
+            .plugin
+            .name()
+            .to_lowercase()
+            .contains(&q.to_lowercase())
+        {
+            return false;
+        }
+    }
+    if let Some(enabled) = query.enabled {
+        // the plugins installed before the flag existed are enabled.
+        if plugin.plugin.enabled.unwrap_or(true) != enabled {
+            return false;
+        }
+    }
+    if let Some(lang) = &query.lang {
+        if !plugin.plugin.lang.to_string().eq_ignore_ascii_case(lang) {
+            return false;
+        }
+    }
+    if let Some(repository) = &query.repository {
+        if plugin.repository.as_ref() != Some(repository) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Name of the repository that contains the plugin.
+fn repository_of(remotes: &[CoffeeListRemote], plugin: &Plugin) -> Option<String> {
+    remotes
+        .iter()
+        .find(|remote| remote.plugins.iter().any(|p| p.name() == plugin.name()))
+        .map(|remote| remote.local_name.clone())
+}
+
+fn plugins_page(query: &PluginsQuery, plugins: Vec<CoffeePlugin>) -> CoffeePage<CoffeePlugin> {
+    let plugins = plugins
+        .into_iter()
+        .filter(|plugin| matches(query, plugin))
+        .collect();
+    paginate(plugins, query.page, query.page_size)
+}
+
+#[api_v2_operation]
+#[get("/v2/plugins")]
+pub(crate) async fn plugins(
+    data: web::Data<AppState>,
+    query: web::Query<PluginsQuery>,
+) -> Result<Json<Value>, HttpdError> {
+    let mut coffee = data.coffee.lock().await;
+    let result: Result<_, CoffeeError> = async {
+        let remotes = coffee.list_remotes().await?.remotes.unwrap_or_default();
+        let plugins = coffee
+            .list()
+            .await?
+            .plugins
+            .into_iter()
+            .map(|plugin| CoffeePlugin {
+                repository: repository_of(&remotes, &plugin),
+                plugin,
+            })
+            .collect();
+        Ok(plugins_page(&query, plugins))
+    }
+    .await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/v2/plugins/{name}")]
+pub(crate) async fn plugin(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+) -> Result<Json<Value>, HttpdError> {
+    let name = name.into_inner();
+    let mut coffee = data.coffee.lock().await;
+    let result: Result<_, CoffeeError> = async {
+        let plugin = coffee
+            .list()
+            .await?
+            .plugins
+            .into_iter()
+            .find(|plugin| plugin.name() == name)
+            .ok_or_else(|| {
+                error!(
+                    kind = PluginNotFound,
+                    "No plugin with name `{name}` found in the plugins installed"
+                )
+            })?;
+        let remotes = coffee.list_remotes().await?.remotes.unwrap_or_default();
+        let repository = repository_of(&remotes, &plugin);
+        // the README is inside the repository, that can be removed
+        // while the plugin is still installed.
+        let (readme, options) = match coffee.show(&name).await {
+            Ok(show) => (Some(show.readme), show.options),
+            Err(err) if err.kind() == ErrorKind::PluginNotFound => (None, vec![]),
+            Err(err) => return Err(err),
+        };
+        Ok(CoffeePluginDetails {
+            plugin: CoffeePlugin { plugin, repository },
+            readme,
+            options,
+        })
+    }
+    .await;
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/v2/remotes")]
+pub(crate) async fn remotes(
+    data: web::Data<AppState>,
+    query: web::Query<Pagination>,
+) -> Result<Json<Value>, HttpdError> {
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.list_remotes().await.map(|remotes| {
+        let remotes = remotes.remotes.unwrap_or_default();
+        paginate(remotes, query.page, query.page_size)
+    });
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/v2/remotes/{name}")]
+pub(crate) async fn remote(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+) -> Result<Json<Value>, HttpdError> {
+    let name = name.into_inner();
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.list_remotes().await.and_then(|remotes| {
+        remotes
+            .remotes
+            .unwrap_or_default()
+            .into_iter()
+            .find(|remote| remote.local_name == name)
+            .ok_or_else(|| {
+                error!(
+                    kind = RepositoryNotFound,
+                    "repository with name: {name} not found"
+                )
+            })
+    });
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/v2/remotes/{name}/plugins")]
+pub(crate) async fn remote_plugins(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+    query: web::Query<PluginsQuery>,
+) -> Result<Json<Value>, HttpdError> {
+    let name = name.into_inner();
+    let coffee = data.coffee.lock().await;
+    let result = coffee.get_plugins_in_remote(&name).await.map(|list| {
+        let plugins = list
+            .plugins
+            .into_iter()
+            .map(|plugin| CoffeePlugin {
+                plugin,
+                repository: Some(name.clone()),
+            })
+            .collect();
+        plugins_page(&query, plugins)
+    });
+
+    handle_httpd_response!(result)
+}
+
+#[api_v2_operation]
+#[get("/v2/remotes/{name}/plugins/{plugin}")]
+pub(crate) async fn remote_plugin(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<Json<Value>, HttpdError> {
+    let (name, plugin) = path.into_inner();
+    let mut coffee = data.coffee.lock().await;
+    let result: Result<_, CoffeeError> = async {
+        let plugin = coffee
+            .get_plugins_in_remote(&name)
+            .await?
+            .plugins
+            .into_iter()
+            .find(|p| p.name() == plugin)
+            .ok_or_else(|| {
+                error!(
+                    kind = PluginNotFound,
+                    "plugin `{plugin}` is not present inside the repository `{name}`"
+                )
+            })?;
+        let show = coffee.show(&plugin.name()).await?;
+        Ok(CoffeePluginDetails {
+            plugin: CoffeePlugin {
+                plugin,
+                repository: Some(name),
+            },
+            readme: Some(show.readme),
+            options: show.options,
+        })
+    }
+    .await;
+
+    handle_httpd_response!(result)
+}
+
+/// Search the plugins inside all the repositories.
+#[api_v2_operation]
+#[get("/v2/search")]
+pub(crate) async fn search(
+    data: web::Data<AppState>,
+    query: web::Query<PluginsQuery>,
+) -> Result<Json<Value>, HttpdError> {
+    if !query.q.as_deref().is_some_and(|q| !q.trim().is_empty()) {
+        return Err(HttpdError::invalid_request(
+            "the `q` query parameter is required",
+        ));
+    }
+    let mut coffee = data.coffee.lock().await;
+    let result = coffee.list_remotes().await.map(|remotes| {
+        let plugins = remotes
+            .remotes
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|remote| {
+                let repository = remote.local_name;
+                remote.plugins.into_iter().map(move |plugin| CoffeePlugin {
+                    plugin,
+                    repository: Some(repository.clone()),
+                })
+            })
+            .collect();
+        plugins_page(&query, plugins)
+    });
+
+    handle_httpd_response!(result)
+}
@@ -1,23 +1,102 @@
 use clap::Parser;
 
+use coffee_core::backup::absolute_path;
 use coffee_core::coffee::CoffeeManager;
+use coffee_core::lock::{LockAccess, LockEndpoint};
+use coffee_core::token::TokenStore;
 use coffee_lib::errors::CoffeeError;
 use coffee_lib::macros::error;
 use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::types::response::Caller;
 
 mod cmd;
 pub mod httpd;
 
+use httpd::auth::Auth;
+use httpd::jobs::JobQueue;
+use httpd::tls;
+use httpd::Bind;
+
 #[actix_web::main]
 async fn main() -> Result<(), CoffeeError> {
     env_logger::init();
     let cmd = cmd::HttpdArgs::parse();
     let mut coffee = CoffeeManager::new(&cmd).await?;
-    coffee.link(&cmd.cln_path).await?;
+    coffee.set_caller(Caller::Httpd(None));
+    // the link is part of the startup, so it is not
+    // stored inside the history.
+    coffee.link_with_cln(&cmd.cln_path).await?;
+    coffee.flush().await?;
+    if !cmd.skip_verify {
+        let status = coffee.nurse_verify().await?;
+        if !status.is_sane() {
+            log::warn!("Coffee found some defects in the configuration, use the `/nurse` endpoint to fix them:\n{status}");
+        }
+    }
+
+    let auth = if cmd.no_auth {
+        log::warn!("The authentication is disabled, every request is allowed");
+        Auth::disabled()
+    } else {
+        let token_file = cmd
+            .token_file
+            .clone()
+            .unwrap_or(TokenStore::path(&coffee.config.root_path));
+        let tokens = TokenStore::load_or_create(&token_file)?;
+        let rune_rpc = match (&coffee.config.cln_root, cmd.rune_auth) {
+            (Some(root), true) => Some(format!("{root}/{}/lightning-rpc", coffee.config.network)),
+            (None, true) => {
+                return Err(error!(
+                    "The runes can not be validated without core lightning"
+                ))
+            }
+            _ => None,
+        };
+        Auth::new(tokens, rune_rpc)
+    };
+
+    let tls = match cmd.tls() {
+        Some(paths) => {
+            let (config, resolver) = tls::server_config(&paths)?;
+            tls::reload_on_sighup(resolver)?;
+            Some(config)
+        }
+        None => None,
+    };
 
-    let port = cmd.port.unwrap_or(8080) as u16;
-    log::info!("Running on port 127.0.0.1:{port}");
-    if let Err(err) = httpd::run_httpd(coffee, ("127.0.0.1", port)).await {
+    let jobs = JobQueue::load(&JobQueue::path(&coffee.config.root_path))?;
+
+    let binds = cmd.binds();
+    // the other coffee processes forward the commands
+    // to the first tcp address.
+    if let Some(Bind::Tcp(host, port)) = binds.first() {
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let host = match host.as_str() {
+            "0.0.0.0" => "127.0.0.1".to_owned(),
+            "::" => "[::1]".to_owned(),
+            host if host.contains(':') => format!("[{host}]"),
+            host => host.to_owned(),
+        };
+        // the paths are read by processes with another working directory.
+        let access = LockAccess {
+            token_file: cmd.token_file.as_deref().map(absolute_path).transpose()?,
+            tls_cert: match &tls {
+                Some(_) => cmd.tls_cert.as_deref().map(absolute_path).transpose()?,
+                None => None,
+            },
+        };
+        coffee.set_lock_endpoint(
+            LockEndpoint::Httpd(format!("{scheme}://{host}:{port}")),
+            access,
+        )?;
+    }
+    for bind in &binds {
+        match bind {
+            Bind::Tcp(host, port) => log::info!("Running on {host} port {port}"),
+            Bind::Unix(path) => log::info!("Running on the unix socket {path}"),
+        }
+    }
+    if let Err(err) = httpd::run_httpd(coffee, &binds, tls, auth, jobs).await {
         return Err(error!("Error while running the httpd: {err}"));
     }
 
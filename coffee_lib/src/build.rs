@@ -0,0 +1,403 @@
+//! Build module that runs the plugin install scripts
+//! under a set of resource limits, and allow to cancel
+//! them while they are running.
+use std::fmt;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::watch;
+
+use crate::errors::CoffeeError;
+use crate::macros::error;
+
+/// Resource limits applied to the install script of a plugin.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildLimits {
+    /// Wall clock time (in seconds) after which the build is killed.
+    pub timeout: Option<u64>,
+    /// Maximum virtual memory (in MB) that the build can allocate.
+    pub memory_limit: Option<u64>,
+    /// Maximum CPU time (in seconds) that the build can consume.
+    pub cpu_limit: Option<u64>,
+}
+
+impl BuildLimits {
+    /// Prepend to the script the `ulimit` calls that set the
+    /// rlimits of the shell, so every process spawned by the
+    /// script inherits them.
+    pub fn wrap_script(&self, script: &str) -> String {
+        let mut wrapped = String::new();
+        if let Some(memory) = self.memory_limit {
+            wrapped += &format!("ulimit -v {}\n", memory * 1024);
+        }
+        if let Some(cpu) = self.cpu_limit {
+            wrapped += &format!("ulimit -t {cpu}\n");
+        }
+        wrapped += script;
+        wrapped
+    }
+}
+
+/// Handle to cancel the builds that are running at
+/// the moment of the call.
+///
+/// The handle is cheap to clone, so it can be shared with
+/// whoever needs to cancel a build without holding
+/// the plugin manager (e.g. a signal handler or an http request).
+#[derive(Clone, Debug)]
+pub struct BuildCanceller {
+    generation: Arc<watch::Sender<u64>>,
+    running: Arc<AtomicUsize>,
+    /// Stop also the builds started after the cancel.
+    once: bool,
+}
+
+impl Default for BuildCanceller {
+    fn default() -> Self {
+        let (generation, _) = watch::channel(0);
+        BuildCanceller {
+            generation: Arc::new(generation),
+            running: Arc::new(AtomicUsize::new(0)),
+            once: false,
+        }
+    }
+}
+
+impl BuildCanceller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Canceller of a single operation (e.g. a job of the httpd),
+    /// once it is cancelled no other build can start.
+    pub fn once() -> Self {
+        BuildCanceller {
+            once: true,
+            ..Self::default()
+        }
+    }
+
+    /// Cancel all the builds that are running, return
+    /// the number of builds that are cancelled.
+    pub fn cancel(&self) -> usize {
+        self.generation.send_modify(|generation| *generation += 1);
+        self.running()
+    }
+
+    /// Return the number of builds that are running.
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running() > 0
+    }
+}
+
+/// Keep track of a running build, and decrement the
+/// counter when the build is finished (in any way).
+struct RunningGuard(Arc<AtomicUsize>);
+
+impl RunningGuard {
+    fn new(running: Arc<AtomicUsize>) -> Self {
+        running.fetch_add(1, Ordering::SeqCst);
+        RunningGuard(running)
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Receive the lines printed by an install script.
+#[derive(Clone)]
+pub struct OutputSink(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl OutputSink {
+    pub fn new(sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        OutputSink(Arc::new(sink))
+    }
+
+    pub fn send(&self, line: &str) {
+        (self.0)(line)
+    }
+}
+
+impl fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OutputSink")
+    }
+}
+
+/// All the information needed to run an install script.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    pub limits: BuildLimits,
+    pub canceller: BuildCanceller,
+    /// Where the output of the script is sent while it runs,
+    /// the output is not captured in verbose mode.
+    pub output: Option<OutputSink>,
+}
+
+impl BuildOptions {
+    pub fn new(limits: BuildLimits, canceller: BuildCanceller) -> Self {
+        BuildOptions {
+            limits,
+            canceller,
+            output: None,
+        }
+    }
+
+    pub fn with_output(mut self, output: OutputSink) -> Self {
+        self.output = Some(output);
+        self
+    }
+}
+
+/// Read the output of the script line by line, sending every
+/// line to the sink, and return the whole output.
+async fn read_output<R: AsyncRead + Unpin>(reader: Option<R>, sink: Option<&OutputSink>) -> String {
+    let mut output = String::new();
+    let Some(reader) = reader else {
+        return output;
+    };
+    let mut reader = BufReader::new(reader);
+    let mut line = vec![];
+    // the output is read until the end, even when it is not valid
+    // utf8, otherwise the script blocks on a full pipe.
+    while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+        if read == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+        if let Some(sink) = sink {
+            sink.send(text);
+        }
+        output.push_str(text);
+        output.push('\n');
+        line.clear();
+    }
+    output
+}
+
+/// Kill all the processes inside the process group
+/// of the build.
+async fn kill_build(pid: Option<u32>) {
+    let Some(pid) = pid else {
+        return;
+    };
+    log::debug!("killing the process group {pid}");
+    let result = Command::new("kill")
+        .args(["-s", "KILL", "--", &format!("-{pid}")])
+        .output()
+        .await;
+    if let Err(err) = result {
+        log::warn!("unable to kill the build with pid {pid}: {err}");
+    }
+}
+
+/// Run an install script inside the `root` directory,
+/// applying the limits defined in the `BuildOptions`.
+///
+/// The script runs in its own process group so on timeout
+/// or cancellation all the processes spawned by it are killed.
+pub async fn run_script(
+    root: &str,
+    script: &str,
+    verbose: bool,
+    options: &BuildOptions,
+) -> Result<(), CoffeeError> {
+    let script = options.limits.wrap_script(script.trim());
+    log::debug!("script: {:?}", script);
+
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", &script]);
+    cmd.current_dir(root);
+    cmd.kill_on_drop(true);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    if !verbose {
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+    }
+
+    let mut cancelled = options.canceller.generation.subscribe();
+    if options.canceller.once && *cancelled.borrow() > 0 {
+        return Err(error!("build cancelled"));
+    }
+    let _guard = RunningGuard::new(options.canceller.running.clone());
+    let mut child = cmd
+        .spawn()
+        .map_err(|err| error!("Unable to run the command: {err}"))?;
+    let pid = child.id();
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let sink = options.output.as_ref();
+    let output = async {
+        let (status, stdout, stderr) = tokio::join!(
+            child.wait(),
+            read_output(stdout, sink),
+            read_output(stderr, sink),
+        );
+        status.map(|status| (status, stdout, stderr))
+    };
+
+    let timeout = options.limits.timeout;
+    let (status, stdout, stderr) = tokio::select! {
+        output = output => output?,
+        _ = async {
+            match timeout {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending::<()>().await,
+            }
+        } => {
+            kill_build(pid).await;
+            return Err(error!(
+                "build timed out after {} seconds",
+                timeout.unwrap_or_default()
+            ));
+        }
+        _ = async {
+            // if the canceller is gone, nobody can cancel
+            // the build anymore.
+            if cancelled.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        } => {
+            kill_build(pid).await;
+            return Err(error!("build cancelled"));
+        }
+    };
+
+    if !status.success() {
+        let mut content = stderr;
+        if content.trim().is_empty() {
+            content = stdout;
+        }
+        if content.trim().is_empty() {
+            content = format!("build failed with {status}");
+        }
+        return Err(CoffeeError::new(2, &content));
+    }
+    Ok(())
+}
+
+/// Run a program with its arguments, without a shell, so the
+/// arguments (e.g. paths with spaces) are never interpreted.
+pub async fn run_command(program: &str, args: &[&str]) -> Result<(), CoffeeError> {
+    log::debug!("command: {program} {:?}", args);
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|err| error!("Unable to run `{program}`: {err}"))?;
+    if !output.status.success() {
+        let mut content = String::from_utf8_lossy(&output.stderr).to_string();
+        if content.trim().is_empty() {
+            content = String::from_utf8_lossy(&output.stdout).to_string();
+        }
+        if content.trim().is_empty() {
+            content = format!("`{program}` failed with {}", output.status);
+        }
+        return Err(CoffeeError::new(2, &content));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_wrap_script_with_limits() {
+        let limits = BuildLimits {
+            timeout: Some(10),
+            memory_limit: Some(512),
+            cpu_limit: Some(60),
+        };
+        let script = limits.wrap_script("make");
+        assert_eq!(script, "ulimit -v 524288\nulimit -t 60\nmake");
+        assert_eq!(BuildLimits::default().wrap_script("make"), "make");
+    }
+
+    #[tokio::test]
+    async fn test_build_timeout() {
+        let options = BuildOptions::new(
+            BuildLimits {
+                timeout: Some(1),
+                ..BuildLimits::default()
+            },
+            BuildCanceller::new(),
+        );
+        let start = Instant::now();
+        let result = run_script("/tmp", "sleep 30", false, &options).await;
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!options.canceller.is_running());
+    }
+
+    #[tokio::test]
+    async fn test_run_command_without_shell() {
+        let dir = std::env::temp_dir().join(format!("coffee run {}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        let path = format!("{dir}/a; touch injected");
+        run_command("mkdir", &["-p", &dir]).await.unwrap();
+        run_command("touch", &[&path]).await.unwrap();
+        assert!(std::path::Path::new(&path).exists());
+        assert!(!std::path::Path::new("injected").exists());
+        run_command("rm", &["-rf", &dir]).await.unwrap();
+        assert!(run_command("false", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_cancel() {
+        let options = BuildOptions::default();
+        let canceller = options.canceller.clone();
+        tokio::spawn(async move {
+            while !canceller.is_running() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            canceller.cancel();
+        });
+        let result = run_script("/tmp", "sleep 30", false, &options).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_cancel_once() {
+        let options = BuildOptions::new(BuildLimits::default(), BuildCanceller::once());
+        options.canceller.cancel();
+        let result = run_script("/tmp", "true", false, &options).await;
+        assert!(result.is_err());
+
+        let options = BuildOptions::default();
+        options.canceller.cancel();
+        let result = run_script("/tmp", "true", false, &options).await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_build_output() {
+        let lines = Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = {
+            let lines = lines.clone();
+            OutputSink::new(move |line| lines.lock().unwrap().push(line.to_owned()))
+        };
+        let options = BuildOptions::default().with_output(sink);
+        let result = run_script("/tmp", "echo hello; echo world >&2", false, &options).await;
+        assert!(result.is_ok(), "{:?}", result);
+        let mut lines = lines.lock().unwrap().clone();
+        lines.sort();
+        assert_eq!(lines, vec!["hello", "world"]);
+    }
+}
@@ -1,10 +1,66 @@
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
+/// Category of the error, that allows the clients
+/// to react to the error without parsing the message.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The plugin is not installed, or it is not inside the repositories.
+    PluginNotFound,
+    RepositoryNotFound,
+    /// Something else that does not exist (e.g. a job or a defect).
+    NotFound,
+    AlreadyInstalled,
+    /// A repository with the same name already exists.
+    AlreadyExists,
+    /// The operation is not allowed in the current state
+    /// (e.g. start a plugin that is already running).
+    Conflict,
+    /// The request is not valid (e.g. an option that the plugin does not declare).
+    InvalidRequest,
+    /// The install script of the plugin failed.
+    BuildFailed,
+    /// Core lightning can not be reached.
+    ClnUnavailable,
+    /// Coffee can not accept the operation now (e.g. the job queue is full).
+    Unavailable,
+    #[default]
+    Internal,
+}
+
+impl ErrorKind {
+    /// Stable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::PluginNotFound => "plugin_not_found",
+            ErrorKind::RepositoryNotFound => "repository_not_found",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::AlreadyInstalled => "already_installed",
+            ErrorKind::AlreadyExists => "already_exists",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::InvalidRequest => "invalid_request",
+            ErrorKind::BuildFailed => "build_failed",
+            ErrorKind::ClnUnavailable => "cln_unavailable",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
 /// Specific repository error.
 #[derive(Debug, Clone)]
 pub struct CoffeeError {
     code: u64,
     msg: String,
+    kind: ErrorKind,
 }
 
 impl CoffeeError {
@@ -14,8 +70,23 @@ impl CoffeeError {
         CoffeeError {
             code,
             msg: msg.to_string(),
+            kind: ErrorKind::default(),
         }
     }
+
+    /// Set the category of the error.
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
 }
 
 impl std::error::Error for CoffeeError {
@@ -40,18 +111,12 @@ impl fmt::Display for CoffeeError {
 
 impl From<std::io::Error> for CoffeeError {
     fn from(err: std::io::Error) -> Self {
-        CoffeeError {
-            code: 1,
-            msg: format!("{}", err),
-        }
+        CoffeeError::new(1, &format!("{}", err))
     }
 }
 
 impl From<String> for CoffeeError {
     fn from(value: String) -> Self {
-        CoffeeError {
-            code: 1,
-            msg: value,
-        }
+        CoffeeError::new(1, &value)
     }
 }
@@ -4,6 +4,7 @@
 //!
 //! author: Vincenzo Palazzo <vincenzopalazzodev@gmail.com>
 #![allow(dead_code)]
+pub mod build;
 pub mod cln_conf;
 pub mod errors;
 pub mod macros;
@@ -1,8 +1,14 @@
 //! Core macros implemented for coffee.
 
-/// return the Coffee Error
+/// return the Coffee Error, the category of the error
+/// can be given as first argument, e.g.
+/// `error!(kind = PluginNotFound, "plugin `{name}` not found")`.
 #[macro_export]
 macro_rules! error {
+    (kind = $kind:ident, $($msg:tt)*) => {{
+        let msg = format!($($msg)*);
+        CoffeeError::new(1, &msg).with_kind($crate::errors::ErrorKind::$kind)
+    }};
     ($($msg:tt)*) => {{
         let msg = format!($($msg)*);
         CoffeeError::new(1, &msg)
@@ -11,8 +17,15 @@ macro_rules! error {
 
 /// sh macro is the macro that allow to run a
 /// script as a sequence of commands.
+///
+/// When the build options are given, the script is
+/// run with the build limits and it can be cancelled.
 #[macro_export]
 macro_rules! sh {
+    ($root:expr, $script:expr, $verbose:expr, $options:expr) => {{
+        $crate::build::run_script(&$root, &$script, $verbose, $options).await?;
+    }};
+
     ($root: expr, $script:expr, $verbose:expr) => {{
         let script = $script.trim();
         log::debug!("script: {:?}", script);
@@ -1,14 +1,16 @@
 //! Plugin module that abstract the concept of a cln plugin
 //! from a plugin manager point of view.
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
+use std::path::Path;
 
 use log;
 use serde::{Deserialize, Serialize};
-use tokio::process::Command;
 
+use crate::build::BuildOptions;
 use crate::errors::CoffeeError;
 use crate::macros::error;
-use crate::plugin_conf::{Conf, Tipping};
+use crate::plugin_conf::{check_option_name, Conf, PluginOption, Tipping};
 use crate::sh;
 
 /// Plugin language definition
@@ -42,18 +44,65 @@ impl Display for PluginLang {
 }
 
 impl PluginLang {
+    /// Return the command that prints the version of the
+    /// toolchain used to build the plugin.
+    pub fn toolchain_version_cmd(&self) -> Option<&'static str> {
+        match self {
+            PluginLang::PyPip | PluginLang::PyPoetry => Some("python3 --version"),
+            PluginLang::Go => Some("go version"),
+            PluginLang::Rust => Some("cargo --version && rustc --version"),
+            PluginLang::Dart => Some("dart --version"),
+            PluginLang::JVM => Some("java -version"),
+            PluginLang::JavaScript | PluginLang::TypeScript => Some("node --version"),
+            PluginLang::Unknown => None,
+        }
+    }
+
+    /// Return true if the dependencies of the plugin are installed
+    /// inside the environment of the interpreter (e.g. by pip), and
+    /// not inside the plugin directory, so a copy of the plugin
+    /// directory does not contain them.
+    pub fn has_external_dependencies(&self) -> bool {
+        matches!(self, PluginLang::PyPip | PluginLang::PyPoetry)
+    }
+
+    /// Install the dependencies of a plugin that is already built
+    /// inside `path` (e.g. restored from the build cache), without
+    /// running the install script again.
+    pub async fn install_dependencies(
+        &self,
+        path: &str,
+        verbose: bool,
+        options: &BuildOptions,
+    ) -> Result<(), CoffeeError> {
+        let script = match self {
+            PluginLang::PyPip => "pip3 install -r requirements.txt --break-system-packages",
+            PluginLang::PyPoetry => "pip3 install -r requirements.txt",
+            _ => return Ok(()),
+        };
+        // the poetry plugins export the requirements during the
+        // build, so the built tree contains them too.
+        if !Path::new(&format!("{path}/requirements.txt")).exists() {
+            log::debug!("no requirements to install inside {path}");
+            return Ok(());
+        }
+        sh!(path, script, verbose, options);
+        Ok(())
+    }
+
     pub async fn default_install(
         &self,
         path: &str,
         name: &str,
         verbose: bool,
+        options: &BuildOptions,
     ) -> Result<String, CoffeeError> {
         match self {
             PluginLang::PyPip => {
                 /* 1. RUN PIP install or poetry install
                  * 2. return the path of the main file */
                 let script = "pip3 install -r requirements.txt --break-system-packages";
-                sh!(path, script, verbose);
+                sh!(path, script, verbose, options);
                 let main_file = format!("{path}/{name}.py");
                 Ok(main_file)
             }
@@ -61,7 +110,7 @@ impl PluginLang {
                 let mut script = "pip3 install poetry\n".to_string();
                 script += "poetry export -f requirements.txt --output requirements.txt\n";
                 script += "pip3 install -r requirements.txt";
-                sh!(path, script, verbose);
+                sh!(path, script, verbose, options);
                 Ok(format!("{path}/{name}.py"))
             }
             PluginLang::Go => Err(error!(
@@ -107,6 +156,8 @@ pub struct Plugin {
     // Optional for now to be backward compatible
     /// If the plugin is enabled or not
     pub enabled: Option<bool>,
+    /// Key of the build cache entry used to install the plugin
+    pub cache_key: Option<String>,
 }
 
 impl Plugin {
@@ -128,26 +179,34 @@ impl Plugin {
             conf: config,
             commit: commit_id,
             enabled,
+            cache_key: None,
         }
     }
 
     /// configure the plugin in order to work with cln.
     ///
     /// In case of success return the path of the executable.
-    pub async fn configure(&mut self, verbose: bool) -> Result<String, CoffeeError> {
+    ///
+    /// The install script is run with the limits contained
+    /// inside the build options.
+    pub async fn configure(
+        &mut self,
+        verbose: bool,
+        options: &BuildOptions,
+    ) -> Result<String, CoffeeError> {
         log::debug!("install plugin inside from root dir {}", self.root_path);
         let exec_path = if let Some(conf) = &self.conf {
             if let Some(script) = &conf.plugin.install {
-                sh!(self.root_path.clone(), script, verbose);
+                sh!(self.root_path.clone(), script, verbose, options);
                 self.exec_path.clone()
             } else {
                 self.lang
-                    .default_install(&self.root_path, &self.name, verbose)
+                    .default_install(&self.root_path, &self.name, verbose, options)
                     .await?
             }
         } else {
             self.lang
-                .default_install(&self.root_path, &self.name, verbose)
+                .default_install(&self.root_path, &self.name, verbose, options)
                 .await?
         };
         Ok(exec_path)
@@ -162,10 +221,38 @@ impl Plugin {
         self.name.clone()
     }
 
+    /// Return the install script declared inside the
+    /// coffee manifest, if any.
+    pub fn install_script(&self) -> Option<String> {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.install.clone())
+    }
+
     pub fn tipping_info(&self) -> Option<Tipping> {
         self.conf.as_ref().and_then(|conf| conf.tipping.clone())
     }
 
+    /// Return the options declared inside the coffee manifest.
+    ///
+    /// The options with an invalid or a reserved name are
+    /// ignored, so they never reach the coffee configuration.
+    pub fn options(&self) -> BTreeMap<String, PluginOption> {
+        let mut options = self
+            .conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.options.clone())
+            .unwrap_or_default();
+        options.retain(|name, _| match check_option_name(name) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!("plugin `{}` option ignored: {err}", self.name);
+                false
+            }
+        });
+        options
+    }
+
     pub fn important(&self) -> bool {
         if let Some(config) = &self.conf {
             if let Some(important) = config.plugin.important {
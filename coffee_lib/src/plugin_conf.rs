@@ -1,5 +1,12 @@
 //! Coffee configuration serialization file.
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::CoffeeError;
+use crate::macros::error;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Conf {
@@ -17,6 +24,115 @@ pub struct Plugin {
     pub install: Option<String>,
     pub main: String,
     pub important: Option<bool>,
+    /// Options supported by the plugin, by name.
+    pub options: Option<BTreeMap<String, PluginOption>>,
+}
+
+/// Option declared by the plugin inside the manifest.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct PluginOption {
+    #[serde(rename = "type")]
+    pub kind: OptionType,
+    pub default: Option<Value>,
+    pub description: Option<String>,
+    /// If the option can be changed at runtime with `setconfig`.
+    pub dynamic: Option<bool>,
+}
+
+/// Options of core lightning that a plugin can not declare, they
+/// would override the configuration of the node (or the plugins
+/// loaded by coffee) when the option is set.
+pub const RESERVED_OPTIONS: [&str; 24] = [
+    "plugin",
+    "important-plugin",
+    "disable-plugin",
+    "plugin-dir",
+    "clear-plugins",
+    "network",
+    "mainnet",
+    "testnet",
+    "signet",
+    "regtest",
+    "lightning-dir",
+    "conf",
+    "include",
+    "rpc-file",
+    "rpc-file-mode",
+    "daemon",
+    "wallet",
+    "log-file",
+    "addr",
+    "bind-addr",
+    "announce-addr",
+    "proxy",
+    "bitcoin-datadir",
+    "bitcoin-rpcpassword",
+];
+
+/// Check that the name declared by the plugin can be
+/// used as an option inside the coffee configuration.
+pub fn check_option_name(name: &str) -> Result<(), CoffeeError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(error!(
+            kind = InvalidRequest,
+            "`{name}` is not a valid option name, only `a-z`, `0-9` and `-` are allowed"
+        ));
+    }
+    if RESERVED_OPTIONS.contains(&name) {
+        return Err(error!(
+            kind = InvalidRequest,
+            "`{name}` is an option of core lightning and it can not be set by a plugin"
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    String,
+    Int,
+    Bool,
+}
+
+impl Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            OptionType::String => "string",
+            OptionType::Int => "int",
+            OptionType::Bool => "bool",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+impl OptionType {
+    /// Check that the value can be used for an option of this type.
+    pub fn validate(&self, value: &str) -> Result<(), CoffeeError> {
+        // every option is a line of the configuration.
+        if value.contains(['\n', '\r']) {
+            return Err(error!(
+                kind = InvalidRequest,
+                "the value of an option can not contain a new line"
+            ));
+        }
+        let valid = match self {
+            OptionType::String => true,
+            OptionType::Int => value.parse::<i64>().is_ok(),
+            OptionType::Bool => value == "true" || value == "false",
+        };
+        if !valid {
+            return Err(error!(
+                kind = InvalidRequest,
+                "value `{value}` is not a valid {self}"
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -28,3 +144,40 @@ pub struct Deprecaterd {
 pub struct Tipping {
     pub bolt12: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_type_validate() {
+        assert!(OptionType::String.validate("USD").is_ok());
+        assert!(OptionType::Int.validate("42").is_ok());
+        assert!(OptionType::Int.validate("USD").is_err());
+        assert!(OptionType::Bool.validate("true").is_ok());
+        assert!(OptionType::Bool.validate("yes").is_err());
+        let err = OptionType::Int.validate("USD").unwrap_err();
+        assert_eq!(err.kind(), crate::errors::ErrorKind::InvalidRequest);
+    }
+
+    #[test]
+    fn test_option_value_with_new_line() {
+        assert!(OptionType::String
+            .validate("USD\nplugin=/tmp/evil")
+            .is_err());
+        assert!(OptionType::String.validate("USD\r").is_err());
+    }
+
+    #[test]
+    fn test_check_option_name() {
+        assert!(check_option_name("summary-currency").is_ok());
+        assert!(check_option_name("plugin").is_err());
+        assert!(check_option_name("important-plugin").is_err());
+        assert!(check_option_name("disable-plugin").is_err());
+        assert!(check_option_name("network").is_err());
+        assert!(check_option_name("").is_err());
+        assert!(check_option_name("Summary").is_err());
+        assert!(check_option_name("summary=USD").is_err());
+        assert!(check_option_name("summary\nplugin").is_err());
+    }
+}
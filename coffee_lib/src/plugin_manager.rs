@@ -1,6 +1,7 @@
 //! Plugin manager module definition.
 use async_trait::async_trait;
 
+use crate::types::request::HistoryFilter;
 use crate::{errors::CoffeeError, types::response::*};
 
 /// Plugin manager traits that define the API a generic
@@ -18,12 +19,61 @@ pub trait PluginManager {
         try_dynamic: bool,
     ) -> Result<(), CoffeeError>;
 
+    /// install multiple plugins by name, building them concurrently.
+    ///
+    /// A failure in one plugin does not abort the install of the others,
+    /// the result contains the status of each plugin.
+    async fn install_many(
+        &mut self,
+        plugins: &[String],
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> Result<CoffeeInstall, CoffeeError>;
+
     // remove a plugin by name, return an error if some error happens.
     async fn remove(&mut self, plugin: &str) -> Result<CoffeeRemove, CoffeeError>;
 
     /// return the list of plugins installed by the plugin manager.
     async fn list(&mut self) -> Result<CoffeeList, CoffeeError>;
 
+    /// return the state of the plugins inside core lightning, including
+    /// the plugins that are running but not managed by the plugin manager.
+    async fn status(&mut self) -> Result<CoffeeStatus, CoffeeError>;
+
+    /// start an installed plugin inside core lightning.
+    async fn start(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError>;
+
+    /// stop an installed plugin that is running inside core lightning.
+    async fn stop(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError>;
+
+    /// restart an installed plugin, so the last build of the plugin
+    /// is used without restarting core lightning.
+    async fn restart(&mut self, plugin: &str) -> Result<PluginStatus, CoffeeError>;
+
+    /// return the options of an installed plugin with the configured
+    /// values, or only the option with the given name.
+    async fn config_get(
+        &mut self,
+        plugin: &str,
+        option: Option<&str>,
+    ) -> Result<CoffeeConfig, CoffeeError>;
+
+    /// set the value of a plugin option, the value is validated against
+    /// the options declared by the plugin.
+    async fn config_set(
+        &mut self,
+        plugin: &str,
+        option: &str,
+        value: &str,
+    ) -> Result<CoffeeConfig, CoffeeError>;
+
+    /// remove the value of a plugin option, so the plugin uses the default one.
+    async fn config_unset(
+        &mut self,
+        plugin: &str,
+        option: &str,
+    ) -> Result<CoffeeConfig, CoffeeError>;
+
     /// upgrade a single or multiple repositories.
     async fn upgrade(&mut self, repo: &str, verbose: bool) -> Result<CoffeeUpgrade, CoffeeError>;
 
@@ -57,6 +107,20 @@ pub trait PluginManager {
     /// verify that coffee configuration is sane without taking any action.
     async fn nurse_verify(&self) -> Result<ChainOfResponsibilityStatus, CoffeeError>;
 
+    /// return the repairs that nurse would apply, without applying them.
+    async fn nurse_plan(&self) -> Result<CoffeeNursePlan, CoffeeError>;
+
+    /// apply only the repairs with the given ids.
+    async fn nurse_apply(&mut self, ids: &[String]) -> Result<CoffeeNurse, CoffeeError>;
+
+    /// store the coffee state inside a backup archive.
+    async fn backup(&mut self, path: &str, with_plugins: bool)
+        -> Result<CoffeeBackup, CoffeeError>;
+
+    /// restore the coffee state from a backup archive, cloning the
+    /// repositories and building the plugins that are missing.
+    async fn restore(&mut self, path: &str) -> Result<CoffeeRestore, CoffeeError>;
+
     /// patch coffee configuration in the case that a repository is present in the coffee
     /// configuration but is absent from the local storage.
     async fn patch_repository_locally_absent(
@@ -72,9 +136,20 @@ pub trait PluginManager {
     /// P.S: only Bitcoin ofc
     async fn tip(&mut self, plugin: &str, amount_msat: u64) -> Result<CoffeeTip, CoffeeError>;
 
+    /// show the content of the build cache.
+    async fn cache_list(&mut self) -> Result<CoffeeCache, CoffeeError>;
+
+    /// remove from the build cache the entries that are not used
+    /// by any installed plugin, or all the entries if `all` is true.
+    async fn cache_prune(&mut self, all: bool) -> Result<CoffeeCache, CoffeeError>;
+
     /// disable a plugin by name
     async fn disable(&mut self, plugin: &str) -> Result<(), CoffeeError>;
 
     /// enable a plugin by name
     async fn enable(&mut self, plugin: &str) -> Result<(), CoffeeError>;
+
+    /// return the operations that changed the coffee state,
+    /// newest first.
+    async fn history(&self, filter: &HistoryFilter) -> Result<CoffeeHistory, CoffeeError>;
 }
@@ -61,16 +61,250 @@ pub mod request {
     pub struct Enable {
         pub plugin: String,
     }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Start {
+        pub plugin: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Stop {
+        pub plugin: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Restart {
+        pub plugin: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct InstallMany {
+        pub plugins: Vec<String>,
+        pub try_dynamic: bool,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Upgrade {
+        pub repository_name: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Link {
+        /// Path of the core lightning root directory.
+        pub cln_path: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Unlink {
+        /// Path of the core lightning root directory.
+        pub cln_path: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Nurse {
+        /// Ids of the repairs to apply, all the repairs
+        /// are applied when it is empty.
+        #[serde(default)]
+        pub ids: Vec<String>,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct PatchRepositoryLocallyAbsent {
+        pub repositories: Vec<String>,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Tip {
+        pub plugin: String,
+        pub amount_msat: u64,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct ConfigGet {
+        pub plugin: String,
+        pub option: Option<String>,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct ConfigSet {
+        pub plugin: String,
+        pub option: String,
+        pub value: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct ConfigUnset {
+        pub plugin: String,
+        pub option: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Backup {
+        /// Name of the archive, stored inside `~/.coffee/backups`
+        /// on the host of the daemon.
+        pub name: String,
+        pub with_plugins: bool,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Restore {
+        /// Name of the archive inside `~/.coffee/backups`
+        /// on the host of the daemon.
+        pub name: String,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct CachePrune {
+        /// Remove also the builds used by the installed plugins.
+        pub all: bool,
+    }
+
+    /// Page of the history, with the same filters of `coffee history`.
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct History {
+        pub operation: Option<String>,
+        pub target: Option<String>,
+        pub caller: Option<String>,
+        pub failed: Option<bool>,
+        /// Page number, starting from 0.
+        pub page: Option<usize>,
+        pub page_size: Option<usize>,
+    }
+
+    /// Filter of the progress event stream.
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Events {
+        /// Receive only the events of the plugin or repository.
+        pub target: Option<String>,
+    }
+
+    /// Filter and page of the plugins returned by the `/v2` endpoints.
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Default, Deserialize, Apiv2Schema, Serialize)]
+    pub struct PluginsQuery {
+        /// Part of the plugin name, case insensitive.
+        pub q: Option<String>,
+        pub enabled: Option<bool>,
+        /// Language of the plugin (e.g. `python`).
+        pub lang: Option<String>,
+        /// Name of the repository of the plugin.
+        pub repository: Option<String>,
+        /// Page number, starting from 0.
+        pub page: Option<usize>,
+        pub page_size: Option<usize>,
+    }
+
+    #[cfg(feature = "open-api")]
+    #[derive(Debug, Default, Deserialize, Apiv2Schema, Serialize)]
+    pub struct Pagination {
+        /// Page number, starting from 0.
+        pub page: Option<usize>,
+        pub page_size: Option<usize>,
+    }
+
+    /// Filter of the history entries.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct HistoryFilter {
+        /// Name of the operation (e.g. `install`).
+        pub operation: Option<String>,
+        /// Plugin or repository affected by the operation.
+        pub target: Option<String>,
+        /// Kind of the caller (`cli`, `httpd` or `plugin`).
+        pub caller: Option<String>,
+        /// Return only the failed operations.
+        pub failed: bool,
+        /// Number of entries to skip, newest first.
+        pub offset: usize,
+        /// Maximum number of entries to return.
+        pub limit: Option<usize>,
+    }
+
+    impl HistoryFilter {
+        pub fn matches(&self, entry: &super::response::HistoryEntry) -> bool {
+            if let Some(operation) = &self.operation {
+                if &entry.operation != operation {
+                    return false;
+                }
+            }
+            if let Some(target) = &self.target {
+                if entry.target.as_ref() != Some(target) {
+                    return false;
+                }
+            }
+            if let Some(caller) = &self.caller {
+                if entry.caller.kind() != caller {
+                    return false;
+                }
+            }
+            !self.failed || !entry.result.is_success()
+        }
+    }
 }
 
 // Definition of the response types.
 pub mod response {
+    use std::collections::BTreeMap;
     use std::fmt;
 
     use serde::{Deserialize, Serialize};
 
+    use crate::errors::CoffeeError;
     use crate::plugin::Plugin;
 
+    /// Result of the install of a single plugin.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PluginInstallStatus {
+        pub plugin: String,
+        /// Path of the plugin executable, when installed.
+        pub exec_path: Option<String>,
+        /// The reason of the failure, when not installed.
+        pub error: Option<String>,
+    }
+
+    impl PluginInstallStatus {
+        pub fn new(plugin: &str, result: Result<String, CoffeeError>) -> Self {
+            match result {
+                Ok(exec_path) => PluginInstallStatus {
+                    plugin: plugin.to_owned(),
+                    exec_path: Some(exec_path),
+                    error: None,
+                },
+                Err(err) => PluginInstallStatus {
+                    plugin: plugin.to_owned(),
+                    exec_path: None,
+                    error: Some(format!("{err}")),
+                },
+            }
+        }
+
+        pub fn is_installed(&self) -> bool {
+            self.error.is_none()
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeInstall {
+        pub plugins: Vec<PluginInstallStatus>,
+    }
+
     #[derive(Debug, Serialize, Deserialize)]
     pub struct CoffeeRemove {
         pub plugin: Plugin,
@@ -130,11 +364,68 @@ pub mod response {
         /// alterate we return the list of plugin
         /// that are effected and need to be recompiled.
         pub plugins_effected: Vec<String>,
+        /// The result of the reinstall of the plugins effected.
+        #[serde(default)]
+        pub plugins_status: Vec<PluginInstallStatus>,
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct CoffeeShow {
         pub readme: String,
+        /// Options declared by the plugin, with the current values.
+        #[serde(default)]
+        pub options: Vec<PluginOptionValue>,
+    }
+
+    /// Option of a plugin with the value configured by coffee.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PluginOptionValue {
+        pub name: String,
+        #[serde(rename = "type")]
+        pub kind: String,
+        pub default: Option<serde_json::Value>,
+        pub description: Option<String>,
+        /// Value written inside the coffee configuration, if any.
+        pub value: Option<String>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeConfig {
+        pub plugin: String,
+        pub options: Vec<PluginOptionValue>,
+        /// How the change is applied to the plugin, if
+        /// the options are changed.
+        #[serde(default)]
+        pub applied: Option<ConfigApplied>,
+    }
+
+    /// How the change of an option is applied to the plugin.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub enum ConfigApplied {
+        /// The option is changed in the running plugin with `setconfig`.
+        SetConfig,
+        /// The plugin is restarted with the new options.
+        Restarted,
+        /// The option is used the next time that core
+        /// lightning starts the plugin (with the reason).
+        NextStart(String),
+    }
+
+    impl fmt::Display for ConfigApplied {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ConfigApplied::SetConfig => {
+                    write!(f, "applied to the running plugin with setconfig")
+                }
+                ConfigApplied::Restarted => write!(f, "applied by restarting the plugin"),
+                ConfigApplied::NextStart(reason) => {
+                    write!(
+                        f,
+                        "will be applied at the next start of the plugin: {reason}"
+                    )
+                }
+            }
+        }
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -150,7 +441,248 @@ pub mod response {
         // A patch operation when a git repository is present in the coffee configuration
         // but is absent from the local storage.
         RepositoryLocallyAbsent(Vec<String>),
-        // TODO: Add more patch operations
+        // The coffee cln configuration contains `plugin` or `important-plugin`
+        // lines with an exec path of a plugin that is not installed.
+        PluginConfEntryUnknown(Vec<String>),
+        // A plugin installed and enabled is not present inside the
+        // coffee cln configuration.
+        PluginConfEntryMissing(Vec<String>),
+        // The `disable-plugin` lines of the coffee cln configuration
+        // do not match the plugins disabled.
+        DisablePluginEntryMismatch(Vec<String>),
+        // The coffee cln configuration contains the same line more than once.
+        DuplicatedConfEntries(Vec<String>),
+        // The directory of an installed plugin is missing, or its
+        // exec path is missing or not executable.
+        PluginDirectoryBroken(Vec<String>),
+        // The core lightning configuration does not include
+        // the configuration managed by coffee.
+        ClnConfigUnlinked(Vec<String>),
+        // The core lightning root directory or the network
+        // directory linked with coffee do not exist.
+        ClnPathMissing(Vec<String>),
+        // The core lightning rpc socket does not exist, so
+        // core lightning is not running.
+        ClnRpcSocketMissing(Vec<String>),
+        // The same option is declared by more than one installed plugin,
+        // so the plugins share the same key of the cln configuration.
+        ConflictingPluginOptions(Vec<String>),
+        // A defect found by a handler registered outside coffee.
+        External(ExternalDefect),
+    }
+
+    /// Defect found by a nurse handler that is registered
+    /// by the application that embeds coffee.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub struct ExternalDefect {
+        /// Unique identifier of the defect, used to apply the repair.
+        pub id: String,
+        pub description: String,
+        /// Description of the repair that the handler applies.
+        pub repair: String,
+        pub risk: RiskLevel,
+        pub affected: Vec<String>,
+    }
+
+    /// How much a nurse repair can change the node setup.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RiskLevel {
+        /// The repair only fixes the coffee configuration.
+        Low,
+        /// The repair changes files outside the coffee configuration.
+        Medium,
+        /// The repair can remove plugins, repositories or the link
+        /// with core lightning.
+        High,
+    }
+
+    impl fmt::Display for RiskLevel {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                RiskLevel::Low => write!(f, "low"),
+                RiskLevel::Medium => write!(f, "medium"),
+                RiskLevel::High => write!(f, "high"),
+            }
+        }
+    }
+
+    impl Defect {
+        /// Return true if coffee can not work correctly until the
+        /// defect is patched, otherwise the defect is only reported.
+        pub fn is_blocking(&self) -> bool {
+            !matches!(
+                self,
+                Defect::ClnRpcSocketMissing(_) | Defect::ConflictingPluginOptions(_)
+            )
+        }
+
+        /// Identifier of the category of the defect, it selects the
+        /// repairs of all the elements affected by the defect.
+        pub fn id(&self) -> String {
+            let id = match self {
+                Defect::RepositoryLocallyAbsent(_) => "repository-locally-absent",
+                Defect::PluginConfEntryUnknown(_) => "plugin-conf-entry-unknown",
+                Defect::PluginConfEntryMissing(_) => "plugin-conf-entry-missing",
+                Defect::DisablePluginEntryMismatch(_) => "disable-plugin-entry-mismatch",
+                Defect::DuplicatedConfEntries(_) => "duplicated-conf-entries",
+                Defect::PluginDirectoryBroken(_) => "plugin-directory-broken",
+                Defect::ClnConfigUnlinked(_) => "cln-config-unlinked",
+                Defect::ClnPathMissing(_) => "cln-path-missing",
+                Defect::ClnRpcSocketMissing(_) => "cln-rpc-socket-missing",
+                Defect::ConflictingPluginOptions(_) => "conflicting-plugin-options",
+                Defect::External(defect) => return defect.id.clone(),
+            };
+            id.to_owned()
+        }
+
+        pub fn description(&self) -> String {
+            let description = match self {
+                Defect::RepositoryLocallyAbsent(_) => "Repository missing locally",
+                Defect::PluginConfEntryUnknown(_) => "Plugin configured but not installed",
+                Defect::PluginConfEntryMissing(_) => "Plugin installed but not configured",
+                Defect::DisablePluginEntryMismatch(_) => "Plugin disabled state mismatch",
+                Defect::DuplicatedConfEntries(_) => "Duplicated configuration entries",
+                Defect::PluginDirectoryBroken(_) => "Plugin installation broken",
+                Defect::ClnConfigUnlinked(_) => "Coffee not included in the cln config",
+                Defect::ClnPathMissing(_) => "Core lightning path missing",
+                Defect::ClnRpcSocketMissing(_) => "Core lightning is not running",
+                Defect::ConflictingPluginOptions(_) => "Option declared by more than one plugin",
+                Defect::External(defect) => return defect.description.clone(),
+            };
+            description.to_owned()
+        }
+
+        /// Description of the repair that nurse applies for the defect.
+        pub fn repair(&self) -> String {
+            let repair = match self {
+                Defect::RepositoryLocallyAbsent(_) => {
+                    "Restore the repository with git, or remove it from coffee"
+                }
+                Defect::PluginConfEntryUnknown(_) => "Remove the lines from the coffee config",
+                Defect::PluginConfEntryMissing(_) => "Restore the lines in the coffee config",
+                Defect::DisablePluginEntryMismatch(_) => {
+                    "Rewrite the disable-plugin lines of the coffee config"
+                }
+                Defect::DuplicatedConfEntries(_) => "Remove the duplicated lines",
+                Defect::PluginDirectoryBroken(_) => {
+                    "Rebuild the plugin, or remove it if the sources are not available"
+                }
+                Defect::ClnConfigUnlinked(_) => "Include the coffee config in the cln config",
+                Defect::ClnPathMissing(_) => {
+                    "Link again to the configuration of the network, if found near the missing path"
+                }
+                Defect::ClnRpcSocketMissing(_) => "None, core lightning needs to be started",
+                Defect::ConflictingPluginOptions(_) => {
+                    "None, remove one of the plugins that declare the option"
+                }
+                Defect::External(defect) => return defect.repair.clone(),
+            };
+            repair.to_owned()
+        }
+
+        pub fn risk(&self) -> RiskLevel {
+            match self {
+                Defect::PluginConfEntryUnknown(_)
+                | Defect::PluginConfEntryMissing(_)
+                | Defect::DisablePluginEntryMismatch(_)
+                | Defect::DuplicatedConfEntries(_)
+                | Defect::ClnRpcSocketMissing(_)
+                | Defect::ConflictingPluginOptions(_) => RiskLevel::Low,
+                Defect::RepositoryLocallyAbsent(_)
+                | Defect::ClnConfigUnlinked(_)
+                | Defect::ClnPathMissing(_) => RiskLevel::Medium,
+                Defect::PluginDirectoryBroken(_) => RiskLevel::High,
+                Defect::External(defect) => defect.risk.clone(),
+            }
+        }
+
+        /// Return the elements affected by the defect.
+        pub fn affected(&self) -> &Vec<String> {
+            match self {
+                Defect::RepositoryLocallyAbsent(values)
+                | Defect::PluginConfEntryUnknown(values)
+                | Defect::PluginConfEntryMissing(values)
+                | Defect::DisablePluginEntryMismatch(values)
+                | Defect::DuplicatedConfEntries(values)
+                | Defect::PluginDirectoryBroken(values)
+                | Defect::ClnConfigUnlinked(values)
+                | Defect::ClnPathMissing(values)
+                | Defect::ClnRpcSocketMissing(values)
+                | Defect::ConflictingPluginOptions(values) => values,
+                Defect::External(defect) => &defect.affected,
+            }
+        }
+
+        fn affected_mut(&mut self) -> &mut Vec<String> {
+            match self {
+                Defect::RepositoryLocallyAbsent(values)
+                | Defect::PluginConfEntryUnknown(values)
+                | Defect::PluginConfEntryMissing(values)
+                | Defect::DisablePluginEntryMismatch(values)
+                | Defect::DuplicatedConfEntries(values)
+                | Defect::PluginDirectoryBroken(values)
+                | Defect::ClnConfigUnlinked(values)
+                | Defect::ClnPathMissing(values)
+                | Defect::ClnRpcSocketMissing(values)
+                | Defect::ConflictingPluginOptions(values) => values,
+                Defect::External(defect) => &mut defect.affected,
+            }
+        }
+
+        /// Identifier of the repair of a single element affected by
+        /// the defect (e.g. `plugin-directory-broken:summary`).
+        pub fn item_id(&self, item: &str) -> String {
+            match self {
+                // the external defects are repaired as a whole.
+                Defect::External(defect) => defect.id.clone(),
+                _ => format!("{}:{item}", self.id()),
+            }
+        }
+
+        /// Return the repair of each element affected by the defect.
+        pub fn repairs(&self) -> Vec<NurseRepair> {
+            let repair = |id: String, affected: Vec<String>| NurseRepair {
+                id,
+                defect: self.description(),
+                repair: self.repair(),
+                risk: self.risk(),
+                affected,
+            };
+            if matches!(self, Defect::External(_)) || self.affected().is_empty() {
+                return vec![repair(self.id(), self.affected().clone())];
+            }
+            self.affected()
+                .iter()
+                .map(|item| repair(self.item_id(item), vec![item.to_owned()]))
+                .collect()
+        }
+
+        /// Return true if the id selects the defect, or one
+        /// of the elements affected by it.
+        pub fn matches(&self, id: &str) -> bool {
+            self.id() == id || self.affected().iter().any(|item| self.item_id(item) == id)
+        }
+
+        /// Return the defect with only the elements selected by
+        /// the ids, or `None` if the ids do not select any of them.
+        pub fn select(&self, ids: &[String]) -> Option<Defect> {
+            if ids.contains(&self.id()) {
+                return Some(self.clone());
+            }
+            let affected: Vec<String> = self
+                .affected()
+                .iter()
+                .filter(|item| ids.contains(&self.item_id(item)))
+                .cloned()
+                .collect();
+            if affected.is_empty() {
+                return None;
+            }
+            let mut defect = self.clone();
+            *defect.affected_mut() = affected;
+            Some(defect)
+        }
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -162,6 +694,11 @@ pub mod response {
         pub fn is_sane(&self) -> bool {
             self.defects.is_empty()
         }
+
+        /// Return true if one of the defects blocks coffee.
+        pub fn is_blocked(&self) -> bool {
+            self.defects.iter().any(|defect| defect.is_blocking())
+        }
     }
 
     impl fmt::Display for ChainOfResponsibilityStatus {
@@ -171,14 +708,11 @@ pub mod response {
             } else {
                 writeln!(f, "Coffee has the following defects:")?;
                 for (i, defect) in self.defects.iter().enumerate() {
-                    match defect {
-                        Defect::RepositoryLocallyAbsent(repos) => {
-                            write!(f, "{}. Repository missing locally: ", i + 1)?;
-                            for repo in repos {
-                                write!(f, " {}", repo)?;
-                            }
-                        }
+                    write!(f, "{}. {}: ", i + 1, defect.description())?;
+                    for value in defect.affected() {
+                        write!(f, " {}", value)?;
                     }
+                    writeln!(f)?;
                 }
                 Ok(())
             }
@@ -192,6 +726,109 @@ pub mod response {
     pub enum NurseStatus {
         RepositoryLocallyRestored(Vec<String>),
         RepositoryLocallyRemoved(Vec<String>),
+        PluginConfEntryRemoved(Vec<String>),
+        PluginConfEntryRestored(Vec<String>),
+        DisablePluginEntryFixed(Vec<String>),
+        DuplicatedConfEntriesRemoved(Vec<String>),
+        PluginRebuilt(Vec<String>),
+        PluginRemoved(Vec<String>),
+        /// The plugin could not be rebuilt, but its sources are
+        /// available, so it is kept to be repaired again.
+        PluginRebuildFailed(Vec<String>),
+        ClnConfigRelinked(Vec<String>),
+        /// The core lightning configuration of the network was not
+        /// found, coffee needs to be linked again by the user.
+        ClnLinkNotRepaired(Vec<String>),
+        /// Repair applied by an external handler (action, affected elements).
+        External(String, Vec<String>),
+    }
+
+    impl NurseStatus {
+        /// Return the elements affected by the action.
+        pub fn affected(&self) -> &Vec<String> {
+            match self {
+                NurseStatus::RepositoryLocallyRestored(values)
+                | NurseStatus::RepositoryLocallyRemoved(values)
+                | NurseStatus::PluginConfEntryRemoved(values)
+                | NurseStatus::PluginConfEntryRestored(values)
+                | NurseStatus::DisablePluginEntryFixed(values)
+                | NurseStatus::DuplicatedConfEntriesRemoved(values)
+                | NurseStatus::PluginRebuilt(values)
+                | NurseStatus::PluginRemoved(values)
+                | NurseStatus::PluginRebuildFailed(values)
+                | NurseStatus::ClnConfigRelinked(values)
+                | NurseStatus::ClnLinkNotRepaired(values)
+                | NurseStatus::External(_, values) => values,
+            }
+        }
+
+        fn affected_mut(&mut self) -> &mut Vec<String> {
+            match self {
+                NurseStatus::RepositoryLocallyRestored(values)
+                | NurseStatus::RepositoryLocallyRemoved(values)
+                | NurseStatus::PluginConfEntryRemoved(values)
+                | NurseStatus::PluginConfEntryRestored(values)
+                | NurseStatus::DisablePluginEntryFixed(values)
+                | NurseStatus::DuplicatedConfEntriesRemoved(values)
+                | NurseStatus::PluginRebuilt(values)
+                | NurseStatus::PluginRemoved(values)
+                | NurseStatus::PluginRebuildFailed(values)
+                | NurseStatus::ClnConfigRelinked(values)
+                | NurseStatus::ClnLinkNotRepaired(values)
+                | NurseStatus::External(_, values) => values,
+            }
+        }
+
+        /// Return true if the two statuses report the same action.
+        fn same_action(&self, other: &NurseStatus) -> bool {
+            match (self, other) {
+                (NurseStatus::External(action, _), NurseStatus::External(other, _)) => {
+                    action == other
+                }
+                _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+            }
+        }
+
+        /// Position of the action inside the nurse report.
+        fn order(&self) -> usize {
+            match self {
+                NurseStatus::RepositoryLocallyRemoved(_) => 0,
+                NurseStatus::RepositoryLocallyRestored(_) => 1,
+                NurseStatus::PluginConfEntryRemoved(_) => 2,
+                NurseStatus::PluginConfEntryRestored(_) => 3,
+                NurseStatus::DisablePluginEntryFixed(_) => 4,
+                NurseStatus::DuplicatedConfEntriesRemoved(_) => 5,
+                NurseStatus::PluginRebuilt(_) => 6,
+                NurseStatus::PluginRemoved(_) => 7,
+                NurseStatus::PluginRebuildFailed(_) => 8,
+                NurseStatus::ClnConfigRelinked(_) => 9,
+                NurseStatus::ClnLinkNotRepaired(_) => 10,
+                NurseStatus::External(..) => 11,
+            }
+        }
+    }
+
+    /// Repair proposed by the nurse for a defect.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct NurseRepair {
+        /// Identifier used to apply the repair.
+        pub id: String,
+        pub defect: String,
+        pub repair: String,
+        pub risk: RiskLevel,
+        pub affected: Vec<String>,
+    }
+
+    /// The repairs that nurse would apply, without applying them.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeNursePlan {
+        pub repairs: Vec<NurseRepair>,
+    }
+
+    impl CoffeeNursePlan {
+        pub fn is_sane(&self) -> bool {
+            self.repairs.is_empty()
+        }
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -199,6 +836,26 @@ pub mod response {
         pub status: Vec<NurseStatus>,
     }
 
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeBackup {
+        /// Path of the backup archive.
+        pub path: String,
+        pub network: String,
+        /// Plugins installed at the moment of the backup.
+        pub plugins: Vec<String>,
+        /// The archive contains also the built plugins.
+        pub with_plugins: bool,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeRestore {
+        pub network: String,
+        /// Plugins installed after the restore.
+        pub plugins: Vec<String>,
+        /// Actions taken by the nurse to validate the restored state.
+        pub nurse: CoffeeNurse,
+    }
+
     impl CoffeeNurse {
         pub fn is_sane(&self) -> bool {
             self.status.is_empty()
@@ -206,30 +863,18 @@ pub mod response {
 
         pub fn organize(&mut self) {
             // For every action taken by the nurse, we want to
-            // have 1 entry with the list of repositories affected.
+            // have 1 entry with the list of elements affected.
             let mut new_status: Vec<NurseStatus> = vec![];
-            let mut repositories_locally_removed: Vec<String> = vec![];
-            let mut repositories_locally_restored: Vec<String> = vec![];
-            for repo in self.status.iter() {
-                match repo {
-                    NurseStatus::RepositoryLocallyRemoved(repos) => {
-                        repositories_locally_removed.append(&mut repos.clone())
-                    }
-                    NurseStatus::RepositoryLocallyRestored(repos) => {
-                        repositories_locally_restored.append(&mut repos.clone())
-                    }
+            for status in self.status.iter() {
+                let same_action = new_status
+                    .iter_mut()
+                    .find(|action| action.same_action(status));
+                match same_action {
+                    Some(action) => action.affected_mut().append(&mut status.affected().clone()),
+                    None => new_status.push(status.clone()),
                 }
             }
-            if !repositories_locally_removed.is_empty() {
-                new_status.push(NurseStatus::RepositoryLocallyRemoved(
-                    repositories_locally_removed,
-                ));
-            }
-            if !repositories_locally_restored.is_empty() {
-                new_status.push(NurseStatus::RepositoryLocallyRestored(
-                    repositories_locally_restored,
-                ));
-            }
+            new_status.sort_by_key(|status| status.order());
             self.status = new_status;
         }
     }
@@ -243,10 +888,81 @@ pub mod response {
                 NurseStatus::RepositoryLocallyRemoved(val) => {
                     write!(f, "Repositories removed locally: {}", val.join(" "))
                 }
+                NurseStatus::PluginConfEntryRemoved(val) => {
+                    write!(
+                        f,
+                        "Plugins removed from the configuration: {}",
+                        val.join(" ")
+                    )
+                }
+                NurseStatus::PluginConfEntryRestored(val) => {
+                    write!(
+                        f,
+                        "Plugins restored in the configuration: {}",
+                        val.join(" ")
+                    )
+                }
+                NurseStatus::DisablePluginEntryFixed(val) => {
+                    write!(f, "Plugins disabled state fixed: {}", val.join(" "))
+                }
+                NurseStatus::DuplicatedConfEntriesRemoved(val) => {
+                    write!(
+                        f,
+                        "Duplicated configuration entries removed: {}",
+                        val.join(" ")
+                    )
+                }
+                NurseStatus::PluginRebuilt(val) => {
+                    write!(f, "Plugins rebuilt: {}", val.join(" "))
+                }
+                NurseStatus::PluginRemoved(val) => {
+                    write!(f, "Plugins removed: {}", val.join(" "))
+                }
+                NurseStatus::PluginRebuildFailed(val) => {
+                    write!(f, "Plugins not rebuilt, kept: {}", val.join(" "))
+                }
+                NurseStatus::ClnConfigRelinked(val) => {
+                    write!(f, "Coffee linked again to: {}", val.join(" "))
+                }
+                NurseStatus::ClnLinkNotRepaired(val) => {
+                    write!(
+                        f,
+                        "Core lightning configuration not found, run `coffee link` to link again: {}",
+                        val.join(" ")
+                    )
+                }
+                NurseStatus::External(action, val) => {
+                    write!(f, "{action}: {}", val.join(" "))
+                }
             }
         }
     }
 
+    /// Entry of the build cache, it contains a built
+    /// plugin that can be reused by other installs.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CacheEntry {
+        /// Content address of the entry.
+        pub key: String,
+        pub plugin: String,
+        pub lang: String,
+        /// Exec path relative to the plugin root directory.
+        pub exec_path: String,
+        /// Version of the toolchain used to build the plugin.
+        pub toolchain: String,
+        pub date: String,
+        /// Size on disk (in bytes).
+        pub size: u64,
+        /// If the entry is used by an installed plugin
+        /// in any network.
+        pub in_use: bool,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeCache {
+        pub entries: Vec<CacheEntry>,
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct CoffeeTip {
         pub for_plugin: String,
@@ -259,6 +975,334 @@ pub mod response {
         pub warning_partial_completion: Option<String>,
     }
 
+    /// State of a plugin from the core lightning point of view.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub enum PluginState {
+        /// The plugin is running inside core lightning.
+        Running,
+        /// The plugin is not running, and it is not expected to run
+        /// (disabled, or dynamic plugin stopped).
+        Stopped,
+        /// The plugin is configured to run with core lightning but
+        /// it is not running.
+        Crashed,
+        /// The plugin is running inside core lightning, but it is
+        /// not managed by coffee.
+        Unmanaged,
+    }
+
+    impl fmt::Display for PluginState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let state = match self {
+                PluginState::Running => "running",
+                PluginState::Stopped => "stopped",
+                PluginState::Crashed => "crashed",
+                PluginState::Unmanaged => "unmanaged",
+            };
+            write!(f, "{state}")
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PluginStatus {
+        pub name: String,
+        pub exec_path: String,
+        pub state: PluginState,
+        /// If the plugin is marked dynamic by core lightning.
+        pub dynamic: Option<bool>,
+        pub pid: Option<u32>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeStatus {
+        pub plugins: Vec<PluginStatus>,
+    }
+
+    /// Plugin as reported by the core lightning `plugin list` command.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ClnPlugin {
+        pub name: String,
+        pub active: bool,
+        pub dynamic: bool,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ClnPluginList {
+        pub plugins: Vec<ClnPlugin>,
+    }
+
+    /// Who asked coffee to run an operation.
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(tag = "kind", content = "address", rename_all = "lowercase")]
+    pub enum Caller {
+        /// The coffee command line.
+        #[default]
+        Cli,
+        /// The coffee httpd daemon, with the address of the client.
+        Httpd(Option<String>),
+        /// The coffee core lightning plugin.
+        Plugin,
+    }
+
+    impl Caller {
+        pub fn kind(&self) -> &str {
+            match self {
+                Caller::Cli => "cli",
+                Caller::Httpd(_) => "httpd",
+                Caller::Plugin => "plugin",
+            }
+        }
+    }
+
+    impl fmt::Display for Caller {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Caller::Httpd(Some(address)) => write!(f, "httpd ({address})"),
+                _ => write!(f, "{}", self.kind()),
+            }
+        }
+    }
+
+    /// Result of an operation stored inside the history.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(tag = "status", content = "error", rename_all = "lowercase")]
+    pub enum HistoryResult {
+        Success,
+        Failure(String),
+    }
+
+    impl HistoryResult {
+        pub fn is_success(&self) -> bool {
+            matches!(self, HistoryResult::Success)
+        }
+    }
+
+    impl<T> From<&Result<T, CoffeeError>> for HistoryResult {
+        fn from(value: &Result<T, CoffeeError>) -> Self {
+            match value {
+                Ok(_) => HistoryResult::Success,
+                Err(err) => HistoryResult::Failure(format!("{err}")),
+            }
+        }
+    }
+
+    /// Operation that changed the coffee state.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct HistoryEntry {
+        pub id: u64,
+        /// Unix timestamp of the operation.
+        pub timestamp: u64,
+        pub date: String,
+        pub operation: String,
+        /// Plugin or repository affected by the operation.
+        pub target: Option<String>,
+        pub arguments: BTreeMap<String, String>,
+        /// Commit of the target before the operation.
+        pub before: Option<String>,
+        /// Commit of the target after the operation.
+        pub after: Option<String>,
+        pub result: HistoryResult,
+        pub caller: Caller,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeHistory {
+        /// Entries that match the filter, newest first.
+        pub entries: Vec<HistoryEntry>,
+        /// Number of entries that match the filter,
+        /// before the pagination.
+        pub total: usize,
+    }
+
+    /// Page of the items returned by the `/v2` endpoints of the httpd.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeePage<T> {
+        pub items: Vec<T>,
+        /// Number of items that match the filter, before the pagination.
+        pub total: usize,
+        pub page: usize,
+        pub page_size: usize,
+    }
+
+    /// Plugin with the name of the repository that contains it.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeePlugin {
+        #[serde(flatten)]
+        pub plugin: Plugin,
+        pub repository: Option<String>,
+    }
+
+    /// Installed plugin with its README and its options.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeePluginDetails {
+        #[serde(flatten)]
+        pub plugin: CoffeePlugin,
+        /// `None` when the plugin is not inside the repositories anymore.
+        pub readme: Option<String>,
+        pub options: Vec<PluginOptionValue>,
+    }
+
+    /// Operation that runs in background inside a job of the httpd.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(tag = "operation", rename_all = "snake_case")]
+    pub enum JobKind {
+        Install {
+            plugins: Vec<String>,
+            try_dynamic: bool,
+        },
+        Upgrade {
+            repository_name: String,
+        },
+        RemoteAdd {
+            repository_name: String,
+            repository_url: String,
+        },
+        Nurse {
+            ids: Vec<String>,
+        },
+    }
+
+    impl JobKind {
+        /// Name of the operation, without its arguments.
+        pub fn operation(&self) -> &'static str {
+            match self {
+                JobKind::Install { .. } => "install",
+                JobKind::Upgrade { .. } => "upgrade",
+                JobKind::RemoteAdd { .. } => "remote_add",
+                JobKind::Nurse { .. } => "nurse",
+            }
+        }
+    }
+
+    impl fmt::Display for JobKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                JobKind::Install { plugins, .. } => write!(f, "install {}", plugins.join(" ")),
+                JobKind::Upgrade { repository_name } => write!(f, "upgrade {repository_name}"),
+                JobKind::RemoteAdd {
+                    repository_name, ..
+                } => write!(f, "remote add {repository_name}"),
+                JobKind::Nurse { ids } if ids.is_empty() => write!(f, "nurse"),
+                JobKind::Nurse { ids } => write!(f, "nurse {}", ids.join(" ")),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum JobState {
+        /// Waiting for the jobs before it.
+        Queued,
+        Running,
+        Succeeded,
+        Failed,
+        Cancelled,
+    }
+
+    impl JobState {
+        /// Return true if the job will not change anymore.
+        pub fn is_finished(&self) -> bool {
+            matches!(
+                self,
+                JobState::Succeeded | JobState::Failed | JobState::Cancelled
+            )
+        }
+    }
+
+    impl fmt::Display for JobState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let state = match self {
+                JobState::Queued => "queued",
+                JobState::Running => "running",
+                JobState::Succeeded => "succeeded",
+                JobState::Failed => "failed",
+                JobState::Cancelled => "cancelled",
+            };
+            write!(f, "{state}")
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct JobLogLine {
+        pub timestamp: u64,
+        pub message: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeJob {
+        pub id: u64,
+        pub kind: JobKind,
+        pub state: JobState,
+        pub caller: Caller,
+        pub created_at: u64,
+        pub started_at: Option<u64>,
+        pub finished_at: Option<u64>,
+        /// Set when the job was asked to stop while it was running.
+        #[serde(default)]
+        pub cancel_requested: bool,
+        /// Response of the operation, with the same format of
+        /// the response of the coffee command.
+        pub result: Option<serde_json::Value>,
+        pub error: Option<String>,
+        /// Code of the error, see [`ErrorResponse::code`].
+        #[serde(default)]
+        pub error_code: Option<String>,
+        #[serde(default)]
+        pub log: Vec<JobLogLine>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeJobs {
+        /// The jobs without their log, oldest first.
+        pub jobs: Vec<CoffeeJob>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CoffeeJobLog {
+        pub id: u64,
+        pub state: JobState,
+        pub log: Vec<JobLogLine>,
+    }
+
+    /// Body of the httpd answer when the request fails.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "open-api", derive(paperclip::actix::Apiv2Schema))]
+    pub struct ErrorResponse {
+        /// Stable code of the error, e.g. `plugin_not_found`,
+        /// the clients should match on it and not on the message.
+        pub code: String,
+        pub message: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub details: BTreeMap<String, String>,
+    }
+
+    impl ErrorResponse {
+        pub fn new(code: &str, message: &str) -> Self {
+            ErrorResponse {
+                code: code.to_owned(),
+                message: message.to_owned(),
+                details: BTreeMap::new(),
+            }
+        }
+
+        pub fn with_detail(mut self, key: &str, value: &str) -> Self {
+            self.details.insert(key.to_owned(), value.to_owned());
+            self
+        }
+    }
+
+    impl From<&CoffeeError> for ErrorResponse {
+        fn from(err: &CoffeeError) -> Self {
+            ErrorResponse::new(err.kind().code(), err.message())
+        }
+    }
+
+    impl fmt::Display for ErrorResponse {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}: {}", self.code, self.message)
+        }
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct PayResponse {
         pub payment_preimage: String,
@@ -28,6 +28,44 @@ pub async fn check_dir_or_make_if_missing(path: String) -> Result<(), CoffeeErro
     Ok(())
 }
 
+/// Compare two paths, resolving them when they are not equal.
+pub fn is_same_path(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Look for the pid of the process that is running the
+/// executable at the given path.
+///
+/// This is based on the `/proc` file system, so on the
+/// systems without it `None` is returned.
+pub fn find_process_pid(exec_path: &str) -> Option<u32> {
+    let processes = std::fs::read_dir("/proc").ok()?;
+    for process in processes.flatten() {
+        let Ok(pid) = process.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(cmdline) = std::fs::read(process.path().join("cmdline")) else {
+            continue;
+        };
+        // the arguments are separated by a null byte, the executable
+        // can be the command itself or the argument of an interpreter.
+        let found = cmdline
+            .split(|byte| *byte == 0)
+            .take(2)
+            .any(|arg| is_same_path(&String::from_utf8_lossy(arg), exec_path));
+        if found {
+            return Some(pid);
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::create_dir_all;
@@ -1,21 +1,80 @@
 //! Model to encode and decode the core lightning plugin response!
+//!
+//! Core lightning sends the params as an array when the method is
+//! called with positional arguments, and as an object when it is
+//! called with named arguments (e.g. `lightning-cli -k`), so the
+//! order of the fields of a request is the order of its positional
+//! arguments.
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use serde_json::{json, Value};
+
+use clightningrpc_plugin::errors::PluginError;
+use coffee_lib::errors::{CoffeeError, ErrorKind};
+use coffee_lib::types::response::ErrorResponse;
+
+/// Code of the errors caused by the params of the request.
+const INVALID_PARAMS: i32 = -32602;
+/// Code of the errors returned by coffee, the category of
+/// the error is the `code` inside the data of the error.
+const COFFEE_ERROR: i32 = -1;
+
+/// Decode the params of a request, in the positional
+/// or in the named form.
+pub(crate) fn params<T: DeserializeOwned>(request: Value) -> Result<T, PluginError> {
+    // a method without arguments can be called without params.
+    let request = match request {
+        Value::Null => json!({}),
+        request => request,
+    };
+    serde_json::from_value(request).map_err(|err| invalid_params(&format!("{err}")))
+}
+
+pub(crate) fn invalid_params(msg: &str) -> PluginError {
+    let data = ErrorResponse::new(ErrorKind::InvalidRequest.code(), msg);
+    PluginError::new(INVALID_PARAMS, msg, Some(json!(data)))
+}
+
+pub(crate) fn coffee_error(err: &CoffeeError) -> PluginError {
+    let data = ErrorResponse::from(err);
+    PluginError::new(COFFEE_ERROR, err.message(), Some(json!(data)))
+}
 
 #[derive(Deserialize)]
 pub(crate) struct InstallReq {
     pub(crate) name: String,
 }
 
+#[derive(Deserialize)]
+pub(crate) struct PluginReq {
+    pub(crate) name: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UpgradeReq {
+    pub(crate) repository: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TipReq {
+    pub(crate) name: String,
+    pub(crate) amount_msat: u64,
+}
+
 #[derive(Deserialize)]
 pub(crate) struct RemoteReq {
     pub(crate) cmd: String,
-    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    #[serde(default)]
     pub(crate) url: Option<String>,
 }
 
 pub(crate) enum RemoteCmd {
     Add,
     Rm,
+    List,
+    Inspect,
 }
 
 impl TryFrom<String> for RemoteCmd {
@@ -25,17 +84,48 @@ impl TryFrom<String> for RemoteCmd {
         match value.as_str() {
             "add" => Ok(Self::Add),
             "rm" => Ok(Self::Rm),
+            "list" => Ok(Self::List),
+            "inspect" => Ok(Self::Inspect),
             _ => Err(format!("command {value} not supported")),
         }
     }
 }
 
 impl RemoteReq {
-    pub fn cmd(&self) -> Result<RemoteCmd, String> {
-        RemoteCmd::try_from(self.cmd.clone())
+    pub fn cmd(&self) -> Result<RemoteCmd, PluginError> {
+        RemoteCmd::try_from(self.cmd.clone()).map_err(|err| invalid_params(&err))
     }
 
-    pub fn url(&self) -> String {
-        self.url.clone().unwrap()
+    pub fn name(&self) -> Result<String, PluginError> {
+        self.name
+            .clone()
+            .ok_or_else(|| invalid_params(&format!("the command {} needs a name", self.cmd)))
     }
+
+    pub fn url(&self) -> Result<String, PluginError> {
+        self.url
+            .clone()
+            .ok_or_else(|| invalid_params(&format!("the command {} needs an url", self.cmd)))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NurseAction {
+    /// Report the defects without changing anything, so a
+    /// call without arguments can not repair coffee by mistake.
+    #[default]
+    Verify,
+    Plan,
+    /// Repair coffee, like `coffee nurse`.
+    Apply,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct NurseReq {
+    #[serde(default)]
+    pub(crate) action: NurseAction,
+    /// Apply only these repairs of the plan, all when empty.
+    #[serde(default)]
+    pub(crate) ids: Vec<String>,
 }
@@ -1,24 +1,27 @@
 //! Coffee plugin implementation to use
 //! Coffee as a core lightning plugin.
-use std::fmt::Display;
-
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::runtime::Runtime;
+use tokio::sync::broadcast::error::RecvError;
 
 use clightningrpc_common::json_utils;
 use clightningrpc_plugin::commands::RPCCommand;
-use clightningrpc_plugin::error;
 use clightningrpc_plugin::plugin::{debug, info};
 use clightningrpc_plugin::{errors::PluginError, plugin::Plugin};
 use clightningrpc_plugin_macros::{plugin, rpc_method};
 
 use coffee_core::coffee::CoffeeManager;
+use coffee_core::lock::{LockAccess, LockEndpoint};
 use coffee_lib::errors::CoffeeError;
 use coffee_lib::macros::error as coffee_err;
 use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::types::response::Caller;
 
-use super::model::{InstallReq, RemoteCmd, RemoteReq};
+use super::model::{
+    coffee_error, params, InstallReq, NurseAction, NurseReq, PluginReq, RemoteCmd, RemoteReq,
+    TipReq, UpgradeReq,
+};
 use super::state::PluginArgs;
 use crate::plugin::State;
 
@@ -32,6 +35,18 @@ pub fn build_plugin() -> Result<Plugin<State>, PluginError> {
             coffee_list,
             coffee_remote,
             coffee_generate_tip,
+            coffee_start,
+            coffee_stop,
+            coffee_restart,
+            coffee_remove,
+            coffee_upgrade,
+            coffee_show,
+            coffee_search,
+            coffee_enable,
+            coffee_disable,
+            coffee_nurse,
+            coffee_tip,
+            coffee_status,
         ],
         hooks: [],
     };
@@ -47,6 +62,9 @@ pub fn build_plugin() -> Result<Plugin<State>, PluginError> {
 fn on_init(plugin: &mut Plugin<State>) -> Value {
     let response = json_utils::init_payload();
     let cln_conf = plugin.configuration.clone().unwrap();
+    // the command line forwards the commands to the plugin
+    // through the core lightning rpc socket.
+    let rpc_path = format!("{}/{}", cln_conf.lightning_dir, cln_conf.rpc_file);
     let args = PluginArgs::from(cln_conf);
     info!("{:?}", args);
     plugin.state.set_args(args);
@@ -62,7 +80,19 @@ fn on_init(plugin: &mut Plugin<State>) -> Value {
             debug!("{err}");
             return Err(coffee_err!("{err}"));
         }
-        let coffee = coffee.unwrap();
+        let mut coffee = coffee.unwrap();
+        coffee.set_lock_endpoint(LockEndpoint::Plugin(rpc_path), LockAccess::default())?;
+        coffee.set_caller(Caller::Plugin);
+        // the progress of the operations is reported
+        // inside the core lightning log.
+        let mut events = coffee.progress.subscribe();
+        std::thread::spawn(move || loop {
+            match events.blocking_recv() {
+                Ok(event) => info!("{event}"),
+                Err(RecvError::Lagged(lost)) => info!("{lost} progress events lost"),
+                Err(RecvError::Closed) => break,
+            }
+        });
         plugin.state.set_coffee(coffee);
         plugin.state.link().await
     });
@@ -77,20 +107,20 @@ fn on_init(plugin: &mut Plugin<State>) -> Value {
     response
 }
 
-fn from<T: Display>(err: T) -> PluginError {
-    error!("{err}")
+fn from(err: CoffeeError) -> PluginError {
+    coffee_error(&err)
 }
 
 #[rpc_method(
     rpc_name = "coffee_install",
-    description = "install a plugin from one of the repository choosed"
+    description = "install a plugin from one of the repository choosed: name"
 )]
 fn coffee_install(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
     let coffee = plugin.state.coffee();
     let mut coffee = coffee.lock().unwrap();
     let rt = Runtime::new().unwrap();
 
-    let request: InstallReq = serde_json::from_value(request)?;
+    let request: InstallReq = params(request)?;
     rt.block_on(coffee.install(&request.name, false, true))
         .map_err(from)?;
     Ok(json!({}))
@@ -108,23 +138,87 @@ fn coffee_list(plugin: &mut Plugin<State>, _: Value) -> Result<Value, PluginErro
     Ok(serde_json::to_value(result)?)
 }
 
-#[rpc_method(rpc_name = "coffee_remote", description = "manage a remote")]
+#[rpc_method(
+    rpc_name = "coffee_remote",
+    description = "manage the repositories: cmd (add|rm|list|inspect) [name] [url]"
+)]
 fn coffee_remote(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
-    let request: RemoteReq = serde_json::from_value(request)?;
+    let request: RemoteReq = params(request)?;
     let runtime = Runtime::new().unwrap();
     let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
 
-    runtime
-        .block_on(async {
-            let mut coffee = coffee.lock().unwrap();
-            let cmd = request.cmd().unwrap();
-            match cmd {
-                RemoteCmd::Add => coffee.add_remote(&request.name, &request.url()).await,
-                RemoteCmd::Rm => coffee.rm_remote(&request.name).await,
-            }
-        })
+    match request.cmd()? {
+        RemoteCmd::Add => {
+            let (name, url) = (request.name()?, request.url()?);
+            runtime
+                .block_on(coffee.add_remote(&name, &url))
+                .map_err(from)?;
+            Ok(json!({}))
+        }
+        RemoteCmd::Rm => {
+            let name = request.name()?;
+            runtime.block_on(coffee.rm_remote(&name)).map_err(from)?;
+            Ok(json!({}))
+        }
+        RemoteCmd::List => {
+            let result = runtime.block_on(coffee.list_remotes()).map_err(from)?;
+            Ok(serde_json::to_value(result)?)
+        }
+        RemoteCmd::Inspect => {
+            let name = request.name()?;
+            let result = runtime
+                .block_on(coffee.get_plugins_in_remote(&name))
+                .map_err(from)?;
+            Ok(serde_json::to_value(result)?)
+        }
+    }
+}
+
+#[rpc_method(
+    rpc_name = "coffee_start",
+    description = "start a plugin installed with coffee: name"
+)]
+fn coffee_start(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.start(&request.name))
         .map_err(from)?;
-    Ok(json!({}))
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_stop",
+    description = "stop a plugin installed with coffee: name"
+)]
+fn coffee_stop(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime.block_on(coffee.stop(&request.name)).map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_restart",
+    description = "restart a plugin installed with coffee, e.g. after an upgrade: name"
+)]
+fn coffee_restart(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.restart(&request.name))
+        .map_err(from)?;
+    Ok(serde_json::to_value(result)?)
 }
 
 #[rpc_method(
@@ -151,3 +245,154 @@ fn coffee_generate_tip(plugin: &mut Plugin<State>, request: Value) -> Result<Val
         .map_err(from)?;
     Ok(serde_json::to_value(offer)?)
 }
+
+#[rpc_method(
+    rpc_name = "coffee_remove",
+    description = "remove a plugin installed with coffee: name"
+)]
+fn coffee_remove(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.remove(&request.name))
+        .map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_upgrade",
+    description = "upgrade the plugins of a repository: repository"
+)]
+fn coffee_upgrade(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: UpgradeReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.upgrade(&request.repository, false))
+        .map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_show",
+    description = "show the README file of a plugin: name"
+)]
+fn coffee_show(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime.block_on(coffee.show(&request.name)).map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_search",
+    description = "search a plugin inside the repositories: name"
+)]
+fn coffee_search(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.search(&request.name))
+        .map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_enable",
+    description = "enable a plugin installed with coffee: name"
+)]
+fn coffee_enable(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    runtime
+        .block_on(coffee.enable(&request.name))
+        .map_err(from)?;
+    Ok(json!({}))
+}
+
+#[rpc_method(
+    rpc_name = "coffee_disable",
+    description = "disable a plugin installed with coffee: name"
+)]
+fn coffee_disable(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: PluginReq = params(request)?;
+    runtime
+        .block_on(coffee.disable(&request.name))
+        .map_err(from)?;
+    Ok(json!({}))
+}
+
+#[rpc_method(
+    rpc_name = "coffee_nurse",
+    description = "verify and repair the coffee data: [action (verify|plan|apply), verify by default] [ids]"
+)]
+fn coffee_nurse(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: NurseReq = params(request)?;
+    let result = match request.action {
+        NurseAction::Verify => {
+            serde_json::to_value(runtime.block_on(coffee.nurse_verify()).map_err(from)?)
+        }
+        NurseAction::Plan => {
+            serde_json::to_value(runtime.block_on(coffee.nurse_plan()).map_err(from)?)
+        }
+        NurseAction::Apply if request.ids.is_empty() => {
+            serde_json::to_value(runtime.block_on(coffee.nurse()).map_err(from)?)
+        }
+        NurseAction::Apply => serde_json::to_value(
+            runtime
+                .block_on(coffee.nurse_apply(&request.ids))
+                .map_err(from)?,
+        ),
+    };
+    Ok(result?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_tip",
+    description = "tip the developer of a plugin: name amount_msat"
+)]
+fn coffee_tip(plugin: &mut Plugin<State>, request: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+
+    let request: TipReq = params(request)?;
+    let result = runtime
+        .block_on(coffee.tip(&request.name, request.amount_msat))
+        .map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
+
+#[rpc_method(
+    rpc_name = "coffee_status",
+    description = "show the state of the plugins installed with coffee"
+)]
+fn coffee_status(plugin: &mut Plugin<State>, _: Value) -> Result<Value, PluginError> {
+    let runtime = Runtime::new().unwrap();
+    let coffee = plugin.state.coffee();
+    let mut coffee = coffee.lock().unwrap();
+    let result = runtime.block_on(coffee.status()).map_err(from)?;
+    Ok(serde_json::to_value(result)?)
+}
@@ -5,7 +5,6 @@ use std::sync::Mutex;
 use clightningrpc_plugin::commands::types::CLNConf;
 use coffee_core::{coffee::CoffeeManager, CoffeeArgs};
 use coffee_lib::errors::CoffeeError;
-use coffee_lib::plugin_manager::PluginManager;
 
 #[derive(Clone)]
 pub struct State {
@@ -41,12 +40,16 @@ impl State {
         self.args.clone().unwrap()
     }
 
+    /// Link coffee with the node that runs the plugin, the
+    /// link is part of the startup so it is not stored inside
+    /// the history.
     pub async fn link(&self) -> Result<(), CoffeeError> {
-        self.coffee()
-            .lock()
-            .unwrap()
-            .link(&self.args.clone().unwrap().conf)
+        let coffee = self.coffee();
+        let mut coffee = coffee.lock().unwrap();
+        coffee
+            .link_with_cln(&self.args.clone().unwrap().conf)
             .await?;
+        coffee.flush().await?;
         Ok(())
     }
 }
@@ -3,6 +3,7 @@
 //! order to define the interface to manage the
 //! information to store on disk.
 pub mod file;
+pub mod migration;
 pub mod model;
 pub mod nosql_db;
 pub mod storage;
@@ -0,0 +1,263 @@
+//! Schema versioning of the data stored by coffee.
+//!
+//! Every key stored inside the database has a schema version
+//! stored alongside it, when coffee finds data with an old
+//! version it runs the chain of migrations to upgrade the data
+//! to the current version. Data stored without version is
+//! considered at version 0.
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use tokio::fs;
+
+use coffee_lib::error;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::check_dir_or_make_if_missing;
+
+use crate::nosql_db::NoSQlStorage;
+use crate::storage::StorageManager;
+
+/// Schema version of the data written by this version of coffee.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A migration that upgrades the data stored under a key
+/// from the previous schema version to `version()`.
+pub trait Migration: Send + Sync {
+    /// Schema version of the data returned by the migration.
+    fn version(&self) -> u32;
+
+    /// Short description of the change, used in the logs.
+    fn description(&self) -> &str;
+
+    /// Migrate the data stored under the `key`.
+    fn migrate(&self, key: &str, value: Value) -> Result<Value, CoffeeError>;
+}
+
+/// Chain of the migrations, ordered by version.
+pub struct MigrationChain {
+    migrations: Vec<Box<dyn Migration>>,
+}
+
+impl MigrationChain {
+    /// Create the chain with all the migrations of coffee.
+    ///
+    /// `conf_plugins` are the plugins listed inside the coffee
+    /// configuration of the network, see `conf_plugins`.
+    pub fn new(conf_plugins: HashSet<String>) -> Self {
+        Self {
+            migrations: vec![Box::new(PluginDefaultsMigration { conf_plugins })],
+        }
+    }
+
+    /// Register a migration inside the chain.
+    pub fn register(&mut self, migration: Box<dyn Migration>) {
+        self.migrations.push(migration);
+        self.migrations.sort_by_key(|migration| migration.version());
+    }
+
+    /// Upgrade the data stored under the `key` to the current schema
+    /// version, a copy of the old data is stored inside the `backup_dir`
+    /// before the upgrade.
+    ///
+    /// Data stored by a newer version of coffee is refused.
+    pub async fn run(
+        &self,
+        storage: &NoSQlStorage,
+        key: &str,
+        backup_dir: &str,
+    ) -> Result<(), CoffeeError> {
+        if !storage.contains(key) {
+            log::debug!("nothing to migrate for the key `{key}`");
+            return Ok(());
+        }
+        let version = storage.schema_version(key)?;
+        if version == SCHEMA_VERSION {
+            return Ok(());
+        }
+        check_version(key, version)?;
+        let value: Value = storage.load(key).await?;
+        backup(key, version, &value, backup_dir).await?;
+        let value = self.migrate(key, version, value)?;
+        storage.store(key, &value).await?;
+        Ok(())
+    }
+
+    /// Upgrade the `value` stored under the `key` from the schema
+    /// `version` to the current one.
+    pub fn migrate(&self, key: &str, version: u32, value: Value) -> Result<Value, CoffeeError> {
+        check_version(key, version)?;
+        let mut value = value;
+        for migration in self
+            .migrations
+            .iter()
+            .filter(|migration| migration.version() > version)
+        {
+            log::info!(
+                "migrating `{key}` to schema version {}: {}",
+                migration.version(),
+                migration.description()
+            );
+            value = migration.migrate(key, value)?;
+        }
+        Ok(value)
+    }
+}
+
+impl Default for MigrationChain {
+    fn default() -> Self {
+        Self::new(HashSet::new())
+    }
+}
+
+/// Paths of the plugins loaded by the coffee configuration
+/// of core lightning (the `plugin` and `important-plugin` lines).
+pub fn conf_plugins(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            matches!(key.trim(), "plugin" | "important-plugin").then(|| value.trim().to_owned())
+        })
+        .collect()
+}
+
+/// Refuse the data stored by a newer version of coffee.
+fn check_version(key: &str, version: u32) -> Result<(), CoffeeError> {
+    if version > SCHEMA_VERSION {
+        return Err(error!(
+            "the data of `{key}` has schema version {version}, but this coffee supports up to version {SCHEMA_VERSION}. Please upgrade coffee"
+        ));
+    }
+    Ok(())
+}
+
+/// Write a copy of the data before running the migrations.
+async fn backup(key: &str, version: u32, value: &Value, dir: &str) -> Result<(), CoffeeError> {
+    check_dir_or_make_if_missing(dir.to_owned()).await?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| error!("{err}"))?
+        .as_secs();
+    let path = format!("{dir}/{key}-v{version}-{timestamp}.json");
+    let content = serde_json::to_string_pretty(value).map_err(|err| error!("{err}"))?;
+    fs::write(&path, content).await?;
+    log::info!("backup of `{key}` stored in {path}");
+    Ok(())
+}
+
+/// Set the plugin fields that were added as optional to be
+/// backward compatible (e.g. `enabled`).
+///
+/// Only the plugins loaded by the coffee configuration are
+/// enabled, the dynamic installs are not inside it and they
+/// keep `enabled` unset.
+struct PluginDefaultsMigration {
+    conf_plugins: HashSet<String>,
+}
+
+impl PluginDefaultsMigration {
+    fn migrate_plugins(&self, plugins: Option<&mut Value>) {
+        let Some(Value::Array(plugins)) = plugins else {
+            return;
+        };
+        for plugin in plugins.iter_mut() {
+            if let Value::Object(plugin) = plugin {
+                let in_conf = plugin
+                    .get("exec_path")
+                    .and_then(Value::as_str)
+                    .is_some_and(|path| self.conf_plugins.contains(path));
+                let enabled = plugin.entry("enabled").or_insert(Value::Null);
+                if enabled.is_null() && in_conf {
+                    *enabled = Value::Bool(true);
+                }
+            }
+        }
+    }
+}
+
+impl Migration for PluginDefaultsMigration {
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn description(&self) -> &str {
+        "plugins inside the coffee configuration without the enabled field are enabled"
+    }
+
+    fn migrate(&self, key: &str, mut value: Value) -> Result<Value, CoffeeError> {
+        if key == "repositories" {
+            // map of repositories, every repository has a list of plugins
+            if let Value::Object(repos) = &mut value {
+                for repo in repos.values_mut() {
+                    self.migrate_plugins(repo.get_mut("plugins"));
+                }
+            }
+        } else {
+            // coffee storage info of the network
+            if let Some(config) = value.get_mut("config") {
+                self.migrate_plugins(config.get_mut("plugins"));
+            }
+            if let Some(Value::Object(repos)) = value.get_mut("repositories") {
+                for repo in repos.values_mut() {
+                    self.migrate_plugins(repo.get_mut("plugins"));
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{conf_plugins, Migration, PluginDefaultsMigration};
+
+    fn migration() -> PluginDefaultsMigration {
+        let conf = "plugin=/coffee/summary.py\nimportant-plugin=/coffee/btcli4j\nnetwork=regtest\n";
+        PluginDefaultsMigration {
+            conf_plugins: conf_plugins(conf),
+        }
+    }
+
+    #[test]
+    fn plugin_defaults_migration() {
+        let value = json!({
+            "config": { "plugins": [
+                { "name": "summary", "exec_path": "/coffee/summary.py" },
+                { "name": "btcli4j", "exec_path": "/coffee/btcli4j", "enabled": false },
+            ] },
+            "repositories": { "lightningd": { "plugins": [
+                { "name": "summary", "exec_path": "/coffee/summary.py", "enabled": null },
+            ] } },
+        });
+        let value = migration().migrate("regtest", value).unwrap();
+        assert_eq!(value["config"]["plugins"][0]["enabled"], json!(true));
+        assert_eq!(value["config"]["plugins"][1]["enabled"], json!(false));
+        assert_eq!(
+            value["repositories"]["lightningd"]["plugins"][0]["enabled"],
+            json!(true)
+        );
+
+        let value = json!({ "lightningd": { "plugins": [
+            { "name": "summary", "exec_path": "/coffee/summary.py" },
+        ] } });
+        let value = migration().migrate("repositories", value).unwrap();
+        assert_eq!(value["lightningd"]["plugins"][0]["enabled"], json!(true));
+    }
+
+    #[test]
+    fn plugin_defaults_migration_keeps_dynamic_plugins() {
+        // a dynamic install is not inside the coffee configuration.
+        let value = json!({
+            "config": { "plugins": [
+                { "name": "helpme", "exec_path": "/coffee/helpme.py", "enabled": null },
+                { "name": "clearnet", "exec_path": "/coffee/clearnet.py" },
+            ] },
+        });
+        let value = migration().migrate("regtest", value).unwrap();
+        assert_eq!(value["config"]["plugins"][0]["enabled"], json!(null));
+        assert_eq!(value["config"]["plugins"][1]["enabled"], json!(null));
+    }
+}
@@ -7,8 +7,15 @@ use nosql_sled::SledDB;
 use coffee_lib::error;
 use coffee_lib::errors::CoffeeError;
 
+use crate::migration::SCHEMA_VERSION;
 use crate::storage::StorageManager;
 
+/// Key where the schema version of the data stored
+/// under `key` is stored.
+fn schema_version_key(key: &str) -> String {
+    format!("{key}.schema_version")
+}
+
 /// No SQL database
 pub struct NoSQlStorage {
     inner: SledDB,
@@ -22,6 +29,27 @@ impl NoSQlStorage {
         let db = SledDB::try_from(config).map_err(|err| error!("{err}"))?;
         Ok(Self { inner: db })
     }
+
+    /// Return true if some data is stored under the key.
+    pub fn contains(&self, key: &str) -> bool {
+        self.inner.contains(key)
+    }
+
+    /// Schema version of the data stored under the key, the
+    /// data stored without version has version 0.
+    pub fn schema_version(&self, key: &str) -> Result<u32, CoffeeError> {
+        let version_key = schema_version_key(key);
+        if !self.inner.contains(&version_key) {
+            return Ok(0);
+        }
+        let version = self
+            .inner
+            .get(&version_key)
+            .map_err(|err| error!("{err}"))?;
+        version
+            .parse::<u32>()
+            .map_err(|err| error!("invalid schema version `{version}` for `{key}`: {err}"))
+    }
 }
 
 #[async_trait]
@@ -48,6 +76,10 @@ impl StorageManager for NoSQlStorage {
     {
         let value = serde_json::to_string(to_store).map_err(|err| error!("{err}"))?;
         self.inner.put(key, &value).map_err(|err| error!("{err}"))?;
+        // the data is always written with the schema of this version of coffee
+        self.inner
+            .put(&schema_version_key(key), &SCHEMA_VERSION.to_string())
+            .map_err(|err| error!("{err}"))?;
         Ok(())
     }
 }
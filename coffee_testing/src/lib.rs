@@ -13,6 +13,9 @@ use port_selector as port;
 use tempfile::TempDir;
 
 use coffee_core::coffee::CoffeeManager;
+use coffee_core::config::CoffeeConf;
+use coffee_core::token::TokenStore;
+use coffee_lib::types::response::CoffeeJob;
 
 static DEFAULT_TIMEOUT: u64 = 100;
 
@@ -212,4 +215,43 @@ impl CoffeeHTTPDTesting {
     pub fn url(&self) -> String {
         format!("http://127.0.0.1:{}", self.httpd_port)
     }
+
+    /// Return the admin token created by the httpd.
+    pub fn token(&self) -> anyhow::Result<String> {
+        let data_dir = self.root_path.path().to_str().unwrap().to_owned();
+        let root_path = CoffeeConf::root_path(Some(data_dir));
+        let tokens = TokenStore::load(&TokenStore::path(&root_path))?;
+        let token = tokens
+            .admin()
+            .ok_or(anyhow::anyhow!("admin token not found"))?;
+        Ok(token.token.clone())
+    }
+
+    /// Return a http client authenticated with the admin token.
+    pub fn client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let value = format!("Bearer {}", self.token()?);
+        headers.insert(reqwest::header::AUTHORIZATION, value.parse()?);
+        Ok(reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?)
+    }
+
+    /// Wait until the job returned by the `response` is finished.
+    pub async fn wait_job(&self, response: reqwest::Response) -> anyhow::Result<CoffeeJob> {
+        let mut job = response.error_for_status()?.json::<CoffeeJob>().await?;
+        let client = self.client()?;
+        while !job.state.is_finished() {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            job = client
+                .get(format!("{}/jobs/{}", self.url(), job.id))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<CoffeeJob>()
+                .await?;
+        }
+        log::info!("job {} {}: {:?}", job.id, job.state, job.result);
+        Ok(job)
+    }
 }
@@ -1,6 +1,7 @@
 use serde_json::json;
 
 use coffee_lib::types::request::*;
+use coffee_lib::types::response::{ErrorResponse, JobState};
 use coffee_testing::cln::Node;
 use coffee_testing::CoffeeHTTPDTesting;
 
@@ -20,7 +21,7 @@ pub async fn httpd_init_add_remote() {
     log::info!("lightning path: {lightning_dir}");
     let url = manager.url();
     log::info!("base url: {url}");
-    let client = reqwest::Client::new();
+    let client = manager.client().unwrap();
 
     // Define the request body to be sent to the /remote/add endpoint
     let remote_add_request = RemoteAdd {
@@ -36,10 +37,9 @@ pub async fn httpd_init_add_remote() {
         .await
         .unwrap();
 
-    // Check the response status code, log the body.
-    assert!(response.status().is_success());
-    let body = response.text().await.unwrap();
-    log::info!("/remote/add response: {}", body);
+    // Wait until the repository is cloned.
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
 
     // Define the request body to be sent to the /install endpoint
     let install_request = Install {
@@ -55,10 +55,9 @@ pub async fn httpd_init_add_remote() {
         .await
         .unwrap();
 
-    // Check the response status code, log the body.
-    // assert!(response.status().is_success());
-    let body = response.text().await.unwrap();
-    log::info!("/install response: {}", body);
+    // Wait until the plugin is installed.
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
 
     // Make sure the "summary" plugin is installed
     cln.rpc()
@@ -82,7 +81,7 @@ pub async fn httpd_add_remove_plugins() {
     log::info!("lightning path: {lightning_dir}");
     let url = manager.url();
     log::info!("base url: {url}");
-    let client = reqwest::Client::new();
+    let client = manager.client().unwrap();
 
     // Define the request body to be sent to the /remote/add endpoint
     let remote_add_request = RemoteAdd {
@@ -98,10 +97,9 @@ pub async fn httpd_add_remove_plugins() {
     assert!(response.is_ok(), "{:?}", response);
     let response = response.unwrap();
 
-    // Check the response status code, log the body.
-    assert!(response.status().is_success());
-    let body = response.text().await.unwrap();
-    log::info!("/remote/add response: {}", body);
+    // Wait until the repository is cloned.
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
 
     // Define the request body to be sent to the /install endpoint
     let install_request = Install {
@@ -117,11 +115,11 @@ pub async fn httpd_add_remove_plugins() {
     assert!(response.is_ok(), "{:?}", response);
     let response = response.unwrap();
 
-    // Check the response status code, log the body.
-    let body = response.text().await.unwrap();
-    log::info!("/install response: {}", body);
+    // Wait until the plugin is installed.
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
 
-    let body = reqwest::get(format!("{}/remote/list", url)).await;
+    let body = client.get(format!("{}/remote/list", url)).send().await;
     assert!(body.is_ok(), "{:?}", body);
     let body = body.unwrap().json::<serde_json::Value>().await;
     assert!(body.is_ok(), "{:?}", body);
@@ -141,7 +139,7 @@ pub async fn httpd_add_remove_plugins() {
         "lightningd remote repository not found in the response"
     );
 
-    let body = reqwest::get(format!("{}/list", url)).await;
+    let body = client.get(format!("{}/list", url)).send().await;
     assert!(body.is_ok(), "{:?}", body);
     let body = body.unwrap().json::<serde_json::Value>().await;
     assert!(body.is_ok(), "{:?}", body);
@@ -196,7 +194,7 @@ pub async fn httpd_add_remove_plugins() {
     let body = response.text().await.unwrap();
     log::info!("/remote/rm response: {}", body);
 
-    let body = reqwest::get(format!("{}/remote/list", url)).await;
+    let body = client.get(format!("{}/remote/list", url)).send().await;
     assert!(body.is_ok(), "{:?}", body);
     let body = body.unwrap().json::<serde_json::Value>().await;
     assert!(body.is_ok(), "{:?}", body);
@@ -233,7 +231,7 @@ pub async fn httpd_search_list_plugins() {
     log::info!("lightning path: {lightning_dir}");
     let url = manager.url();
     log::info!("base url: {url}");
-    let client = reqwest::Client::new();
+    let client = manager.client().unwrap();
 
     // Define the request body to be sent to the /remote/add endpoint
     let remote_add_request = RemoteAdd {
@@ -249,10 +247,9 @@ pub async fn httpd_search_list_plugins() {
     assert!(response.is_ok(), "{:?}", response);
     let response = response.unwrap();
 
-    // Check the response status code, log the body.
-    assert!(response.status().is_success());
-    let body = response.text().await.unwrap();
-    log::info!("/remote/add response: {}", body);
+    // Wait until the repository is cloned.
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
 
     // Define the request body to be sent to the /remote/list_plugins endpoint
     let remote_plugins_list_request = RemotePluginsList {
@@ -352,3 +349,436 @@ pub async fn httpd_search_list_plugins() {
     );
     cln.stop().await.unwrap();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_auth() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+
+    // Assert that the requests without credentials are refused
+    let response = reqwest::get(format!("{}/list", url)).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // Assert that the requests with an unknown token are refused
+    let response = reqwest::Client::new()
+        .get(format!("{}/list", url))
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // Assert that the API documentation is public
+    let response = reqwest::get(format!("{}/api/v1", url)).await.unwrap();
+    assert!(response.status().is_success(), "{:?}", response);
+
+    // Assert that the admin token is accepted
+    let client = manager.client().unwrap();
+    let response = client.get(format!("{}/list", url)).send().await.unwrap();
+    assert!(response.status().is_success(), "{:?}", response);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_jobs() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    let client = manager.client().unwrap();
+
+    // Install a plugin that is not inside any repository
+    let install_request = Install {
+        plugin: "summary".to_string(),
+        try_dynamic: false,
+    };
+    let response = client
+        .post(format!("{}/install", url))
+        .json(&install_request)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert that the request returns the job without waiting
+    assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Failed, "{:?}", job);
+    assert!(job.error.is_some(), "{:?}", job);
+
+    // Assert that the log of the job is available
+    let body = client
+        .get(format!("{}/jobs/{}/log", url, job.id))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    log::info!("/jobs/{}/log response: {}", job.id, body);
+    let log = body["log"].as_array();
+    assert!(log.is_some_and(|log| !log.is_empty()), "{:?}", body);
+
+    // Assert that the job is inside the list of jobs
+    let body = client
+        .get(format!("{}/jobs", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let jobs = body["jobs"].as_array().unwrap();
+    assert!(jobs.iter().any(|item| item["id"] == job.id), "{:?}", body);
+
+    // Assert that a finished job can not be cancelled
+    let response = client
+        .post(format!("{}/jobs/{}/cancel", url, job.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+
+    // Assert that an unknown job is not found
+    let response = client
+        .get(format!("{}/jobs/4242", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_events() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    let client = manager.client().unwrap();
+
+    // Follow the events of the repository
+    let mut events = client
+        .get(format!("{}/events?target=lightningd", url))
+        .send()
+        .await
+        .unwrap();
+    assert!(events.status().is_success(), "{:?}", events);
+    let content_type = events.headers()["content-type"].to_str().unwrap();
+    assert_eq!(content_type, "text/event-stream");
+
+    let remote_add_request = RemoteAdd {
+        repository_name: "lightningd".to_string(),
+        repository_url: "https://github.com/lightningd/plugins.git".to_string(),
+    };
+    let response = client
+        .post(format!("{}/remote/add", url))
+        .json(&remote_add_request)
+        .send()
+        .await
+        .unwrap();
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
+
+    // Assert that the clone and the index of the repository are streamed
+    let mut stream = String::new();
+    while !stream.contains(r#""phase":"indexing""#) {
+        let chunk = events.chunk().await.unwrap();
+        assert!(chunk.is_some(), "event stream closed: {stream}");
+        stream += &String::from_utf8_lossy(&chunk.unwrap());
+    }
+    log::info!("/events stream: {stream}");
+    assert!(stream.contains(r#""phase":"cloning""#), "{stream}");
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_errors() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    let client = manager.client().unwrap();
+
+    // Assert that an unknown plugin is not found, with a json error
+    let show_request = Show {
+        plugin: "unknown".to_string(),
+    };
+    let response = client
+        .get(format!("{}/show", url))
+        .json(&show_request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(body.code, "plugin_not_found", "{:?}", body);
+
+    let remove_request = Remove {
+        plugin: "unknown".to_string(),
+    };
+    let response = client
+        .post(format!("{}/remove", url))
+        .json(&remove_request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(body.code, "plugin_not_found", "{:?}", body);
+
+    // Assert that an unknown repository is not found
+    let remote_rm_request = RemoteRm {
+        repository_name: "unknown".to_string(),
+    };
+    let response = client
+        .post(format!("{}/remote/rm", url))
+        .json(&remote_rm_request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(body.code, "repository_not_found", "{:?}", body);
+
+    // Assert that an invalid body is refused
+    let response = client
+        .post(format!("{}/remove", url))
+        .json(&json!({ "name": "unknown" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(body.code, "invalid_request", "{:?}", body);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_v2() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    let client = manager.client().unwrap();
+
+    let remote_add_request = RemoteAdd {
+        repository_name: "lightningd".to_string(),
+        repository_url: "https://github.com/lightningd/plugins.git".to_string(),
+    };
+    let response = client
+        .post(format!("{}/remote/add", url))
+        .json(&remote_add_request)
+        .send()
+        .await
+        .unwrap();
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
+
+    // Assert that the repository is returned by its name
+    let body = client
+        .get(format!("{}/v2/remotes/lightningd", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(body["local_name"], "lightningd", "{:?}", body);
+
+    // Assert that the plugins of the repository are filtered and paginated
+    let body = client
+        .get(format!(
+            "{}/v2/remotes/lightningd/plugins?q=summary&page_size=1",
+            url
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    log::info!("/v2/remotes/lightningd/plugins response: {}", body);
+    let items = body["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1, "{:?}", body);
+    assert_eq!(items[0]["name"], "summary", "{:?}", body);
+    assert_eq!(items[0]["repository"], "lightningd", "{:?}", body);
+
+    // Assert that the search looks inside all the repositories
+    let body = client
+        .get(format!("{}/v2/search?q=SUMM", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let items = body["items"].as_array().unwrap();
+    assert!(
+        items.iter().any(|item| item["name"] == "summary"),
+        "{:?}",
+        body
+    );
+
+    // Assert that the search requires the query
+    let response = client
+        .get(format!("{}/v2/search", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Assert that no plugin is installed
+    let body = client
+        .get(format!("{}/v2/plugins", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(body["total"], 0, "{:?}", body);
+    let response = client
+        .get(format!("{}/v2/plugins/summary", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_metrics() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    let client = manager.client().unwrap();
+
+    let remote_add_request = RemoteAdd {
+        repository_name: "lightningd".to_string(),
+        repository_url: "https://github.com/lightningd/plugins.git".to_string(),
+    };
+    let response = client
+        .post(format!("{}/remote/add", url))
+        .json(&remote_add_request)
+        .send()
+        .await
+        .unwrap();
+    let job = manager.wait_job(response).await.unwrap();
+    assert_eq!(job.state, JobState::Succeeded, "{:?}", job.error);
+
+    // Assert that the repositories, the operations and the
+    // requests are exposed in the prometheus format
+    let response = client.get(format!("{}/metrics", url)).send().await.unwrap();
+    assert!(response.status().is_success(), "{:?}", response);
+    let body = response.text().await.unwrap();
+    log::info!("/metrics response: {}", body);
+    assert!(body.contains("coffee_remotes 1"), "{body}");
+    assert!(
+        body.contains(
+            r#"coffee_operation_duration_seconds_count{operation="remote_add",state="succeeded"} 1"#
+        ),
+        "{body}"
+    );
+    assert!(
+        body.contains(r#"coffee_http_request_duration_seconds_count{method="POST",path="/remote/add",status="202"} 1"#),
+        "{body}"
+    );
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn httpd_dashboard() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    let manager = CoffeeHTTPDTesting::tmp(lightning_dir.to_string())
+        .await
+        .unwrap();
+    let url = manager.url();
+    // the static files are available without the token
+    let client = reqwest::Client::new();
+
+    // Assert that the dashboard and the swagger files are
+    // served by the daemon, without loading anything from outside
+    for (path, content_type) in [
+        ("/", "text/html; charset=utf-8"),
+        ("/dashboard/app.js", "text/javascript; charset=utf-8"),
+        ("/dashboard/style.css", "text/css; charset=utf-8"),
+        ("/swagger/", "text/html; charset=utf-8"),
+        (
+            "/swagger/swagger-ui-bundle.js",
+            "text/javascript; charset=utf-8",
+        ),
+    ] {
+        let response = client.get(format!("{url}{path}")).send().await.unwrap();
+        assert!(response.status().is_success(), "{path}: {:?}", response);
+        assert_eq!(response.headers()["content-type"], content_type, "{path}");
+        let body = response.text().await.unwrap();
+        assert!(!body.contains("unpkg.com"), "{path}");
+    }
+
+    let response = client
+        .get(format!("{url}/dashboard/unknown.js"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Assert that the api still requires the token
+    let response = client
+        .get(format!("{url}/v2/plugins"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    cln.stop().await.unwrap();
+}
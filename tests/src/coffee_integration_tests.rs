@@ -5,7 +5,10 @@ use tokio::fs;
 use serde_json::json;
 
 use coffee_lib::plugin_manager::PluginManager;
-use coffee_lib::types::response::{Defect, NurseStatus};
+use coffee_lib::types::request::HistoryFilter;
+use coffee_lib::types::response::{
+    Caller, Defect, HistoryResult, NurseStatus, PluginState, RiskLevel,
+};
 use coffee_testing::cln::Node;
 use coffee_testing::prelude::tempfile;
 use coffee_testing::{CoffeeTesting, CoffeeTestingArgs};
@@ -696,3 +699,368 @@ pub async fn test_nurse_repository_missing_on_disk() {
 
     cln.stop().await.unwrap();
 }
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_plugin_status() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    log::info!("lightning path: {lightning_dir}");
+
+    manager.coffee().link(&lightning_dir).await.unwrap();
+    manager
+        .coffee()
+        .add_remote("lightningd", "https://github.com/lightningd/plugins.git")
+        .await
+        .unwrap();
+
+    // Install the summary plugin dynamically, so it is running
+    manager
+        .coffee()
+        .install("summary", false, true)
+        .await
+        .unwrap();
+
+    let result = manager.coffee().status().await;
+    assert!(result.is_ok(), "{:?}", result);
+    let plugins = result.unwrap().plugins;
+    log::debug!("plugins status: {:?}", plugins);
+    let summary = plugins
+        .iter()
+        .find(|plugin| plugin.name == "summary")
+        .expect("Plugin 'summary' not found");
+    assert_eq!(summary.state, PluginState::Running, "{:?}", summary);
+    assert_eq!(summary.dynamic, Some(true), "{:?}", summary);
+    // the core lightning builtin plugins are not managed by coffee
+    assert!(
+        plugins
+            .iter()
+            .any(|plugin| plugin.state == PluginState::Unmanaged),
+        "{:?}",
+        plugins
+    );
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_nurse_plugin_conf_drift() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    manager.coffee().link(&lightning_dir).await.unwrap();
+    manager
+        .coffee()
+        .add_remote("lightningd", "https://github.com/lightningd/plugins.git")
+        .await
+        .unwrap();
+    manager
+        .coffee()
+        .install("summary", false, false)
+        .await
+        .unwrap();
+
+    // Remove the plugin from the coffee configuration (we simulate
+    // a configuration edited by hand)
+    let exec_path = manager.coffee().config.plugins[0].exec_path.clone();
+    manager
+        .coffee()
+        .coffee_cln_config
+        .rm_conf("plugin", Some(&exec_path))
+        .unwrap();
+    manager.coffee().update_conf().await.unwrap();
+
+    // Assert that nurse --verify finds the plugin missing in the configuration
+    let result = manager.coffee().nurse_verify().await;
+    assert!(result.is_ok(), "{:?}", result);
+    let defects = result.unwrap().defects;
+    assert_eq!(
+        defects,
+        vec![Defect::PluginConfEntryMissing(vec!["summary".to_string()])],
+        "{:?}",
+        defects
+    );
+
+    // Assert that nurse restores the plugin in the configuration
+    let result = manager.coffee().nurse().await;
+    assert!(result.is_ok(), "{:?}", result);
+    let result = result.unwrap();
+    assert_eq!(
+        result.status,
+        vec![NurseStatus::PluginConfEntryRestored(vec![
+            "summary".to_string()
+        ])],
+        "{:?}",
+        result
+    );
+    let result = manager.coffee().nurse_verify().await.unwrap();
+    assert!(result.is_sane(), "{:?}", result);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_nurse_plan_apply_by_id() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    manager.coffee().link(&lightning_dir).await.unwrap();
+
+    // Add two plugins that are not installed to the coffee configuration
+    for path in ["/tmp/not-installed.py", "/tmp/not-installed-too.py"] {
+        manager
+            .coffee()
+            .coffee_cln_config
+            .add_conf("plugin", path)
+            .unwrap();
+    }
+    manager.coffee().update_conf().await.unwrap();
+
+    // Assert that the plan reports a repair for each plugin without patching them
+    let plan = manager.coffee().nurse_plan().await.unwrap();
+    assert_eq!(plan.repairs.len(), 2, "{:?}", plan);
+    let ids: Vec<&str> = plan
+        .repairs
+        .iter()
+        .map(|repair| repair.id.as_str())
+        .collect();
+    assert!(
+        ids.contains(&"plugin-conf-entry-unknown:/tmp/not-installed.py"),
+        "{:?}",
+        plan
+    );
+    assert_eq!(plan.repairs[0].risk, RiskLevel::Low);
+
+    // Assert that an unknown id is refused
+    let result = manager
+        .coffee()
+        .nurse_apply(&["cln-path-missing".to_string()])
+        .await;
+    assert!(result.is_err(), "{:?}", result);
+
+    // Assert that only the selected repair is applied
+    let result = manager
+        .coffee()
+        .nurse_apply(&["plugin-conf-entry-unknown:/tmp/not-installed.py".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(
+        result.status,
+        vec![NurseStatus::PluginConfEntryRemoved(vec![
+            "/tmp/not-installed.py".to_string()
+        ])],
+        "{:?}",
+        result
+    );
+    let plan = manager.coffee().nurse_plan().await.unwrap();
+    assert_eq!(plan.repairs.len(), 1, "{:?}", plan);
+    assert_eq!(
+        plan.repairs[0].id,
+        "plugin-conf-entry-unknown:/tmp/not-installed-too.py"
+    );
+
+    // Assert that the category selects the repairs of all its elements
+    manager
+        .coffee()
+        .nurse_apply(&["plugin-conf-entry-unknown".to_string()])
+        .await
+        .unwrap();
+    let plan = manager.coffee().nurse_plan().await.unwrap();
+    assert!(plan.is_sane(), "{:?}", plan);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_nurse_relink_cln_path_missing() {
+    init();
+
+    let mut cln = Node::tmp("regtest").await.unwrap();
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+
+    let lightning_dir = cln.rpc().getinfo().unwrap().ligthning_dir;
+    let lightning_dir = lightning_dir.strip_suffix("/regtest").unwrap();
+    manager.coffee().link(&lightning_dir).await.unwrap();
+
+    // Link coffee to the network directory, like a wrong `coffee link`
+    let network_dir = format!("{lightning_dir}/regtest");
+    manager.coffee().config.cln_root = Some(network_dir.clone());
+    manager.coffee().config.cln_config_path = Some(format!("{network_dir}/regtest/config"));
+
+    let plan = manager.coffee().nurse_plan().await.unwrap();
+    assert_eq!(plan.repairs.len(), 1, "{:?}", plan);
+    assert!(
+        plan.repairs[0].id.starts_with("cln-path-missing:"),
+        "{:?}",
+        plan
+    );
+
+    // Assert that coffee is linked again to the right directory
+    let result = manager
+        .coffee()
+        .nurse_apply(&["cln-path-missing".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(
+        result.status,
+        vec![NurseStatus::ClnConfigRelinked(vec![format!(
+            "{network_dir}/config"
+        )])],
+        "{:?}",
+        result
+    );
+    assert_eq!(
+        manager.coffee().config.cln_root,
+        Some(lightning_dir.to_string())
+    );
+    let plan = manager.coffee().nurse_plan().await.unwrap();
+    assert!(plan.is_sane(), "{:?}", plan);
+
+    cln.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_backup_and_restore() {
+    init();
+
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+    manager
+        .coffee()
+        .add_remote("folgore", "https://github.com/coffee-tools/folgore.git")
+        .await
+        .unwrap();
+
+    let backup_dir = tempfile::tempdir().unwrap();
+    let backup_path = format!("{}/coffee.tar.gz", backup_dir.path().to_str().unwrap());
+    let backup = manager.coffee().backup(&backup_path, false).await.unwrap();
+    assert_eq!(backup.path, backup_path);
+    assert!(Path::new(&backup_path).exists());
+
+    // Restore the backup inside a new coffee home (we simulate
+    // a new host)
+    let mut new_manager = CoffeeTesting::tmp().await.unwrap();
+    let result = new_manager.coffee().restore(&backup_path).await;
+    assert!(result.is_ok(), "{:?}", result);
+    let result = result.unwrap();
+    assert_eq!(
+        result.nurse.status,
+        vec![NurseStatus::RepositoryLocallyRestored(vec![
+            "folgore".to_string()
+        ])],
+        "{:?}",
+        result
+    );
+
+    // Assert that the repository is cloned inside the new coffee home
+    let root_path = new_manager.root_path();
+    let root_path = root_path.path().to_str().unwrap();
+    let folgore_path = format!("{root_path}/.coffee/repositories/folgore/README.md");
+    assert!(Path::new(&folgore_path).exists(), "{folgore_path}");
+    let remotes = new_manager.coffee().list_remotes().await.unwrap();
+    let remotes = remotes.remotes.unwrap();
+    assert_eq!(remotes.len(), 1, "{:?}", remotes);
+    assert_eq!(remotes[0].local_name, "folgore");
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_data_dir_lock() {
+    init();
+
+    let dir = Arc::new(tempfile::tempdir().unwrap());
+    let args = CoffeeTestingArgs {
+        conf: None,
+        data_dir: dir.path().to_str().unwrap().to_owned(),
+        network: "regtest".to_string(),
+    };
+    let manager = CoffeeTesting::tmp_with_args(&args, dir.clone())
+        .await
+        .unwrap();
+
+    // Assert that a second coffee can not open the same data directory
+    let result = CoffeeTesting::tmp_with_args(&args, dir.clone()).await;
+    let err = result.err().unwrap().to_string();
+    assert!(
+        err.contains(&format!("in use by PID {}", std::process::id())),
+        "{err}"
+    );
+
+    // Assert that the data directory can be opened again
+    // when the first coffee is dropped
+    drop(manager);
+    let result = CoffeeTesting::tmp_with_args(&args, dir.clone()).await;
+    assert!(result.is_ok(), "{:?}", result.err());
+}
+
+#[tokio::test]
+#[ntest::timeout(560000)]
+pub async fn test_history() {
+    init();
+
+    let mut manager = CoffeeTesting::tmp().await.unwrap();
+    manager
+        .coffee()
+        .add_remote("folgore", "https://github.com/coffee-tools/folgore.git")
+        .await
+        .unwrap();
+    let result = manager.coffee().rm_remote("lightningd").await;
+    assert!(result.is_err(), "{:?}", result);
+
+    // Assert that both the operations are stored, the newest first
+    let history = manager
+        .coffee()
+        .history(&HistoryFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(history.total, 2, "{:?}", history);
+    let failed = &history.entries[0];
+    assert_eq!(failed.operation, "rm_remote");
+    assert_eq!(failed.target, Some("lightningd".to_string()));
+    assert!(!failed.result.is_success(), "{:?}", failed);
+    let added = &history.entries[1];
+    assert_eq!(added.operation, "add_remote");
+    assert_eq!(added.result, HistoryResult::Success);
+    assert_eq!(added.caller, Caller::Cli);
+    assert_eq!(added.before, None);
+    assert!(added.after.is_some(), "{:?}", added);
+    assert_eq!(
+        added.arguments.get("url").map(|url| url.as_str()),
+        Some("https://github.com/coffee-tools/folgore.git")
+    );
+
+    // Assert that the history can be filtered and paginated
+    let filter = HistoryFilter {
+        failed: true,
+        ..HistoryFilter::default()
+    };
+    let history = manager.coffee().history(&filter).await.unwrap();
+    assert_eq!(history.total, 1, "{:?}", history);
+    assert_eq!(history.entries[0].operation, "rm_remote");
+
+    let filter = HistoryFilter {
+        offset: 1,
+        limit: Some(1),
+        ..HistoryFilter::default()
+    };
+    let history = manager.coffee().history(&filter).await.unwrap();
+    assert_eq!(history.total, 2, "{:?}", history);
+    assert_eq!(history.entries.len(), 1);
+    assert_eq!(history.entries[0].operation, "add_remote");
+}
@@ -87,3 +87,68 @@ pub async fn init_cln_with_coffee_install_plugin_test() {
 
     cln.stop().await.unwrap();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[ntest::timeout(560000)]
+pub async fn init_cln_with_coffee_rpc_params_test() {
+    init();
+
+    let cargo_target = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let path = std::path::Path::new(cargo_target).to_str().unwrap();
+    let plugin_path = format!("{path}/target/debug/coffee_plugin");
+    log::info!("plugin path {plugin_path}");
+
+    let mut cln = Node::with_params(&format!("--plugin={plugin_path}"), "regtest")
+        .await
+        .unwrap();
+
+    // positional params
+    let result: serde_json::Value = cln
+        .rpc()
+        .call(
+            "coffee_remote",
+            serde_json::json!([
+                "add",
+                "folgore",
+                "https://github.com/coffee-tools/folgore.git"
+            ]),
+        )
+        .unwrap();
+    log::info!("cln response {result}");
+
+    // named params
+    let result: serde_json::Value = cln
+        .rpc()
+        .call("coffee_remote", serde_json::json!({ "cmd": "list" }))
+        .unwrap();
+    log::info!("cln response {result}");
+    let remotes = result["remotes"].as_array().unwrap();
+    assert!(remotes
+        .iter()
+        .any(|remote| remote["local_name"] == "folgore"));
+
+    let result: serde_json::Value = cln
+        .rpc()
+        .call("coffee_remote", serde_json::json!(["inspect", "folgore"]))
+        .unwrap();
+    log::info!("cln response {result}");
+    assert!(!result["plugins"].as_array().unwrap().is_empty());
+
+    let result: serde_json::Value = cln
+        .rpc()
+        .call("coffee_status", serde_json::json!({}))
+        .unwrap();
+    log::info!("cln response {result}");
+
+    let result = cln
+        .rpc()
+        .call::<serde_json::Value, serde_json::Value>("coffee_remove", serde_json::json!(["foo"]));
+    assert!(result.is_err(), "{:?}", result);
+
+    let result = cln
+        .rpc()
+        .call::<serde_json::Value, serde_json::Value>("coffee_show", serde_json::json!([]));
+    assert!(result.is_err(), "{:?}", result);
+
+    cln.stop().await.unwrap();
+}